@@ -27,6 +27,7 @@
           packages = with pkgs; [
             grpcurl
             just
+            openssl
             protobuf 
             rust
 
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use tonic::{
+    Request, Status,
+    metadata::{AsciiMetadataValue, MetadataMap, errors::InvalidMetadataValue},
+    service::Interceptor,
+};
+
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// API key presented by the caller, stored in the request extensions by [`ServerAuth`]
+#[derive(Clone)]
+pub struct ApiKey(pub String);
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiKey(***)")
+    }
+}
+
+/// Build the `authorization` header value for `key`
+pub fn bearer_value(key: &str) -> Result<AsciiMetadataValue, InvalidMetadataValue> {
+    format!("{}{}", BEARER_PREFIX, key).parse()
+}
+
+/// Server interceptor extracting the bearer API key of every request.
+///
+/// When `required` is set, requests without a well formed key are rejected before
+/// they reach the service.
+#[derive(Clone, Copy, Debug)]
+pub struct ServerAuth {
+    required: bool,
+}
+
+impl ServerAuth {
+    pub fn new(required: bool) -> Self {
+        Self { required }
+    }
+}
+
+impl Interceptor for ServerAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        match bearer_key(request.metadata()) {
+            Ok(Some(key)) => {
+                request.extensions_mut().insert(ApiKey(key));
+                Ok(request)
+            }
+            Ok(None) if !self.required => Ok(request),
+            Ok(None) => Err(Status::unauthenticated("missing API key")),
+            Err(status) => Err(status),
+        }
+    }
+}
+
+fn bearer_key(metadata: &MetadataMap) -> Result<Option<String>, Status> {
+    let Some(value) = metadata.get(AUTHORIZATION_HEADER) else {
+        return Ok(None);
+    };
+
+    let key = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.strip_prefix(BEARER_PREFIX))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| Status::unauthenticated("malformed authorization header"))?;
+
+    Ok(Some(key.to_string()))
+}
+
+/// Client interceptor attaching an API key to every outgoing request
+#[derive(Clone, Default)]
+pub struct ClientAuth {
+    header: Option<AsciiMetadataValue>,
+}
+
+impl ClientAuth {
+    pub fn new(key: Option<&str>) -> Result<Self, InvalidMetadataValue> {
+        Ok(Self {
+            header: key.map(bearer_value).transpose()?,
+        })
+    }
+}
+
+impl Interceptor for ClientAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(header) = &self.header {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION_HEADER, header.clone());
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_with_header(value: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert(AUTHORIZATION_HEADER, value.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn test_server_auth_extracts_key() {
+        let request = ServerAuth::new(true)
+            .call(request_with_header("Bearer nf_123"))
+            .unwrap();
+        assert_eq!(request.extensions().get::<ApiKey>().unwrap().0, "nf_123");
+    }
+
+    #[test]
+    fn test_server_auth_rejects_missing_key_when_required() {
+        let status = ServerAuth::new(true).call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_server_auth_allows_missing_key_when_optional() {
+        let request = ServerAuth::new(false).call(Request::new(())).unwrap();
+        assert!(request.extensions().get::<ApiKey>().is_none());
+    }
+
+    #[test]
+    fn test_server_auth_rejects_malformed_header() {
+        let status = ServerAuth::new(false)
+            .call(request_with_header("Basic abc"))
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_client_auth_sets_header() {
+        let request = ClientAuth::new(Some("nf_123"))
+            .unwrap()
+            .call(Request::new(()))
+            .unwrap();
+        assert_eq!(
+            request.metadata().get(AUTHORIZATION_HEADER).unwrap(),
+            "Bearer nf_123"
+        );
+    }
+
+    #[test]
+    fn test_client_auth_without_key() {
+        let request = ClientAuth::default().call(Request::new(())).unwrap();
+        assert!(request.metadata().get(AUTHORIZATION_HEADER).is_none());
+    }
+}
@@ -0,0 +1,30 @@
+//! API key authentication shared by the NoctiForge services and CLI.
+
+use sha2::{Digest, Sha256};
+
+mod interceptor;
+mod remote;
+mod scope;
+
+pub use interceptor::{AUTHORIZATION_HEADER, ApiKey, ClientAuth, ServerAuth, bearer_value};
+pub use remote::RemoteAuthorizer;
+pub use scope::{
+    ALL_NAMESPACES, Scope, is_allowed, namespace_matches, resource_matches, resources_within,
+};
+
+/// Hash under which an API key is stored and cached, so the key itself never is
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_key_is_stable() {
+        assert_eq!(hash_key("nf_abc"), hash_key("nf_abc"));
+        assert_ne!(hash_key("nf_abc"), hash_key("nf_abd"));
+        assert_eq!(hash_key("nf_abc").len(), 64);
+    }
+}
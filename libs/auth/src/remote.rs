@@ -0,0 +1,97 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use proto::api::controlplane::{
+    AuthorizeRequest, control_plane_service_client::ControlPlaneServiceClient,
+};
+use tokio::{sync::RwLock, time::Instant};
+use tonic::{Code, Request, Status, transport::Channel};
+use tracing::{debug, warn};
+
+use crate::{AUTHORIZATION_HEADER, ApiKey, Scope, bearer_value, hash_key};
+
+/// Expired grants are only pruned once the cache grows past this size
+const PRUNE_THRESHOLD: usize = 1024;
+
+type GrantKey = (String, String, Scope, String);
+
+/// Authorizes requests by asking the control plane about the caller's API key.
+///
+/// Successful answers are remembered for `ttl` so a busy function does not cost a
+/// control plane round trip per call.
+#[derive(Clone)]
+pub struct RemoteAuthorizer {
+    client: ControlPlaneServiceClient<Channel>,
+    ttl: Duration,
+    grants: Arc<RwLock<HashMap<GrantKey, Instant>>>,
+}
+
+impl RemoteAuthorizer {
+    pub fn new(channel: Channel, ttl: Duration) -> Self {
+        Self {
+            client: ControlPlaneServiceClient::new(channel),
+            ttl,
+            grants: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Check that the caller may use `scope` on `resource` in `namespace`, where an
+    /// empty namespace or resource means the operation is not tied to one
+    pub async fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        namespace: &str,
+        scope: Scope,
+        resource: &str,
+    ) -> Result<(), Status> {
+        let key = request
+            .extensions()
+            .get::<ApiKey>()
+            .ok_or_else(|| Status::unauthenticated("missing API key"))?;
+
+        let grant = (
+            hash_key(&key.0),
+            namespace.to_string(),
+            scope,
+            resource.to_string(),
+        );
+        if let Some(granted_at) = self.grants.read().await.get(&grant)
+            && granted_at.elapsed() < self.ttl
+        {
+            return Ok(());
+        }
+
+        let mut authorize = Request::new(AuthorizeRequest {
+            scope: scope.to_string(),
+            resource: resource.to_string(),
+            namespace: namespace.to_string(),
+        });
+        authorize.metadata_mut().insert(
+            AUTHORIZATION_HEADER,
+            bearer_value(&key.0).map_err(|_| Status::unauthenticated("malformed API key"))?,
+        );
+
+        let response = self
+            .client
+            .clone()
+            .authorize(authorize)
+            .await
+            .map_err(|status| match status.code() {
+                Code::Unauthenticated | Code::PermissionDenied => status,
+                _ => {
+                    warn!(error = %status, "Failed to reach control plane for authorization");
+                    Status::unavailable(format!("authorization failed: {}", status.message()))
+                }
+            })?
+            .into_inner();
+
+        debug!(key_name = %response.key_name, namespace, %scope, resource, "Request authorized");
+
+        let mut grants = self.grants.write().await;
+        if grants.len() >= PRUNE_THRESHOLD {
+            grants.retain(|_, granted_at| granted_at.elapsed() < self.ttl);
+        }
+        grants.insert(grant, Instant::now());
+
+        Ok(())
+    }
+}
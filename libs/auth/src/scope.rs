@@ -0,0 +1,203 @@
+use std::{fmt, str::FromStr};
+
+/// What an API key is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Upload packages to the registry
+    Push,
+    /// Point a function name at a digest
+    Deploy,
+    /// Execute a function
+    Invoke,
+    /// Manage API keys, implies every other scope
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Push => "push",
+            Scope::Deploy => "deploy",
+            Scope::Invoke => "invoke",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "push" => Ok(Scope::Push),
+            "deploy" => Ok(Scope::Deploy),
+            "invoke" => Ok(Scope::Invoke),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!(
+                "unknown scope '{}', expected push, deploy, invoke or admin",
+                other
+            )),
+        }
+    }
+}
+
+/// Check `resource` against a list of patterns.
+///
+/// `*` matches everything, a pattern ending in `*` matches by prefix and anything
+/// else has to match exactly.
+pub fn resource_matches(patterns: &[String], resource: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => resource.starts_with(prefix),
+            None => pattern == resource,
+        })
+}
+
+/// Check that the pattern `requested` only covers names some pattern in `granted`
+/// covers, so a key cannot hand out more than it holds.
+pub fn resources_within(granted: &[String], requested: &str) -> bool {
+    // A pattern is itself a name its prefix matches, `billing-eu-*` starts with
+    // `billing-`, while an exact name never covers a prefix.
+    resource_matches(granted, requested)
+}
+
+/// Namespace of a key that is valid in every namespace
+pub const ALL_NAMESPACES: &str = "*";
+
+/// Check whether a key created in `key_namespace` may act on `resource` in
+/// `namespace`.
+///
+/// An empty `namespace` means the operation is not tied to one, so any key matches,
+/// but only when no `resource` is named: functions always live in a namespace.
+/// Asking for [`ALL_NAMESPACES`] only matches keys that are themselves global.
+pub fn namespace_matches(key_namespace: &str, namespace: &str, resource: &str) -> bool {
+    key_namespace == ALL_NAMESPACES
+        || (namespace.is_empty() && resource.is_empty())
+        || key_namespace == namespace
+}
+
+/// Decide whether a key holding `scopes` on `resources` may use `scope` on `resource`.
+///
+/// An empty `resource` means the operation is not tied to a function, so only the
+/// scope is checked.
+pub fn is_allowed(scopes: &[Scope], resources: &[String], scope: Scope, resource: &str) -> bool {
+    let has_scope = scopes.contains(&scope) || scopes.contains(&Scope::Admin);
+    has_scope && (resource.is_empty() || resource_matches(resources, resource))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_scope_round_trip() {
+        for scope in [Scope::Push, Scope::Deploy, Scope::Invoke, Scope::Admin] {
+            assert_eq!(scope.as_str().parse::<Scope>().unwrap(), scope);
+        }
+        assert!("owner".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn test_resource_matches_exact() {
+        assert!(resource_matches(&patterns(&["echo"]), "echo"));
+        assert!(!resource_matches(&patterns(&["echo"]), "echo2"));
+    }
+
+    #[test]
+    fn test_resource_matches_prefix() {
+        let p = patterns(&["team-a-*"]);
+        assert!(resource_matches(&p, "team-a-echo"));
+        assert!(!resource_matches(&p, "team-b-echo"));
+    }
+
+    #[test]
+    fn test_resource_matches_wildcard() {
+        assert!(resource_matches(&patterns(&["*"]), "anything"));
+        assert!(!resource_matches(&[], "anything"));
+    }
+
+    #[test]
+    fn test_is_allowed_requires_scope() {
+        let resources = patterns(&["*"]);
+        assert!(is_allowed(
+            &[Scope::Invoke],
+            &resources,
+            Scope::Invoke,
+            "echo"
+        ));
+        assert!(!is_allowed(
+            &[Scope::Invoke],
+            &resources,
+            Scope::Deploy,
+            "echo"
+        ));
+    }
+
+    #[test]
+    fn test_is_allowed_admin_implies_all_scopes() {
+        let resources = patterns(&["*"]);
+        assert!(is_allowed(&[Scope::Admin], &resources, Scope::Push, ""));
+        assert!(is_allowed(
+            &[Scope::Admin],
+            &resources,
+            Scope::Deploy,
+            "echo"
+        ));
+    }
+
+    #[test]
+    fn test_resources_within() {
+        assert!(resources_within(&patterns(&["*"]), "*"));
+        assert!(resources_within(&patterns(&["billing-*"]), "billing-eu-*"));
+        assert!(resources_within(&patterns(&["billing-*"]), "billing-echo"));
+        assert!(!resources_within(&patterns(&["billing-*"]), "*"));
+        assert!(!resources_within(&patterns(&["billing-*"]), "bill*"));
+        assert!(!resources_within(&patterns(&["echo"]), "ech*"));
+        assert!(resources_within(&patterns(&["echo"]), "echo"));
+    }
+
+    #[test]
+    fn test_namespace_matches() {
+        assert!(namespace_matches("team-a", "team-a", "echo"));
+        assert!(!namespace_matches("team-a", "team-b", "echo"));
+        assert!(namespace_matches("team-a", "", ""));
+        assert!(namespace_matches(ALL_NAMESPACES, "team-b", "echo"));
+        assert!(!namespace_matches("team-a", ALL_NAMESPACES, ""));
+        assert!(namespace_matches(ALL_NAMESPACES, ALL_NAMESPACES, ""));
+    }
+
+    #[test]
+    fn test_namespace_matches_needs_namespace_for_functions() {
+        assert!(!namespace_matches("team-a", "", "echo"));
+        assert!(namespace_matches(ALL_NAMESPACES, "", "echo"));
+    }
+
+    #[test]
+    fn test_is_allowed_limits_resources() {
+        let resources = patterns(&["echo"]);
+        assert!(is_allowed(
+            &[Scope::Deploy],
+            &resources,
+            Scope::Deploy,
+            "echo"
+        ));
+        assert!(!is_allowed(
+            &[Scope::Deploy],
+            &resources,
+            Scope::Deploy,
+            "custom"
+        ));
+        assert!(is_allowed(&[Scope::Push], &resources, Scope::Push, ""));
+    }
+}
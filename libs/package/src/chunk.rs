@@ -0,0 +1,107 @@
+use std::io::{self, Read};
+
+use fastcdc::v2020::{Error, StreamCDC};
+use sha2::{Digest as _, Sha256};
+
+pub const MIN_CHUNK_SIZE: u32 = 16 * 1024;
+pub const AVG_CHUNK_SIZE: u32 = 64 * 1024;
+pub const MAX_CHUNK_SIZE: u32 = 256 * 1024;
+
+const HASH_LEN: usize = 64;
+
+/// A content-defined slice of an uncompressed archive. Cut points depend on the
+/// bytes around them, so a change to one file only changes the chunks covering it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// Hex SHA-256 of the chunk's bytes
+    pub hash: String,
+    pub offset: u64,
+    pub length: usize,
+}
+
+/// Split everything `reader` yields into chunks
+pub fn chunk_archive<R: Read>(reader: R) -> impl Iterator<Item = io::Result<Chunk>> {
+    StreamCDC::new(reader, MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE).map(|chunk| {
+        let chunk = chunk.map_err(|err| match err {
+            Error::IoError(err) => err,
+            err => io::Error::other(err.to_string()),
+        })?;
+        Ok(Chunk {
+            hash: chunk_hash(&chunk.data),
+            offset: chunk.offset,
+            length: chunk.length,
+        })
+    })
+}
+
+pub fn chunk_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Whether `hash` can name a chunk, anything else must never become a path
+pub fn is_chunk_hash(hash: &str) -> bool {
+    hash.len() == HASH_LEN
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic bytes that do not repeat, so cut points are content driven
+    fn data(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    fn chunks(data: &[u8]) -> Vec<Chunk> {
+        chunk_archive(data).collect::<io::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn test_chunks_cover_input() {
+        let data = data(1024 * 1024, 1);
+        let chunks = chunks(&data);
+
+        let mut offset = 0;
+        for chunk in &chunks {
+            assert_eq!(chunk.offset, offset);
+            assert!(chunk.length <= MAX_CHUNK_SIZE as usize);
+            let bytes = &data[offset as usize..offset as usize + chunk.length];
+            assert_eq!(chunk.hash, chunk_hash(bytes));
+            assert!(is_chunk_hash(&chunk.hash));
+            offset += chunk.length as u64;
+        }
+        assert_eq!(offset, data.len() as u64);
+    }
+
+    #[test]
+    fn test_edit_changes_few_chunks() {
+        let original = data(1024 * 1024, 2);
+        let mut edited = original.clone();
+        edited.splice(500_000..500_000, b"inserted".iter().copied());
+
+        let before: Vec<String> = chunks(&original).into_iter().map(|c| c.hash).collect();
+        let after = chunks(&edited);
+        let new = after.iter().filter(|c| !before.contains(&c.hash)).count();
+
+        assert!(after.len() > 4);
+        assert!(new <= 2, "{} of {} chunks changed", new, after.len());
+    }
+
+    #[test]
+    fn test_is_chunk_hash() {
+        assert!(is_chunk_hash(&chunk_hash(b"hello")));
+        assert!(!is_chunk_hash("../etc/passwd"));
+        assert!(!is_chunk_hash(&chunk_hash(b"hello").to_uppercase()));
+    }
+}
@@ -0,0 +1,160 @@
+use std::{fmt, pin::Pin, str::FromStr};
+
+use async_compression::tokio::{
+    bufread::{GzipDecoder, ZstdDecoder},
+    write::{GzipEncoder, ZstdEncoder},
+};
+use proto::api::registry::Compression as WireCompression;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+pub type BoxedReader<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+pub type BoxedWriter<'a> = Pin<Box<dyn AsyncWrite + Send + 'a>>;
+
+/// How a package archive is compressed in transfer and storage. Digests are always
+/// taken over the uncompressed archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 3] = [Compression::Zstd, Compression::Gzip, Compression::None];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// File extension of a stored archive
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "tar",
+            Compression::Gzip => "tar.gz",
+            Compression::Zstd => "tar.zst",
+        }
+    }
+
+    /// Read the uncompressed archive from `reader`
+    pub fn decoder<'a, R: AsyncBufRead + Send + 'a>(&self, reader: R) -> BoxedReader<'a> {
+        match self {
+            Compression::None => Box::pin(reader),
+            Compression::Gzip => Box::pin(GzipDecoder::new(reader)),
+            Compression::Zstd => Box::pin(ZstdDecoder::new(reader)),
+        }
+    }
+
+    /// Compress everything written into `writer`. Call `shutdown` to write the end of
+    /// the stream.
+    pub fn encoder<'a, W: AsyncWrite + Send + 'a>(&self, writer: W) -> BoxedWriter<'a> {
+        match self {
+            Compression::None => Box::pin(writer),
+            Compression::Gzip => Box::pin(GzipEncoder::new(writer)),
+            Compression::Zstd => Box::pin(ZstdEncoder::new(writer)),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!(
+                "unknown compression '{}', expected none, gzip or zstd",
+                s
+            )),
+        }
+    }
+}
+
+impl From<WireCompression> for Compression {
+    fn from(value: WireCompression) -> Self {
+        match value {
+            WireCompression::None => Compression::None,
+            WireCompression::Gzip => Compression::Gzip,
+            WireCompression::Zstd => Compression::Zstd,
+        }
+    }
+}
+
+impl From<Compression> for WireCompression {
+    fn from(value: Compression) -> Self {
+        match value {
+            Compression::None => WireCompression::None,
+            Compression::Gzip => WireCompression::Gzip,
+            Compression::Zstd => WireCompression::Zstd,
+        }
+    }
+}
+
+impl TryFrom<i32> for Compression {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        WireCompression::try_from(value)
+            .map(Compression::from)
+            .map_err(|_| format!("unknown compression {}", value))
+    }
+}
+
+impl From<Compression> for i32 {
+    fn from(value: Compression) -> Self {
+        WireCompression::from(value) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let data = b"hello hello hello hello".repeat(100);
+        for compression in Compression::ALL {
+            let mut compressed = Vec::new();
+            let mut encoder = compression.encoder(&mut compressed);
+            encoder.write_all(&data).await.unwrap();
+            encoder.shutdown().await.unwrap();
+            drop(encoder);
+
+            if compression != Compression::None {
+                assert!(compressed.len() < data.len(), "{}", compression);
+            }
+
+            let mut decompressed = Vec::new();
+            compression
+                .decoder(&compressed[..])
+                .read_to_end(&mut decompressed)
+                .await
+                .unwrap();
+            assert_eq!(decompressed, data, "{}", compression);
+        }
+    }
+
+    #[test]
+    fn test_wire_conversion() {
+        for compression in Compression::ALL {
+            let wire: i32 = compression.into();
+            assert_eq!(Compression::try_from(wire).unwrap(), compression);
+            assert_eq!(compression.as_str().parse(), Ok(compression));
+        }
+        assert!(Compression::try_from(42).is_err());
+        assert!("lz4".parse::<Compression>().is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use std::{collections::BTreeMap, fmt, io, str::FromStr};
+
+use sha2::{Digest as _, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_stream::StreamExt;
+use tokio_tar::{Archive, Entry};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const HASH_LEN: usize = 64;
+const V2_PREFIX: &str = "v2:";
+const V2_DOMAIN: &[u8] = b"noctiforge-digest-v2\n";
+
+/// How a digest was computed. Old digests stay valid so existing deployments keep
+/// resolving, new packages always get [`CURRENT_VERSION`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DigestVersion {
+    /// Bare hex over the paths of directories and the paths and bytes of regular files
+    V1,
+    /// `v2:` prefixed hex over every entry's type, path, mode, owner, link target and
+    /// content, independent of entry order and timestamps
+    V2,
+}
+
+pub const CURRENT_VERSION: DigestVersion = DigestVersion::V2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Digest {
+    version: DigestVersion,
+    hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidDigest(String);
+
+impl fmt::Display for InvalidDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid digest '{}'", self.0)
+    }
+}
+
+impl std::error::Error for InvalidDigest {}
+
+impl Digest {
+    pub fn version(&self) -> DigestVersion {
+        self.version
+    }
+
+    /// Hex encoded SHA-256, without the version prefix
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Short identifier, unique enough to name a running function
+    pub fn short(&self) -> String {
+        let hash = &self.hash[..16];
+        match self.version {
+            DigestVersion::V1 => hash.to_string(),
+            DigestVersion::V2 => format!("v2-{}", hash),
+        }
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            DigestVersion::V1 => f.write_str(&self.hash),
+            DigestVersion::V2 => write!(f, "{}{}", V2_PREFIX, self.hash),
+        }
+    }
+}
+
+impl FromStr for Digest {
+    type Err = InvalidDigest;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (version, hash) = match s.strip_prefix(V2_PREFIX) {
+            Some(hash) => (DigestVersion::V2, hash),
+            None => (DigestVersion::V1, s),
+        };
+
+        let valid = hash.len() == HASH_LEN
+            && hash
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c));
+        if !valid {
+            return Err(InvalidDigest(s.to_string()));
+        }
+
+        Ok(Self {
+            version,
+            hash: hash.to_string(),
+        })
+    }
+}
+
+/// Compute the `version` digest of `archive`, reading file contents in chunks
+pub async fn compute_digest<R: AsyncRead + Unpin>(
+    mut archive: Archive<R>,
+    version: DigestVersion,
+) -> io::Result<Digest> {
+    let hash = match version {
+        DigestVersion::V1 => hash_v1(&mut archive).await?,
+        DigestVersion::V2 => hash_v2(&mut archive).await?,
+    };
+    Ok(Digest { version, hash })
+}
+
+async fn hash_v1<R: AsyncRead + Unpin>(archive: &mut Archive<R>) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+
+    let mut entries = archive.entries()?;
+    while let Some(file) = entries.next().await {
+        let mut entry = file?;
+        let path = entry.path()?.to_string_lossy().to_string();
+
+        if entry.header().entry_type().is_file() {
+            hasher.update(b"file:"); // prefix to differentiate files/folders
+            hasher.update(path.as_bytes());
+            hash_content(&mut entry, &mut hasher).await?;
+        } else if entry.header().entry_type().is_dir() {
+            hasher.update(b"dir:"); // prefix for directories
+            hasher.update(path.as_bytes());
+        }
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+async fn hash_v2<R: AsyncRead + Unpin>(archive: &mut Archive<R>) -> io::Result<String> {
+    // One record per path, sorted so the order entries were archived in does not
+    // matter. A later entry for the same path replaces the earlier one, just like
+    // it does on extraction.
+    let mut records = BTreeMap::new();
+
+    let mut entries = archive.entries()?;
+    while let Some(file) = entries.next().await {
+        let mut entry = file?;
+        let path = normalize(&entry.path()?.to_string_lossy());
+        if path.is_empty() {
+            continue;
+        }
+
+        let header = entry.header();
+        let kind = header.entry_type();
+        let mode = header.mode()? & 0o7777;
+        let uid = header.uid()?;
+        let gid = header.gid()?;
+        let link = entry
+            .link_name()?
+            .map(|link| link.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let content = match kind.is_file() {
+            true => {
+                let mut hasher = Sha256::new();
+                hash_content(&mut entry, &mut hasher).await?;
+                format!("{:x}", hasher.finalize())
+            }
+            false => String::new(),
+        };
+
+        let record = format!(
+            "{}\0{:o}\0{}\0{}\0{}\0{}\n",
+            kind.as_byte() as char,
+            mode,
+            uid,
+            gid,
+            link,
+            content
+        );
+        records.insert(path, record);
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(V2_DOMAIN);
+    for (path, record) in records {
+        hasher.update(path.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(record.as_bytes());
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+async fn hash_content<R: AsyncRead + Unpin>(
+    entry: &mut Entry<Archive<R>>,
+    hasher: &mut Sha256,
+) -> io::Result<()> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = entry.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// `./bin/` and `bin` name the same entry
+fn normalize(path: &str) -> String {
+    let mut path = path.trim_end_matches('/');
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    match path {
+        "." => String::new(),
+        path => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tar::{Builder, EntryType, Header};
+
+    struct TestEntry {
+        path: &'static str,
+        kind: EntryType,
+        mode: u32,
+        link: Option<&'static str>,
+        data: &'static [u8],
+        mtime: u64,
+    }
+
+    fn file(path: &'static str, mode: u32, data: &'static [u8]) -> TestEntry {
+        TestEntry {
+            path,
+            kind: EntryType::Regular,
+            mode,
+            link: None,
+            data,
+            mtime: 0,
+        }
+    }
+
+    fn symlink(path: &'static str, target: &'static str) -> TestEntry {
+        TestEntry {
+            path,
+            kind: EntryType::Symlink,
+            mode: 0o777,
+            link: Some(target),
+            data: b"",
+            mtime: 0,
+        }
+    }
+
+    async fn archive(entries: &[TestEntry]) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        for entry in entries {
+            let mut header = Header::new_gnu();
+            header.set_entry_type(entry.kind);
+            header.set_mode(entry.mode);
+            header.set_size(entry.data.len() as u64);
+            header.set_mtime(entry.mtime);
+            header.set_uid(0);
+            header.set_gid(0);
+            if let Some(link) = entry.link {
+                header.set_link_name(link).unwrap();
+            }
+            header.set_cksum();
+            builder
+                .append_data(&mut header, entry.path, entry.data)
+                .await
+                .unwrap();
+        }
+        builder.into_inner().await.unwrap()
+    }
+
+    async fn digest(data: &[u8], version: DigestVersion) -> Digest {
+        compute_digest(Archive::new(data), version).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_v1_matches_legacy_format() {
+        let data = archive(&[file("bootstrap", 0o755, b"hello")]).await;
+
+        let mut hasher = Sha256::new();
+        hasher.update(b"file:bootstrap");
+        hasher.update(b"hello");
+        let expected = format!("{:x}", hasher.finalize());
+
+        assert_eq!(digest(&data, DigestVersion::V1).await.to_string(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_v2_covers_mode() {
+        let executable = archive(&[file("bootstrap", 0o755, b"hello")]).await;
+        let plain = archive(&[file("bootstrap", 0o644, b"hello")]).await;
+
+        assert_eq!(
+            digest(&executable, DigestVersion::V1).await,
+            digest(&plain, DigestVersion::V1).await
+        );
+        assert_ne!(
+            digest(&executable, DigestVersion::V2).await,
+            digest(&plain, DigestVersion::V2).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_v2_covers_symlink_target() {
+        let a = archive(&[symlink("lib", "lib-a")]).await;
+        let b = archive(&[symlink("lib", "lib-b")]).await;
+
+        assert_ne!(
+            digest(&a, DigestVersion::V2).await,
+            digest(&b, DigestVersion::V2).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_v2_ignores_order_and_mtime() {
+        let a = archive(&[file("a", 0o644, b"a"), file("./b", 0o644, b"b")]).await;
+        let mut later = file("a", 0o644, b"a");
+        later.mtime = 1_700_000_000;
+        let b = archive(&[file("b", 0o644, b"b"), later]).await;
+
+        assert_eq!(
+            digest(&a, DigestVersion::V2).await,
+            digest(&b, DigestVersion::V2).await
+        );
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let hash = "ab".repeat(32);
+        let v1: Digest = hash.parse().unwrap();
+        assert_eq!(v1.version(), DigestVersion::V1);
+        assert_eq!(v1.to_string(), hash);
+        assert_eq!(v1.short(), &hash[..16]);
+
+        let v2: Digest = format!("v2:{}", hash).parse().unwrap();
+        assert_eq!(v2.version(), DigestVersion::V2);
+        assert_eq!(v2.hash(), hash);
+        assert_eq!(v2.to_string(), format!("v2:{}", hash));
+        assert_eq!(v2.short(), format!("v2-{}", &hash[..16]));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        for invalid in [
+            "",
+            "abc",
+            "../../etc/passwd",
+            &"AB".repeat(32),
+            &format!("v3:{}", "ab".repeat(32)),
+            &format!("v2:{}", "ab".repeat(31)),
+        ] {
+            assert!(invalid.parse::<Digest>().is_err(), "{}", invalid);
+        }
+    }
+}
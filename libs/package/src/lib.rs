@@ -0,0 +1,15 @@
+//! Function packages as stored by the registry and unpacked by the worker.
+
+mod chunk;
+mod compression;
+mod digest;
+mod signature;
+mod validate;
+
+pub use chunk::{
+    AVG_CHUNK_SIZE, Chunk, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE, chunk_archive, chunk_hash, is_chunk_hash,
+};
+pub use compression::{BoxedReader, BoxedWriter, Compression};
+pub use digest::{CURRENT_VERSION, Digest, DigestVersion, InvalidDigest, compute_digest};
+pub use signature::{PublicKey, Signature, SignatureError, SigningKey, TrustedKeys};
+pub use validate::{LimitedReader, Limits, ValidationError, validate_archive};
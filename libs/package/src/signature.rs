@@ -0,0 +1,263 @@
+use std::{fmt, str::FromStr};
+
+use ed25519_dalek::{Signer, Verifier};
+use rand_core::OsRng;
+
+use crate::Digest;
+
+const KEY_PREFIX: &str = "ed25519:";
+/// Signatures cover this followed by the digest, so they cannot be replayed as
+/// signatures over anything else
+const SIGNED_DOMAIN: &[u8] = b"noctiforge-package-signature-v1\n";
+
+/// Private half of a package signing key, stored as the hex encoded seed
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+/// Public half of a signing key, written as `ed25519:<hex>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey(ed25519_dalek::VerifyingKey);
+
+/// A signature over a digest together with the key that made it, written as
+/// `ed25519:<public key hex>:<signature hex>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    key: PublicKey,
+    signature: ed25519_dalek::Signature,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    Malformed(String),
+    /// The package carries no signature at all
+    Unsigned,
+    /// None of the signatures were made by a trusted key
+    Untrusted,
+    /// A trusted key signed something other than this digest
+    Invalid,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(value) => write!(f, "malformed key or signature '{}'", value),
+            Self::Unsigned => f.write_str("package is not signed"),
+            Self::Untrusted => f.write_str("package is not signed by a trusted key"),
+            Self::Invalid => f.write_str("package signature does not match its digest"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        Self(ed25519_dalek::SigningKey::generate(&mut OsRng))
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.verifying_key())
+    }
+
+    pub fn sign(&self, digest: &Digest) -> Signature {
+        Signature {
+            key: self.public_key(),
+            signature: self.0.sign(&signed_message(digest)),
+        }
+    }
+
+    /// Hex encoded seed, the format [`FromStr`] reads
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0.to_bytes())
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let seed = decode_hex::<32>(s.trim())
+            .ok_or_else(|| SignatureError::Malformed("<private key>".to_string()))?;
+        Ok(Self(ed25519_dalek::SigningKey::from_bytes(&seed)))
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", KEY_PREFIX, hex::encode(self.0.as_bytes()))
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || SignatureError::Malformed(s.to_string());
+        let bytes = s
+            .strip_prefix(KEY_PREFIX)
+            .and_then(decode_hex::<32>)
+            .ok_or_else(malformed)?;
+        ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+            .map(Self)
+            .map_err(|_| malformed())
+    }
+}
+
+impl Signature {
+    pub fn public_key(&self) -> &PublicKey {
+        &self.key
+    }
+
+    /// Whether this is a signature over `digest` by its key. Whether the key is
+    /// trusted is up to the caller.
+    pub fn verify(&self, digest: &Digest) -> Result<(), SignatureError> {
+        self.key
+            .0
+            .verify(&signed_message(digest), &self.signature)
+            .map_err(|_| SignatureError::Invalid)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.key, hex::encode(self.signature.to_bytes()))
+    }
+}
+
+impl FromStr for Signature {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, signature) = s
+            .rsplit_once(':')
+            .ok_or_else(|| SignatureError::Malformed(s.to_string()))?;
+        let signature =
+            decode_hex::<64>(signature).ok_or_else(|| SignatureError::Malformed(s.to_string()))?;
+        Ok(Self {
+            key: key.parse()?,
+            signature: ed25519_dalek::Signature::from_bytes(&signature),
+        })
+    }
+}
+
+/// Public keys a package must be signed with before it runs
+#[derive(Debug, Clone, Default)]
+pub struct TrustedKeys(Vec<PublicKey>);
+
+impl TrustedKeys {
+    pub fn new(keys: Vec<PublicKey>) -> Self {
+        Self(keys)
+    }
+
+    /// One `ed25519:<hex>` key per line, blank lines and `#` comments are skipped
+    pub fn parse(content: &str) -> Result<Self, SignatureError> {
+        content
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Accept `digest` if one of `signatures` is a valid signature by a trusted key,
+    /// and return that key
+    pub fn verify(
+        &self,
+        digest: &Digest,
+        signatures: &[Signature],
+    ) -> Result<&PublicKey, SignatureError> {
+        if signatures.is_empty() {
+            return Err(SignatureError::Unsigned);
+        }
+
+        let mut result = Err(SignatureError::Untrusted);
+        for signature in signatures {
+            let Some(key) = self.0.iter().find(|key| **key == signature.key) else {
+                continue;
+            };
+            match signature.verify(digest) {
+                Ok(()) => return Ok(key),
+                Err(err) => result = Err(err),
+            }
+        }
+        result
+    }
+}
+
+fn signed_message(digest: &Digest) -> Vec<u8> {
+    let mut message = SIGNED_DOMAIN.to_vec();
+    message.extend_from_slice(digest.to_string().as_bytes());
+    message
+}
+
+fn decode_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let mut bytes = [0; N];
+    hex::decode_to_slice(s, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(byte: char) -> Digest {
+        format!("v2:{}", byte.to_string().repeat(64))
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::generate();
+        let trusted = TrustedKeys::new(vec![key.public_key()]);
+        let signature = key.sign(&digest('a'));
+
+        assert_eq!(
+            trusted.verify(&digest('a'), std::slice::from_ref(&signature)),
+            Ok(&key.public_key())
+        );
+        assert_eq!(
+            trusted.verify(&digest('b'), &[signature]),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            trusted.verify(&digest('a'), &[]),
+            Err(SignatureError::Unsigned)
+        );
+    }
+
+    #[test]
+    fn test_untrusted_key() {
+        let trusted = TrustedKeys::new(vec![SigningKey::generate().public_key()]);
+        let signature = SigningKey::generate().sign(&digest('a'));
+
+        assert!(signature.verify(&digest('a')).is_ok());
+        assert_eq!(
+            trusted.verify(&digest('a'), &[signature]),
+            Err(SignatureError::Untrusted)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let key = SigningKey::generate();
+        let parsed: SigningKey = key.to_hex().parse().unwrap();
+        assert_eq!(parsed.public_key(), key.public_key());
+
+        let signature = key.sign(&digest('c'));
+        assert_eq!(signature.to_string().parse::<Signature>(), Ok(signature));
+
+        let content = format!("# deploy key\n{} # ci\n\n", key.public_key());
+        assert_eq!(TrustedKeys::parse(&content).unwrap().len(), 1);
+        assert!(TrustedKeys::parse("ed25519:zz").is_err());
+        assert!("ed25519:00:00".parse::<Signature>().is_err());
+    }
+}
@@ -0,0 +1,572 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt, io,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio_stream::StreamExt;
+use tokio_tar::{Archive, EntryType};
+
+pub const DEFAULT_MAX_TOTAL_SIZE: u64 = 512 * 1024 * 1024;
+pub const DEFAULT_MAX_ENTRIES: u64 = 10_000;
+pub const DEFAULT_MAX_RATIO: u64 = 100;
+/// Symlinks followed while resolving one link before it counts as a loop, as on Linux
+const MAX_LINK_HOPS: usize = 40;
+/// Tar header and padding allowed per entry on top of the unpacked size limit
+const TAR_OVERHEAD_PER_ENTRY: u64 = 1024;
+/// Fixed growth allowed on top of 1/128 for an archive that does not compress, zstd
+/// and gzip frames add far less
+const COMPRESSION_OVERHEAD: u64 = 64 * 1024;
+
+/// Bounds a package has to stay within before it is stored or unpacked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Sum of the sizes of all entries once unpacked
+    pub max_total_size: u64,
+    pub max_entries: u64,
+    /// Unpacked size divided by the size of the archive as transferred
+    pub max_ratio: u64,
+}
+
+impl Limits {
+    /// Largest tar archive that can still unpack within the limits
+    pub fn max_archive_size(&self) -> u64 {
+        self.max_total_size
+            .saturating_add(self.max_entries.saturating_mul(TAR_OVERHEAD_PER_ENTRY))
+    }
+
+    /// Largest archive as transferred that can still unpack within the limits, for
+    /// data that does not compress
+    pub fn max_compressed_size(&self) -> u64 {
+        let size = self.max_archive_size();
+        size.saturating_add(size / 128)
+            .saturating_add(COMPRESSION_OVERHEAD)
+    }
+
+    /// Wrap the uncompressed archive `reader` so it fails once it grows past
+    /// [`Limits::max_archive_size`], before an archive is hashed or checked entry by
+    /// entry
+    pub fn limit_reader<R: AsyncRead>(&self, reader: R) -> LimitedReader<R> {
+        LimitedReader {
+            inner: reader,
+            remaining: self.max_archive_size(),
+            limit: self.max_archive_size(),
+        }
+    }
+}
+
+/// Uncompressed archive that fails with [`io::ErrorKind::InvalidData`] instead of
+/// yielding more than its limit, so a small compressed archive cannot keep a reader
+/// busy unpacking
+pub struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+    limit: u64,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for LimitedReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let read = (buf.filled().len() - filled) as u64;
+        if read > self.remaining {
+            buf.set_filled(filled);
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("archive unpacks to more than {} bytes", self.limit),
+            )));
+        }
+        self.remaining -= read;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_ratio: DEFAULT_MAX_RATIO,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ValidationError {
+    Io(io::Error),
+    AbsolutePath(String),
+    PathTraversal(String),
+    DeviceNode(String),
+    UnsupportedEntry(String),
+    SetId(String),
+    EscapingLink { path: String, target: String },
+    LinkLoop(String),
+    TooLarge { limit: u64 },
+    TooManyEntries { limit: u64 },
+    RatioExceeded { ratio: u64, limit: u64 },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read archive: {}", err),
+            Self::AbsolutePath(path) => write!(f, "entry '{}' has an absolute path", path),
+            Self::PathTraversal(path) => write!(f, "entry '{}' contains '..'", path),
+            Self::DeviceNode(path) => write!(f, "entry '{}' is a device node or fifo", path),
+            Self::UnsupportedEntry(path) => write!(f, "entry '{}' has an unsupported type", path),
+            Self::SetId(path) => write!(f, "entry '{}' has the setuid or setgid bit", path),
+            Self::EscapingLink { path, target } => write!(
+                f,
+                "link '{}' points to '{}' outside of the package",
+                path, target
+            ),
+            Self::LinkLoop(path) => write!(f, "link '{}' is part of a symlink loop", path),
+            Self::TooLarge { limit } => {
+                write!(f, "unpacked size exceeds the limit of {} bytes", limit)
+            }
+            Self::TooManyEntries { limit } => {
+                write!(f, "archive has more than {} entries", limit)
+            }
+            Self::RatioExceeded { ratio, limit } => write!(
+                f,
+                "unpacked size is {} times the archive size, the limit is {}",
+                ratio, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl From<io::Error> for ValidationError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Check every entry of `archive` against `limits`. `archive_size` is the number of
+/// bytes the archive took to transfer.
+pub async fn validate_archive<R: AsyncRead + Unpin>(
+    mut archive: Archive<R>,
+    archive_size: u64,
+    limits: &Limits,
+) -> Result<(), ValidationError> {
+    let mut total_size: u64 = 0;
+    let mut count: u64 = 0;
+    // A link can point through links that come later in the archive, so they are
+    // only resolved once every entry is known.
+    let mut symlinks: BTreeMap<Vec<OsString>, PathBuf> = BTreeMap::new();
+    let mut hard_links: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    let mut entries = archive.entries()?;
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+
+        count += 1;
+        if count > limits.max_entries {
+            return Err(ValidationError::TooManyEntries {
+                limit: limits.max_entries,
+            });
+        }
+
+        let header = entry.header();
+        let path = entry.path()?;
+        let link = entry.link_name()?;
+        check_entry(&path, header.entry_type(), header.mode()?, link.as_deref())?;
+
+        let key = components(&path);
+        match (header.entry_type(), link) {
+            (EntryType::Symlink, Some(target)) => {
+                symlinks.insert(key, target.into_owned());
+            }
+            (EntryType::Link, Some(target)) => {
+                symlinks.remove(&key);
+                hard_links.push((path.into_owned(), target.into_owned()));
+            }
+            _ => {
+                symlinks.remove(&key);
+            }
+        }
+
+        total_size = total_size.saturating_add(header.size()?);
+        if total_size > limits.max_total_size {
+            return Err(ValidationError::TooLarge {
+                limit: limits.max_total_size,
+            });
+        }
+    }
+
+    for (path, target) in &symlinks {
+        let parent = path[..path.len().saturating_sub(1)].to_vec();
+        let name: PathBuf = path.iter().collect();
+        follow(&symlinks, parent, target, &mut 0).map_err(|err| err.into_error(&name, target))?;
+    }
+    for (path, target) in &hard_links {
+        follow(&symlinks, Vec::new(), target, &mut 0)
+            .map_err(|err| err.into_error(path, target))?;
+    }
+
+    let ratio = total_size / archive_size.max(1);
+    if ratio > limits.max_ratio {
+        return Err(ValidationError::RatioExceeded {
+            ratio,
+            limit: limits.max_ratio,
+        });
+    }
+
+    Ok(())
+}
+
+fn check_entry(
+    path: &Path,
+    kind: EntryType,
+    mode: u32,
+    link: Option<&Path>,
+) -> Result<(), ValidationError> {
+    let name = path.to_string_lossy().to_string();
+
+    if path.has_root() {
+        return Err(ValidationError::AbsolutePath(name));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(ValidationError::PathTraversal(name));
+    }
+    let depth = path
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .count();
+
+    match kind {
+        EntryType::Regular | EntryType::Directory | EntryType::Continuous => {}
+        EntryType::Symlink => {
+            // Relative targets resolve from the directory holding the link.
+            let target = link.unwrap_or(Path::new(""));
+            let parent = depth.saturating_sub(1);
+            if resolve(parent, target).is_none() {
+                return Err(escaping(name, target));
+            }
+        }
+        EntryType::Link => {
+            // Hard link targets are names of other entries in the archive.
+            let target = link.unwrap_or(Path::new(""));
+            if resolve(0, target).is_none() {
+                return Err(escaping(name, target));
+            }
+        }
+        EntryType::Char | EntryType::Block | EntryType::Fifo => {
+            return Err(ValidationError::DeviceNode(name));
+        }
+        _ => return Err(ValidationError::UnsupportedEntry(name)),
+    }
+
+    if mode & 0o6000 != 0 {
+        return Err(ValidationError::SetId(name));
+    }
+
+    Ok(())
+}
+
+/// Depth below the package root `path` ends up at when followed from `base`
+/// directories deep, `None` if it leaves the root
+fn resolve(base: usize, path: &Path) -> Option<usize> {
+    path.components()
+        .try_fold(base, |depth, component| match component {
+            Component::Normal(_) => Some(depth + 1),
+            Component::CurDir => Some(depth),
+            Component::ParentDir => depth.checked_sub(1),
+            Component::RootDir | Component::Prefix(_) => None,
+        })
+}
+
+enum Unresolved {
+    Escapes,
+    Loop,
+}
+
+impl Unresolved {
+    fn into_error(self, path: &Path, target: &Path) -> ValidationError {
+        let name = path.to_string_lossy().to_string();
+        match self {
+            Self::Escapes => escaping(name, target),
+            Self::Loop => ValidationError::LinkLoop(name),
+        }
+    }
+}
+
+/// Normal components of an entry path, the key symlinks are looked up by
+fn components(path: &Path) -> Vec<OsString> {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Where `target` ends up when followed from the directory `dir` once unpacked,
+/// going through every symlink of the archive on the way like the kernel does
+fn follow(
+    symlinks: &BTreeMap<Vec<OsString>, PathBuf>,
+    mut dir: Vec<OsString>,
+    target: &Path,
+    hops: &mut usize,
+) -> Result<Vec<OsString>, Unresolved> {
+    for component in target.components() {
+        match component {
+            Component::Normal(name) => {
+                dir.push(name.to_owned());
+                if let Some(link) = symlinks.get(&dir) {
+                    *hops += 1;
+                    if *hops > MAX_LINK_HOPS {
+                        return Err(Unresolved::Loop);
+                    }
+                    dir.pop();
+                    dir = follow(symlinks, dir, link, hops)?;
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                dir.pop().ok_or(Unresolved::Escapes)?;
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(Unresolved::Escapes),
+        }
+    }
+    Ok(dir)
+}
+
+fn escaping(path: String, target: &Path) -> ValidationError {
+    ValidationError::EscapingLink {
+        path,
+        target: target.to_string_lossy().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(path: &str, kind: EntryType, mode: u32, link: Option<&str>) -> Result<(), String> {
+        check_entry(Path::new(path), kind, mode, link.map(Path::new)).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_accepts_regular_package() {
+        assert!(check("bootstrap", EntryType::Regular, 0o755, None).is_ok());
+        assert!(check("./lib/", EntryType::Directory, 0o755, None).is_ok());
+        assert!(check("lib/libc.so", EntryType::Symlink, 0o777, Some("libc.so.6")).is_ok());
+        assert!(
+            check(
+                "lib/a/b",
+                EntryType::Symlink,
+                0o777,
+                Some("../../bootstrap")
+            )
+            .is_ok()
+        );
+        assert!(check("bin/echo", EntryType::Link, 0o755, Some("bootstrap")).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_unsafe_paths() {
+        assert!(matches!(
+            check_entry(Path::new("/etc/passwd"), EntryType::Regular, 0o644, None),
+            Err(ValidationError::AbsolutePath(_))
+        ));
+        assert!(matches!(
+            check_entry(Path::new("../outside"), EntryType::Regular, 0o644, None),
+            Err(ValidationError::PathTraversal(_))
+        ));
+        assert!(matches!(
+            check_entry(
+                Path::new("lib/../bootstrap"),
+                EntryType::Regular,
+                0o644,
+                None
+            ),
+            Err(ValidationError::PathTraversal(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_escaping_links() {
+        assert!(check("lib", EntryType::Symlink, 0o777, Some("/usr/lib")).is_err());
+        assert!(check("lib/a", EntryType::Symlink, 0o777, Some("../../etc")).is_err());
+        assert!(check("lib", EntryType::Link, 0o644, Some("../secret")).is_err());
+    }
+
+    #[test]
+    fn test_rejects_devices_and_setid() {
+        assert!(matches!(
+            check_entry(Path::new("null"), EntryType::Char, 0o666, None),
+            Err(ValidationError::DeviceNode(_))
+        ));
+        assert!(matches!(
+            check_entry(Path::new("su"), EntryType::Regular, 0o4755, None),
+            Err(ValidationError::SetId(_))
+        ));
+        assert!(matches!(
+            check_entry(Path::new("dir"), EntryType::Directory, 0o2755, None),
+            Err(ValidationError::SetId(_))
+        ));
+    }
+
+    async fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tokio_tar::Builder::new(Vec::new());
+        for (path, data) in files {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_mode(0o644);
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).await.unwrap();
+        }
+        builder.into_inner().await.unwrap()
+    }
+
+    async fn archive_with_links(links: &[(&str, EntryType, &str)]) -> Vec<u8> {
+        let mut builder = tokio_tar::Builder::new(Vec::new());
+        for (path, kind, target) in links {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_entry_type(*kind);
+            header.set_mode(0o777);
+            header.set_size(0);
+            header.set_link_name(target).unwrap();
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, &[][..])
+                .await
+                .unwrap();
+        }
+        builder.into_inner().await.unwrap()
+    }
+
+    async fn validate_links(links: &[(&str, EntryType, &str)]) -> Result<(), ValidationError> {
+        let data = archive_with_links(links).await;
+        validate_archive(
+            Archive::new(&data[..]),
+            data.len() as u64,
+            &Limits::default(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_rejects_link_chain_escaping() {
+        // Lexically `d/d/../..` never leaves the root, but `d` is `.` on disk.
+        let chain = [
+            ("d", EntryType::Symlink, "."),
+            ("e", EntryType::Symlink, "d/d/../.."),
+        ];
+        assert!(matches!(
+            validate_links(&chain).await,
+            Err(ValidationError::EscapingLink { .. })
+        ));
+
+        let reversed = [chain[1], chain[0]];
+        assert!(matches!(
+            validate_links(&reversed).await,
+            Err(ValidationError::EscapingLink { .. })
+        ));
+
+        // The hard link resolves through `d` the same way.
+        let hard = [
+            ("d", EntryType::Symlink, "."),
+            ("h", EntryType::Link, "d/d/../../x"),
+        ];
+        assert!(matches!(
+            validate_links(&hard).await,
+            Err(ValidationError::EscapingLink { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_follows_links_inside_package() {
+        assert!(
+            validate_links(&[
+                ("lib", EntryType::Symlink, "usr/lib"),
+                ("usr/lib/libc.so", EntryType::Symlink, "libc.so.6"),
+                ("bin", EntryType::Symlink, "lib/../bootstrap"),
+            ])
+            .await
+            .is_ok()
+        );
+
+        assert!(matches!(
+            validate_links(&[
+                ("a", EntryType::Symlink, "b"),
+                ("b", EntryType::Symlink, "a"),
+            ])
+            .await,
+            Err(ValidationError::LinkLoop(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_enforces_limits() {
+        let data = archive(&[("a", &[0u8; 100]), ("b", &[0u8; 100])]).await;
+        let size = data.len() as u64;
+
+        assert!(
+            validate_archive(Archive::new(&data[..]), size, &Limits::default())
+                .await
+                .is_ok()
+        );
+
+        let limits = Limits {
+            max_entries: 1,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            validate_archive(Archive::new(&data[..]), size, &limits).await,
+            Err(ValidationError::TooManyEntries { limit: 1 })
+        ));
+
+        let limits = Limits {
+            max_total_size: 150,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            validate_archive(Archive::new(&data[..]), size, &limits).await,
+            Err(ValidationError::TooLarge { limit: 150 })
+        ));
+
+        // As if the archive had been compressed down to 1 byte.
+        assert!(matches!(
+            validate_archive(Archive::new(&data[..]), 1, &Limits::default()).await,
+            Err(ValidationError::RatioExceeded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_limit_reader() {
+        use tokio::io::AsyncReadExt;
+
+        let limits = Limits {
+            max_total_size: 100,
+            max_entries: 1,
+            ..Limits::default()
+        };
+        let max_size = limits.max_archive_size() as usize;
+
+        let mut data = Vec::new();
+        let read = limits
+            .limit_reader(&vec![0u8; max_size][..])
+            .read_to_end(&mut data)
+            .await;
+        assert_eq!(read.unwrap(), max_size);
+
+        let err = limits
+            .limit_reader(&vec![0u8; max_size + 1][..])
+            .read_to_end(&mut Vec::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
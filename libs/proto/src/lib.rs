@@ -12,3 +12,5 @@ pub mod api {
         tonic::include_proto!("noctiforge.worker");
     }
 }
+
+pub mod namespace;
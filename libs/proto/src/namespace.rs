@@ -0,0 +1,88 @@
+//! Function names are unique per namespace. Requests carry the namespace in their
+//! own field, or inline as `namespace/name`; an empty namespace means the default one.
+
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+const SEPARATOR: char = '/';
+const MAX_LEN: usize = 63;
+
+/// Split `name` into namespace and function name.
+///
+/// A qualified `namespace/name` wins over `namespace`, which in turn falls back to
+/// [`DEFAULT_NAMESPACE`] when empty. Fails unless both parts are non-empty and the
+/// namespace is valid, so `/echo` never reaches authorization as "no namespace".
+pub fn resolve<'a>(namespace: &'a str, name: &'a str) -> Result<(&'a str, &'a str), String> {
+    let (namespace, name) = match name.split_once(SEPARATOR) {
+        Some((namespace, name)) => (namespace, name),
+        None if namespace.is_empty() => (DEFAULT_NAMESPACE, name),
+        None => (namespace, name),
+    };
+    validate(namespace)?;
+    if name.is_empty() {
+        return Err("function name must not be empty".to_string());
+    }
+    Ok((namespace, name))
+}
+
+/// `namespace/name`, the form used for cache keys and logs
+pub fn qualified(namespace: &str, name: &str) -> String {
+    format!("{}{}{}", namespace, SEPARATOR, name)
+}
+
+/// Namespaces are 1 to 63 lowercase letters, digits or dashes
+pub fn validate(namespace: &str) -> Result<(), String> {
+    let valid = !namespace.is_empty()
+        && namespace.len() <= MAX_LEN
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+    match valid {
+        true => Ok(()),
+        false => Err(format!(
+            "invalid namespace '{}', use 1 to {} lowercase letters, digits or '-'",
+            namespace, MAX_LEN
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_defaults_namespace() {
+        assert_eq!(resolve("", "echo"), Ok(("default", "echo")));
+        assert_eq!(resolve("team-a", "echo"), Ok(("team-a", "echo")));
+    }
+
+    #[test]
+    fn test_resolve_qualified_name_wins() {
+        assert_eq!(resolve("", "team-a/echo"), Ok(("team-a", "echo")));
+        assert_eq!(resolve("team-b", "team-a/echo"), Ok(("team-a", "echo")));
+    }
+
+    #[test]
+    fn test_resolve_rejects_empty_parts() {
+        assert!(resolve("", "/echo").is_err());
+        assert!(resolve("team-a", "/echo").is_err());
+        assert!(resolve("", "team-a/").is_err());
+        assert!(resolve("team-a", "").is_err());
+        assert!(resolve("Team", "echo").is_err());
+    }
+
+    #[test]
+    fn test_qualified_round_trips() {
+        let name = qualified("team-a", "echo");
+        assert_eq!(resolve("", &name), Ok(("team-a", "echo")));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("team-a").is_ok());
+        assert!(validate("").is_err());
+        assert!(validate("Team").is_err());
+        assert!(validate("a/b").is_err());
+        assert!(validate(&"a".repeat(64)).is_err());
+    }
+}
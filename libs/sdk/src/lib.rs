@@ -16,6 +16,11 @@ use tonic::Status;
 
 pub use proto::api::action::Problem;
 
+/// Where the handler listens inside its container
+const DEFAULT_SOCKET_PATH: &str = "/run/app.sock";
+/// Overrides the socket path, used to run a handler outside a container
+pub const SOCKET_PATH_ENV: &str = "NOCTI_SOCKET_PATH";
+
 #[allow(dead_code)]
 pub struct Context {
     pub values: HashMap<String, String>,
@@ -94,13 +99,14 @@ where
         }
     }
 
-    let socket_path = "/run/app.sock";
+    let socket_path =
+        std::env::var(SOCKET_PATH_ENV).unwrap_or_else(|_| DEFAULT_SOCKET_PATH.to_string());
 
     if std::path::Path::new(&socket_path).exists() {
-        std::fs::remove_file(socket_path)?;
+        std::fs::remove_file(&socket_path)?;
     }
 
-    let listener = UnixListener::bind(socket_path)?;
+    let listener = UnixListener::bind(&socket_path)?;
     log::info!("Starting server on Unix socket: {}", socket_path);
 
     let svc = FunctionRunnerServiceServer::new(MyService {
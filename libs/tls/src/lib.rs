@@ -0,0 +1,194 @@
+//! TLS settings shared by the NoctiForge services and CLI.
+//!
+//! Plain `http://` URLs keep working; TLS is used for every `https://` URL and for
+//! servers that are given a certificate.
+
+use std::{fmt, io, path::PathBuf};
+
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig};
+use tracing::debug;
+
+#[derive(Debug)]
+pub enum TlsError {
+    Read { path: PathBuf, source: io::Error },
+    Config(&'static str),
+    Transport(tonic::transport::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            Self::Config(reason) => write!(f, "invalid TLS configuration: {}", reason),
+            Self::Transport(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read { source, .. } => Some(source),
+            Self::Config(_) => None,
+            Self::Transport(e) => Some(e),
+        }
+    }
+}
+
+impl From<tonic::transport::Error> for TlsError {
+    fn from(e: tonic::transport::Error) -> Self {
+        Self::Transport(e)
+    }
+}
+
+/// Certificate paths of a service or client.
+///
+/// `cert` and `key` are presented to peers, as server identity and, when the other
+/// side asks for it, as client identity. `ca` verifies the servers we connect to and,
+/// with `require_client_cert`, the clients connecting to us.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub ca: Option<PathBuf>,
+    pub require_client_cert: bool,
+}
+
+impl TlsConfig {
+    pub fn identity(&self) -> Result<Option<Identity>, TlsError> {
+        match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => Ok(Some(Identity::from_pem(read(cert)?, read(key)?))),
+            (None, None) => Ok(None),
+            _ => Err(TlsError::Config(
+                "a certificate and key must be given together",
+            )),
+        }
+    }
+
+    fn ca_certificate(&self) -> Result<Option<Certificate>, TlsError> {
+        self.ca
+            .as_ref()
+            .map(|ca| read(ca).map(Certificate::from_pem))
+            .transpose()
+    }
+
+    /// Server side settings, `None` when no certificate is configured
+    pub fn server(&self) -> Result<Option<ServerTlsConfig>, TlsError> {
+        let Some(identity) = self.identity()? else {
+            if self.require_client_cert {
+                return Err(TlsError::Config(
+                    "client certificates can only be required when serving TLS",
+                ));
+            }
+            return Ok(None);
+        };
+
+        let mut config = ServerTlsConfig::new().identity(identity);
+        if self.require_client_cert {
+            let ca = self.ca_certificate()?.ok_or(TlsError::Config(
+                "a CA is required to verify client certificates",
+            ))?;
+            config = config.client_ca_root(ca);
+        }
+        Ok(Some(config))
+    }
+
+    /// Client side settings used for `https://` URLs
+    pub fn client(&self) -> Result<ClientTlsConfig, TlsError> {
+        let mut config = ClientTlsConfig::new();
+        config = match self.ca_certificate()? {
+            Some(ca) => config.ca_certificate(ca),
+            None => config.with_enabled_roots(),
+        };
+        if let Some(identity) = self.identity()? {
+            config = config.identity(identity);
+        }
+        Ok(config)
+    }
+
+    /// Endpoint for `url`, with TLS enabled when the URL uses `https`
+    pub fn endpoint(&self, url: impl Into<String>) -> Result<Endpoint, TlsError> {
+        let url = url.into();
+        let endpoint = Endpoint::from_shared(url.clone())?;
+        if !url.starts_with("https://") {
+            return Ok(endpoint);
+        }
+
+        debug!(url = %url, "Using TLS");
+        Ok(endpoint.tls_config(self.client()?)?)
+    }
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path).map_err(|source| TlsError::Read {
+        path: path.clone(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_no_certificate_means_plaintext_server() {
+        assert!(TlsConfig::default().server().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_certificate_requires_key() {
+        let config = TlsConfig {
+            cert: Some(PathBuf::from("server.pem")),
+            ..Default::default()
+        };
+        assert!(matches!(config.identity(), Err(TlsError::Config(_))));
+    }
+
+    #[test]
+    fn test_client_cert_requires_server_tls() {
+        let config = TlsConfig {
+            require_client_cert: true,
+            ..Default::default()
+        };
+        assert!(matches!(config.server(), Err(TlsError::Config(_))));
+    }
+
+    #[test]
+    fn test_client_cert_requires_ca() {
+        let temp = TempDir::new().unwrap();
+        let cert = temp.path().join("server.pem");
+        let key = temp.path().join("server.key");
+        std::fs::write(&cert, "cert").unwrap();
+        std::fs::write(&key, "key").unwrap();
+
+        let config = TlsConfig {
+            cert: Some(cert),
+            key: Some(key),
+            ca: None,
+            require_client_cert: true,
+        };
+        assert!(matches!(config.server(), Err(TlsError::Config(_))));
+    }
+
+    #[test]
+    fn test_missing_file_is_reported() {
+        let config = TlsConfig {
+            ca: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..Default::default()
+        };
+        let err = config.endpoint("https://localhost:50001").unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/ca.pem"));
+    }
+
+    #[test]
+    fn test_http_url_skips_tls() {
+        let config = TlsConfig {
+            ca: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..Default::default()
+        };
+        assert!(config.endpoint("http://localhost:50001").is_ok());
+    }
+}
@@ -0,0 +1,33 @@
+#!/bin/sh
+set -eu
+
+# Generates a local CA and certificates for development. Every certificate is valid
+# for localhost and can be used both as server and client identity, so the same
+# files work for TLS and mutual TLS. Not meant for production.
+OUT="${1:-certs}"
+DAYS=365
+mkdir -p "$OUT"
+
+echo "Creating CA in $OUT..."
+openssl req -x509 -newkey rsa:4096 -nodes -days "$DAYS" \
+  -subj "/CN=NoctiForge Dev CA" \
+  -keyout "$OUT/ca.key" -out "$OUT/ca.pem" 2>/dev/null
+
+for name in registry controlplane worker cli; do
+  echo "Creating certificate for $name..."
+  cat > "$OUT/$name.ext" <<EOF
+basicConstraints = CA:FALSE
+keyUsage = digitalSignature, keyEncipherment
+extendedKeyUsage = serverAuth, clientAuth
+subjectAltName = DNS:localhost, IP:127.0.0.1, IP:::1
+EOF
+  openssl req -newkey rsa:2048 -nodes -subj "/CN=$name" \
+    -keyout "$OUT/$name.key" -out "$OUT/$name.csr" 2>/dev/null
+  openssl x509 -req -in "$OUT/$name.csr" -days "$DAYS" \
+    -CA "$OUT/ca.pem" -CAkey "$OUT/ca.key" -CAcreateserial \
+    -extfile "$OUT/$name.ext" -out "$OUT/$name.pem" 2>/dev/null
+  rm "$OUT/$name.csr" "$OUT/$name.ext"
+done
+
+rm -f "$OUT/ca.srl"
+echo "Done. Keep $OUT/ca.key private, the services only need ca.pem."
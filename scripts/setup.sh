@@ -1,6 +1,8 @@
 #!/bin/sh
 set -euo pipefail
 
+# The services create their own data directories on startup. This script is only
+# needed when running them as a regular user with the default /var/lib paths.
 echo "Creating /var/lib/noctiforge directory with proper permissions..."
 sudo mkdir -pv /var/lib/noctiforge/{registry,controlplane,native_worker/{pkgs,run}}
 sudo chown -R "$(id -u):$(id -g)" /var/lib/noctiforge
@@ -1,8 +1,24 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use package::Compression;
+
+use crate::{
+    config::{CliConfig, Overrides},
+    output::Output,
+};
 
+mod dev;
+mod init;
+mod invoke;
+mod key;
+mod list;
+mod namespace;
 mod push;
-mod trigger;
+mod registry;
+mod run;
+mod signing_key;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -11,20 +27,187 @@ struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 
+    /// How results are printed on stdout, logs always go to stderr
+    #[arg(short, long, global = true, value_enum, default_value_t = Output::Text, env = "NOCTI_OUTPUT")]
+    pub output: Output,
+
+    /// Profile of the config file to use, overrides its `default_profile`
+    #[arg(long, global = true, env = "NOCTI_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Registry URL, overrides the config file
+    #[arg(long, global = true, env = "NOCTI_REGISTRY_URL")]
+    pub registry_url: Option<String>,
+
+    /// Control plane URL, overrides the config file
+    #[arg(long, global = true, env = "NOCTI_CONTROL_PLANE_URL")]
+    pub control_plane_url: Option<String>,
+
+    /// Worker URL, overrides the config file
+    #[arg(long, global = true, env = "NOCTI_WORKER_URL")]
+    pub worker_url: Option<String>,
+
+    /// API key sent with every request, overrides the key in the config file
+    #[arg(long, global = true, env = "NOCTI_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+
+    /// PEM CA certificate used to verify `https://` services
+    #[arg(long, global = true, env = "NOCTI_TLS_CA")]
+    pub tls_ca: Option<PathBuf>,
+
+    /// PEM client certificate, for services requiring mutual TLS
+    #[arg(long, global = true, env = "NOCTI_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key belonging to `--tls-cert`
+    #[arg(long, global = true, env = "NOCTI_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Namespace to work in, overrides the project and config file
+    #[arg(long, global = true, env = "NOCTI_NAMESPACE")]
+    pub namespace: Option<String>,
+
     #[command(subcommand)]
     command: Command,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
+    /// Run a function and print its output
+    Invoke {
+        name: String,
+        /// Request body
+        #[arg(conflicts_with = "file")]
+        body: Option<String>,
+        /// Read the request body from a file, `-` reads stdin
+        #[arg(short, long)]
+        file: Option<String>,
+        /// Metadata passed to the function, can be repeated
+        #[arg(short, long = "metadata", value_name = "KEY=VALUE")]
+        metadata: Vec<String>,
+        /// Print the output as returned instead of pretty-printing JSON
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Build a function and run it once as a local process, without the services
+    Run {
+        path: String,
+        /// Request body
+        #[arg(conflicts_with = "file")]
+        body: Option<String>,
+        /// Read the request body from a file, `-` reads stdin
+        #[arg(short, long)]
+        file: Option<String>,
+        /// Metadata passed to the function, can be repeated
+        #[arg(short, long = "metadata", value_name = "KEY=VALUE")]
+        metadata: Vec<String>,
+        /// Print the output as returned instead of pretty-printing JSON
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Rebuild and redeploy a function under a dev alias whenever its files change
+    Dev {
+        path: String,
+        /// Name the function is deployed as, defaults to `<name>-dev`
+        #[arg(long)]
+        alias: Option<String>,
+        /// File with a request body sent after every deploy, `-` reads stdin
+        #[arg(long)]
+        request: Option<String>,
+        /// Metadata sent with `--request`, can be repeated
+        #[arg(
+            short,
+            long = "metadata",
+            value_name = "KEY=VALUE",
+            requires = "request"
+        )]
+        metadata: Vec<String>,
+        /// Print the output as returned instead of pretty-printing JSON
+        #[arg(long)]
+        raw: bool,
+        /// How the package is compressed for upload and storage: zstd, gzip or none
+        #[arg(long, default_value_t = Compression::Zstd)]
+        compression: Compression,
+        /// Private key the package digest is signed with, overrides the config file
+        #[arg(long, env = "NOCTI_SIGNING_KEY")]
+        signing_key: Option<PathBuf>,
+    },
+    /// Create a new function project
+    Init {
+        name: String,
+        #[arg(long, value_enum, default_value_t = init::Template::Rust)]
+        template: init::Template,
+        /// Directory to create the project in, defaults to `./<name>`
+        #[arg(long)]
+        path: Option<PathBuf>,
+        /// Local SDK checkout to depend on instead of the git repository
+        #[arg(long)]
+        sdk_path: Option<PathBuf>,
+    },
+    /// Older form of `invoke`
+    #[command(hide = true)]
     Trigger {
         action: String,
         payload: String,
         #[arg(value_name = "KEY=VALUE", trailing_var_arg = true)]
         metadata: Vec<String>,
     },
+    /// List the deployed functions with their digest and last update
+    List {
+        /// Same as `--output json`
+        #[arg(long)]
+        json: bool,
+    },
+    /// Build and push the function in a directory, or every function with `push all`
+    #[command(subcommand_negates_reqs = true)]
     Push {
-        path: String,
+        #[command(subcommand)]
+        all: Option<PushAll>,
+        #[arg(required = true)]
+        path: Option<String>,
+        /// How the package is compressed for upload and storage: zstd, gzip or none
+        #[arg(long, global = true, default_value_t = Compression::Zstd)]
+        compression: Compression,
+        /// Private key the package digest is signed with, overrides the config file
+        #[arg(long, global = true, env = "NOCTI_SIGNING_KEY")]
+        signing_key: Option<PathBuf>,
+        /// Only build and print the package digest, without pushing or deploying
+        #[arg(long, global = true)]
+        dry_run: bool,
+    },
+    /// Manage API keys
+    Key {
+        #[command(subcommand)]
+        command: key::KeyCommand,
+    },
+    /// Manage namespaces and their quotas
+    Namespace {
+        #[command(subcommand)]
+        command: namespace::NamespaceCommand,
+    },
+    /// Inspect and clean up stored packages
+    Registry {
+        #[command(subcommand)]
+        command: registry::RegistryCommand,
+    },
+    /// Create and inspect keys for signing packages
+    SigningKey {
+        #[command(subcommand)]
+        command: signing_key::SigningKeyCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PushAll {
+    /// Build and push every function of a workspace, then deploy them together
+    All {
+        /// Workspace root, searched for `Nocti.toml` files unless its own
+        /// `Nocti.toml` lists the members
+        #[arg(default_value = ".")]
+        root: PathBuf,
+        /// Functions built and pushed at the same time, defaults to the CPU count
+        #[arg(short, long)]
+        jobs: Option<usize>,
     },
 }
 
@@ -39,15 +222,139 @@ pub async fn run() -> Result<()> {
 
     setup_tracing(cli.verbose)?;
 
+    let config = CliConfig::load(cli.profile.as_deref())?;
+    let default_namespace = config.namespace().map(str::to_string);
+    let namespace = cli.namespace.clone().or(default_namespace.clone());
+    let default_signing_key = config.signing_key().map(PathBuf::from);
+
+    let connector = config.connector(Overrides {
+        api_key: cli.api_key,
+        tls_ca: cli.tls_ca,
+        tls_cert: cli.tls_cert,
+        tls_key: cli.tls_key,
+        registry_url: cli.registry_url,
+        control_plane_url: cli.control_plane_url,
+        worker_url: cli.worker_url,
+    })?;
+
+    let output = cli.output;
     match cli.command {
+        Command::Invoke {
+            name,
+            body,
+            file,
+            metadata,
+            raw,
+        } => {
+            let payload = match file {
+                Some(path) => invoke::Payload::File(path),
+                None => invoke::Payload::Inline(body.unwrap_or_default()),
+            };
+            invoke::run(name, payload, metadata, raw, namespace, output, &connector).await?
+        }
+        Command::Run {
+            path,
+            body,
+            file,
+            metadata,
+            raw,
+        } => {
+            let payload = match file {
+                Some(path) => invoke::Payload::File(path),
+                None => invoke::Payload::Inline(body.unwrap_or_default()),
+            };
+            run::run(&path, payload, metadata, raw, output).await?
+        }
+        Command::Dev {
+            path,
+            alias,
+            request,
+            metadata,
+            raw,
+            compression,
+            signing_key,
+        } => {
+            let signing_key = signing_key
+                .or(default_signing_key)
+                .map(|path| signing_key::load(&path))
+                .transpose()?;
+            let options = dev::DevOptions {
+                alias,
+                request: request.map(invoke::Payload::File),
+                metadata,
+                raw,
+                output,
+                compression,
+                signing_key,
+            };
+            dev::run(&path, options, cli.namespace, default_namespace, &connector).await?
+        }
+        Command::Init {
+            name,
+            template,
+            path,
+            sdk_path,
+        } => init::run(&name, template, path, sdk_path, output)?,
         Command::Trigger {
             action,
             payload,
             metadata,
-        } => trigger::run(action, payload, metadata).await?,
-        Command::Push { path } => {
-            push::run(&path).await?;
+        } => {
+            let payload = invoke::Payload::Inline(payload);
+            invoke::run(
+                action, payload, metadata, false, namespace, output, &connector,
+            )
+            .await?
+        }
+        Command::List { json } => {
+            let output = if json { Output::Json } else { output };
+            list::run(namespace, output, &connector).await?
+        }
+        Command::Push {
+            all,
+            path,
+            compression,
+            signing_key,
+            dry_run,
+        } => {
+            let signing_key = signing_key
+                .or(default_signing_key)
+                .map(|path| signing_key::load(&path))
+                .transpose()?;
+            let options = push::PushOptions {
+                compression,
+                signing_key,
+                output,
+                dry_run,
+            };
+            if let Some(PushAll::All { root, jobs }) = all {
+                let jobs = jobs.unwrap_or_else(|| {
+                    std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+                });
+                push::all::run(
+                    &root,
+                    jobs,
+                    options,
+                    cli.namespace,
+                    default_namespace,
+                    &connector,
+                )
+                .await?;
+                return Ok(());
+            }
+            push::run(
+                &path.unwrap_or_default(),
+                options,
+                cli.namespace,
+                default_namespace,
+                &connector,
+            )
+            .await?;
         }
+        Command::Key { command } => key::run(command, namespace, output, &connector).await?,
+        Command::Namespace { command } => namespace::run(command, output, &connector).await?,
+        Command::Registry { command } => registry::run(command, output, &connector).await?,
+        Command::SigningKey { command } => signing_key::run(command, output)?,
     }
 
     Ok(())
@@ -66,6 +373,7 @@ fn setup_tracing(verbosity: u8) -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter(env_filter)
         .with_target(false)
+        .with_writer(std::io::stderr)
         .init();
 
     Ok(())
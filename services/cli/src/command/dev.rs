@@ -0,0 +1,266 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use package::{Compression, SigningKey};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use super::invoke::{Payload, execute, parse_metadata, print_outcome};
+use super::push::{Function, build, deploy, is_skipped, push_package};
+use crate::{config::Connector, output::Output};
+
+/// Changes arriving this close together are handled by one rebuild
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+type Changes = mpsc::UnboundedReceiver<notify::Result<Event>>;
+
+/// Settings of a `dev` session
+pub struct DevOptions {
+    /// Name the function is deployed as, `<name>-dev` by default
+    pub alias: Option<String>,
+    /// Request sent to the function after every deploy
+    pub request: Option<Payload>,
+    pub metadata: Vec<String>,
+    pub raw: bool,
+    pub output: Output,
+    pub compression: Compression,
+    pub signing_key: Option<SigningKey>,
+}
+
+/// A request replayed after every deploy
+struct Replay {
+    body: Vec<u8>,
+    metadata: HashMap<String, String>,
+}
+
+/// Build, push and deploy the function at `path` under its dev alias, then do it
+/// again every time a file in the project changes
+pub async fn run(
+    path: &str,
+    options: DevOptions,
+    namespace: Option<String>,
+    default_namespace: Option<String>,
+    connector: &Connector,
+) -> Result<()> {
+    // Events carry absolute paths, which are compared against this.
+    let project_path =
+        &std::fs::canonicalize(path).with_context(|| format!("Failed to resolve {:?}", path))?;
+    // Loaded once up front so a broken project fails at once.
+    Function::load(project_path)?;
+
+    let replay = match options.request {
+        Some(payload) => Some(Replay {
+            body: payload.read().await?,
+            metadata: parse_metadata(options.metadata)?,
+        }),
+        None => None,
+    };
+
+    let (sender, mut changes) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = sender.send(event);
+    })
+    .context("Failed to create file watcher")?;
+    watcher
+        .watch(project_path, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {:?}", project_path))?;
+    info!(
+        "Watching {:?} for changes, press Ctrl-C to stop",
+        project_path
+    );
+
+    let session = Session {
+        alias: options.alias.as_deref(),
+        replay: replay.as_ref(),
+        raw: options.raw,
+        output: options.output,
+        compression: options.compression,
+        signing_key: options.signing_key.as_ref(),
+        namespace: namespace.as_deref(),
+        default_namespace: default_namespace.as_deref(),
+        connector,
+    };
+    loop {
+        // A failed cycle is reported and the next change tries again.
+        if let Err(e) = session.cycle(project_path).await {
+            error!("{:#}", e);
+        }
+
+        // Sources saved while the cycle ran start the next one at once. Cargo only
+        // writes `Cargo.lock` when it changes, so a build starts itself again at
+        // most once.
+        if !has_pending_change(project_path, &mut changes) {
+            wait_for_change(project_path, &mut changes).await?;
+        }
+        settle(&mut changes).await;
+    }
+}
+
+struct Session<'a> {
+    alias: Option<&'a str>,
+    replay: Option<&'a Replay>,
+    raw: bool,
+    output: Output,
+    compression: Compression,
+    signing_key: Option<&'a SigningKey>,
+    namespace: Option<&'a str>,
+    default_namespace: Option<&'a str>,
+    connector: &'a Connector,
+}
+
+impl Session<'_> {
+    async fn cycle(&self, project_path: &Path) -> Result<()> {
+        // Reloaded every time, `Nocti.toml` may be what changed.
+        let function = Function::load(project_path)?;
+        let alias = match self.alias {
+            Some(alias) => alias.to_string(),
+            None => format!("{}-dev", function.name()),
+        };
+        let namespace = function.namespace(self.namespace, self.default_namespace);
+
+        let archive = build(&function).await?;
+        let digest = push_package(
+            &function,
+            &archive,
+            self.compression,
+            self.signing_key,
+            self.connector,
+        )
+        .await?;
+        deploy(
+            &function,
+            &alias,
+            digest.clone(),
+            namespace.clone(),
+            self.connector,
+        )
+        .await?;
+        info!("'{}' now runs {}", alias, digest);
+
+        if let Some(replay) = self.replay {
+            let outcome = execute(
+                &alias,
+                replay.body.clone(),
+                replay.metadata.clone(),
+                namespace,
+                self.connector,
+            )
+            .await?;
+            print_outcome(&alias, outcome, self.raw, self.output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a file of the project changed since the watcher was last read, without
+/// waiting. Changes that are not relevant, such as build output, are dropped.
+fn has_pending_change(project_path: &Path, changes: &mut Changes) -> bool {
+    let mut changed = false;
+    while let Ok(event) = changes.try_recv() {
+        changed |= is_relevant(project_path, event);
+    }
+    changed
+}
+
+/// Wait until a file of the project is created, changed or removed
+async fn wait_for_change(project_path: &Path, changes: &mut Changes) -> Result<()> {
+    loop {
+        let event = changes.recv().await.context("File watcher stopped")?;
+        if is_relevant(project_path, event) {
+            return Ok(());
+        }
+    }
+}
+
+/// Swallow the rest of a burst of changes, the rebuild that follows covers them
+async fn settle(changes: &mut Changes) {
+    tokio::time::sleep(DEBOUNCE).await;
+    while changes.try_recv().is_ok() {}
+    info!("Change detected, rebuilding");
+}
+
+fn is_relevant(project_path: &Path, event: notify::Result<Event>) -> bool {
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            warn!("File watcher error: {}", e);
+            return false;
+        }
+    };
+    if !matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) {
+        return false;
+    }
+
+    // Build output and hidden files change on every build and are not sources.
+    let relevant = event.paths.iter().any(|path| {
+        !path
+            .strip_prefix(project_path)
+            .unwrap_or(path)
+            .components()
+            .any(|component| is_skipped(&component.as_os_str().to_string_lossy()))
+    });
+    if relevant {
+        debug!("Changed: {:?}", event.paths);
+    }
+    relevant
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, CreateKind, DataChange, ModifyKind};
+
+    use super::*;
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(Path::new("/project").join(path)))
+    }
+
+    fn modified(path: &str) -> notify::Result<Event> {
+        event(
+            EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            path,
+        )
+    }
+
+    #[test]
+    fn test_is_relevant() {
+        let project = Path::new("/project");
+        assert!(is_relevant(project, modified("src/main.rs")));
+        assert!(is_relevant(project, modified("Nocti.toml")));
+        assert!(is_relevant(
+            project,
+            event(EventKind::Create(CreateKind::File), "src/lib.rs")
+        ));
+
+        assert!(!is_relevant(project, modified("target/debug/bootstrap")));
+        assert!(!is_relevant(project, modified(".git/index")));
+        assert!(!is_relevant(project, modified("src/.main.rs.swp")));
+        assert!(!is_relevant(
+            project,
+            event(EventKind::Access(AccessKind::Read), "src/main.rs")
+        ));
+        assert!(!is_relevant(
+            project,
+            Err(notify::Error::generic("watch limit reached"))
+        ));
+    }
+
+    #[test]
+    fn test_keeps_changes_made_during_a_build() {
+        let project = Path::new("/project");
+        let (sender, mut changes) = mpsc::unbounded_channel();
+
+        sender.send(modified("target/debug/bootstrap")).unwrap();
+        assert!(!has_pending_change(project, &mut changes));
+
+        sender.send(modified("target/debug/bootstrap")).unwrap();
+        sender.send(modified("src/main.rs")).unwrap();
+        sender.send(modified("target/debug/deps")).unwrap();
+        assert!(has_pending_change(project, &mut changes));
+        assert!(!has_pending_change(project, &mut changes));
+    }
+}
@@ -0,0 +1,183 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde_json::json;
+use tracing::info;
+
+use crate::{error::BadInput, output::Output};
+
+/// Where a project gets the SDK from unless `--sdk-path` is given
+const SDK_GIT: &str = "https://github.com/ow1lab/NoctiForge";
+
+/// Build type of the generated `Nocti.toml`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Template {
+    /// Built by the CLI with cargo
+    Rust,
+    /// Built by a shell script in `Nocti.toml`
+    Custom,
+}
+
+impl Template {
+    fn as_str(self) -> &'static str {
+        match self {
+            Template::Rust => "rust",
+            Template::Custom => "custom",
+        }
+    }
+}
+
+/// File path in the project and its template, which are embedded so `init` works
+/// offline
+const COMMON_FILES: &[(&str, &str)] = &[
+    (
+        "Cargo.toml",
+        include_str!("../../templates/rust/Cargo.toml.tmpl"),
+    ),
+    (
+        "src/main.rs",
+        include_str!("../../templates/rust/src/main.rs"),
+    ),
+    (".gitignore", include_str!("../../templates/rust/gitignore")),
+    (
+        "request.json",
+        include_str!("../../templates/rust/request.json"),
+    ),
+];
+const RUST_NOCTI_TOML: &str = include_str!("../../templates/rust/Nocti.toml");
+const CUSTOM_NOCTI_TOML: &str = include_str!("../../templates/custom/Nocti.toml");
+
+/// Create a function called `name` in `path`, `./<name>` by default
+pub fn run(
+    name: &str,
+    template: Template,
+    path: Option<PathBuf>,
+    sdk_path: Option<PathBuf>,
+    output: Output,
+) -> Result<()> {
+    validate_name(name)?;
+    let path = path.unwrap_or_else(|| PathBuf::from(name));
+    if path.exists() && path.read_dir()?.next().is_some() {
+        bail!(BadInput(format!(
+            "{:?} already exists and is not empty",
+            path
+        )));
+    }
+
+    let sdk = match sdk_path {
+        Some(sdk_path) => {
+            // Made absolute, the project does not live next to the current directory.
+            let sdk_path = std::fs::canonicalize(&sdk_path)
+                .with_context(|| BadInput(format!("SDK not found at {:?}", sdk_path)))?;
+            format!("sdk = {{ path = {:?} }}", sdk_path.display().to_string())
+        }
+        None => format!("sdk = {{ git = \"{}\" }}", SDK_GIT),
+    };
+
+    for (file, content) in render(name, template, &sdk) {
+        write(&path.join(file), &content)?;
+    }
+
+    info!("Created {:?} from the {:?} template", path, template);
+    info!(
+        "Try it with: noctiForge run {} --file {}",
+        path.display(),
+        path.join("request.json").display()
+    );
+    output.print(
+        &json!({ "name": name, "path": path, "template": template.as_str() }),
+        || println!("{}", path.display()),
+    )
+}
+
+/// Function and crate names are kept to what both accept
+fn validate_name(name: &str) -> Result<()> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!(BadInput(format!(
+            "invalid function name '{}', use letters, digits, '-' and '_' and start with a letter",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Every file of the project with its content
+fn render(name: &str, template: Template, sdk: &str) -> Vec<(&'static str, String)> {
+    let nocti_toml = match template {
+        Template::Rust => RUST_NOCTI_TOML,
+        Template::Custom => CUSTOM_NOCTI_TOML,
+    };
+    COMMON_FILES
+        .iter()
+        .copied()
+        .chain([("Nocti.toml", nocti_toml)])
+        .map(|(file, content)| {
+            let content = content.replace("{{name}}", name).replace("{{sdk}}", sdk);
+            (file, content)
+        })
+        .collect()
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {:?}", parent))?;
+    }
+    std::fs::write(path, content).with_context(|| format!("Failed to write {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("hello-world_2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("2fast").is_err());
+        assert!(validate_name("team/echo").is_err());
+    }
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        let sdk = "sdk = { path = \"/sdk\" }";
+        for template in [Template::Rust, Template::Custom] {
+            let files = render("greeter", template, sdk);
+            assert!(files.iter().all(|(_, content)| !content.contains("{{")));
+
+            let nocti_toml = &files
+                .iter()
+                .find(|(file, _)| *file == "Nocti.toml")
+                .unwrap()
+                .1;
+            assert!(nocti_toml.contains("name = \"greeter\""));
+            let cargo_toml = &files
+                .iter()
+                .find(|(file, _)| *file == "Cargo.toml")
+                .unwrap()
+                .1;
+            assert!(cargo_toml.contains(sdk));
+        }
+    }
+
+    #[test]
+    fn test_nocti_toml_has_build_type() {
+        let build_type = |template| {
+            let files = render("greeter", template, "");
+            let nocti_toml = &files
+                .iter()
+                .find(|(file, _)| *file == "Nocti.toml")
+                .unwrap()
+                .1;
+            let value: toml::Value = toml::from_str(nocti_toml).unwrap();
+            value["build"]["type"].as_str().unwrap().to_string()
+        };
+        assert_eq!(build_type(Template::Rust), "rust");
+        assert_eq!(build_type(Template::Custom), "custom");
+    }
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result, bail};
+use proto::api::worker::worker_service_client::WorkerServiceClient;
+use proto::api::worker::{ExecuteRequest, ProblemDetails, execute_response};
+use serde_json::{Value, json};
+use tokio::io::AsyncReadExt;
+use tracing::{debug, info};
+
+use crate::{
+    config::Connector,
+    error::{BadInput, ProblemOutcome},
+    output::Output,
+};
+
+/// Where the request body comes from
+#[derive(Debug)]
+pub enum Payload {
+    Inline(String),
+    /// A file, or stdin for `-`
+    File(String),
+}
+
+impl Payload {
+    pub(super) async fn read(self) -> Result<Vec<u8>> {
+        match self {
+            Payload::Inline(body) => Ok(body.into_bytes()),
+            Payload::File(path) if path == "-" => {
+                let mut body = Vec::new();
+                tokio::io::stdin()
+                    .read_to_end(&mut body)
+                    .await
+                    .context(BadInput("Failed to read payload from stdin".to_string()))?;
+                Ok(body)
+            }
+            Payload::File(path) => tokio::fs::read(Path::new(&path))
+                .await
+                .with_context(|| BadInput(format!("Failed to read payload from {}", path))),
+        }
+    }
+}
+
+/// Run the function `key` on the worker and print its output. JSON output is
+/// pretty-printed unless `raw` is set, a problem is printed and returned as error.
+pub async fn run(
+    key: String,
+    payload: Payload,
+    metadata: Vec<String>,
+    raw: bool,
+    namespace: Option<String>,
+    output: Output,
+    connector: &Connector,
+) -> Result<()> {
+    let body = payload.read().await?;
+    let metadata = parse_metadata(metadata)?;
+    let outcome = execute(
+        &key,
+        body,
+        metadata,
+        namespace.unwrap_or_default(),
+        connector,
+    )
+    .await?;
+    print_outcome(&key, outcome, raw, output)
+}
+
+/// Run the function `key` on the worker and return its outcome
+pub(super) async fn execute(
+    key: &str,
+    body: Vec<u8>,
+    metadata: HashMap<String, String>,
+    namespace: String,
+    connector: &Connector,
+) -> Result<Option<execute_response::Outcome>> {
+    info!("Invoking function: '{}'", key);
+    debug!("Request body: {} bytes", body.len());
+
+    let url = connector.worker_url();
+    debug!("Connecting to WorkerService at {}", url);
+    let mut client = WorkerServiceClient::new(
+        connector
+            .connect(url)
+            .await
+            .with_context(|| format!("Failed to connect to WorkerService at {}", url))?,
+    );
+
+    let response = client
+        .execute(tonic::Request::new(ExecuteRequest {
+            action: key.to_string(),
+            body,
+            metadata,
+            namespace,
+        }))
+        .await
+        .context("Worker execute call failed")?
+        .into_inner();
+    debug!("Received response from worker");
+    Ok(response.outcome)
+}
+
+/// Print a successful output to stdout, or the problem and return it as error. As
+/// text the problem goes to stderr, as JSON or YAML both go to stdout.
+pub(super) fn print_outcome(
+    key: &str,
+    outcome: Option<execute_response::Outcome>,
+    raw: bool,
+    output: Output,
+) -> Result<()> {
+    match outcome {
+        Some(execute_response::Outcome::Success(success)) => output.print(
+            &json!({ "name": key, "output": body_json(&success.body) }),
+            || println!("{}", format_body(&success.body, raw)),
+        ),
+        Some(execute_response::Outcome::Problem(problem)) => {
+            let value = problem_json(&problem);
+            match output {
+                Output::Text => eprintln!("{}", serde_json::to_string_pretty(&value)?),
+                _ => output.print(&json!({ "name": key, "problem": value }), || {})?,
+            }
+            Err(ProblemOutcome {
+                name: key.to_string(),
+                r#type: problem.r#type,
+            }
+            .into())
+        }
+        None => bail!("Worker returned no outcome for '{}'", key),
+    }
+}
+
+pub(super) fn parse_metadata(metadata: Vec<String>) -> Result<HashMap<String, String>> {
+    metadata
+        .into_iter()
+        .map(|meta| {
+            meta.split_once('=')
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .ok_or_else(|| BadInput(format!("Invalid metadata entry: {}", meta)).into())
+        })
+        .collect()
+}
+
+/// Pretty-print JSON bodies, anything else is printed as is
+pub(super) fn format_body(body: &[u8], raw: bool) -> String {
+    if !raw && let Ok(value) = serde_json::from_slice::<Value>(body) {
+        return serde_json::to_string_pretty(&value).unwrap_or_default();
+    }
+    String::from_utf8_lossy(body).into_owned()
+}
+
+/// A JSON body as is, anything else as string
+fn body_json(body: &[u8]) -> Value {
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| Value::from(String::from_utf8_lossy(body).into_owned()))
+}
+
+/// Problem details in their RFC 9457 JSON form
+pub(super) fn problem_json(problem: &ProblemDetails) -> Value {
+    let mut value = json!({
+        "type": problem.r#type,
+        "detail": problem.detail,
+    });
+    if !problem.instance.is_empty() {
+        value["instance"] = json!(problem.instance);
+    }
+    for (key, extension) in &problem.extensions {
+        value[key] = json!(extension);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata = parse_metadata(vec!["a=1".to_string(), "b=x=y".to_string()]).unwrap();
+        assert_eq!(metadata["a"], "1");
+        assert_eq!(metadata["b"], "x=y");
+        assert!(parse_metadata(vec!["novalue".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_format_body() {
+        assert_eq!(format_body(br#"{"a":1}"#, false), "{\n  \"a\": 1\n}");
+        assert_eq!(format_body(br#"{"a":1}"#, true), r#"{"a":1}"#);
+        assert_eq!(format_body(b"plain text", false), "plain text");
+    }
+
+    #[test]
+    fn test_body_json() {
+        assert_eq!(body_json(br#"{"a":1}"#), json!({ "a": 1 }));
+        assert_eq!(body_json(b"plain text"), json!("plain text"));
+    }
+
+    #[test]
+    fn test_problem_is_returned_as_error() {
+        let outcome = execute_response::Outcome::Problem(ProblemDetails {
+            r#type: "urn:test:problem".to_string(),
+            ..Default::default()
+        });
+        let err = print_outcome("echo", Some(outcome), false, Output::Json).unwrap_err();
+        assert!(err.downcast_ref::<ProblemOutcome>().is_some());
+    }
+}
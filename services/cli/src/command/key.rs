@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use proto::api::controlplane::{
+    CreateApiKeyRequest, ListApiKeysRequest, RevokeApiKeyRequest,
+    control_plane_service_client::ControlPlaneServiceClient,
+};
+use serde_json::json;
+use tonic::Request;
+use tracing::{debug, info};
+
+use crate::{config::Connector, output::Output};
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommand {
+    /// Create a new API key, the key is only shown once
+    Create {
+        name: String,
+        /// Scope granted to the key: push, deploy, invoke or admin
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<String>,
+        /// Function name or prefix ending in '*' the key is limited to, defaults to all
+        #[arg(long = "resource")]
+        resources: Vec<String>,
+    },
+    /// List the existing API keys
+    List,
+    /// Revoke an API key of the namespace by name
+    Revoke { name: String },
+}
+
+/// Keys are created, listed and revoked in `namespace`, pass `*` for keys valid
+/// everywhere
+pub async fn run(
+    command: KeyCommand,
+    namespace: Option<String>,
+    output: Output,
+    connector: &Connector,
+) -> Result<()> {
+    let namespace = namespace.unwrap_or_default();
+    let url = connector.control_plane_url();
+    debug!("Connecting to ControlPlaneService at {}", url);
+    let mut client = ControlPlaneServiceClient::new(
+        connector
+            .connect(url)
+            .await
+            .with_context(|| format!("Failed to connect to ControlPlaneService at {}", url))?,
+    );
+
+    match command {
+        KeyCommand::Create {
+            name,
+            scopes,
+            resources,
+        } => {
+            let response = client
+                .create_api_key(Request::new(CreateApiKeyRequest {
+                    name,
+                    scopes,
+                    resources,
+                    namespace,
+                }))
+                .await
+                .context("Failed to create API key")?
+                .into_inner();
+
+            info!("Created API key '{}'", response.name);
+            output.print(
+                &json!({ "name": response.name, "key": response.key }),
+                || println!("{}", response.key),
+            )?;
+        }
+        KeyCommand::List => {
+            let response = client
+                .list_api_keys(Request::new(ListApiKeysRequest { namespace }))
+                .await
+                .context("Failed to list API keys")?
+                .into_inner();
+
+            let value: Vec<_> = response
+                .keys
+                .iter()
+                .map(|key| {
+                    json!({
+                        "namespace": key.namespace,
+                        "name": key.name,
+                        "scopes": key.scopes,
+                        "resources": key.resources,
+                    })
+                })
+                .collect();
+            output.print(&value, || {
+                for key in &response.keys {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        key.namespace,
+                        key.name,
+                        key.scopes.join(","),
+                        key.resources.join(",")
+                    );
+                }
+            })?;
+        }
+        KeyCommand::Revoke { name } => {
+            client
+                .revoke_api_key(Request::new(RevokeApiKeyRequest {
+                    name: name.clone(),
+                    namespace,
+                }))
+                .await
+                .context("Failed to revoke API key")?;
+
+            info!("Revoked API key '{}'", name);
+            output.print(&json!({ "name": name, "revoked": true }), || {})?;
+        }
+    }
+
+    Ok(())
+}
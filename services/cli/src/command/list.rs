@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use proto::api::controlplane::{
+    ListFunctionsRequest, control_plane_service_client::ControlPlaneServiceClient,
+};
+use serde_json::json;
+use tonic::Request;
+use tracing::debug;
+
+use crate::{config::Connector, output::Output};
+
+/// Print the functions of `namespace`, pass `*` for every namespace
+pub async fn run(namespace: Option<String>, output: Output, connector: &Connector) -> Result<()> {
+    let url = connector.control_plane_url();
+    debug!("Connecting to ControlPlaneService at {}", url);
+    let mut client = ControlPlaneServiceClient::new(
+        connector
+            .connect(url)
+            .await
+            .with_context(|| format!("Failed to connect to ControlPlaneService at {}", url))?,
+    );
+
+    let functions = client
+        .list_functions(Request::new(ListFunctionsRequest {
+            namespace: namespace.unwrap_or_default(),
+        }))
+        .await
+        .context("Failed to list functions")?
+        .into_inner()
+        .functions;
+
+    let value: Vec<_> = functions
+        .iter()
+        .map(|function| {
+            json!({
+                "namespace": function.namespace,
+                "name": function.name,
+                "digest": function.digest,
+                "created_at": function.created_at,
+                "updated_at": function.updated_at,
+            })
+        })
+        .collect();
+    output.print(&value, || {
+        for function in &functions {
+            println!(
+                "{}\t{}\t{}\t{}",
+                function.namespace, function.name, function.digest, function.updated_at
+            );
+        }
+    })
+}
@@ -0,0 +1,117 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use proto::api::controlplane::{
+    CreateNamespaceRequest, DeleteNamespaceRequest, ListNamespacesRequest,
+    SetNamespaceQuotaRequest, control_plane_service_client::ControlPlaneServiceClient,
+};
+use serde_json::json;
+use tonic::Request;
+use tracing::{debug, info};
+
+use crate::{config::Connector, output::Output};
+
+#[derive(Subcommand, Debug)]
+pub enum NamespaceCommand {
+    /// Create a namespace
+    Create {
+        name: String,
+        /// Maximum number of functions, 0 for unlimited
+        #[arg(long, default_value_t = 0)]
+        max_functions: u32,
+    },
+    /// List namespaces with their function count and quota
+    List,
+    /// Change the function quota of a namespace, 0 for unlimited
+    SetQuota { name: String, max_functions: u32 },
+    /// Delete a namespace without functions or API keys
+    Delete { name: String },
+}
+
+pub async fn run(command: NamespaceCommand, output: Output, connector: &Connector) -> Result<()> {
+    let url = connector.control_plane_url();
+    debug!("Connecting to ControlPlaneService at {}", url);
+    let mut client = ControlPlaneServiceClient::new(
+        connector
+            .connect(url)
+            .await
+            .with_context(|| format!("Failed to connect to ControlPlaneService at {}", url))?,
+    );
+
+    match command {
+        NamespaceCommand::Create {
+            name,
+            max_functions,
+        } => {
+            client
+                .create_namespace(Request::new(CreateNamespaceRequest {
+                    name: name.clone(),
+                    max_functions,
+                }))
+                .await
+                .context("Failed to create namespace")?;
+
+            info!("Created namespace '{}'", name);
+            output.print(
+                &json!({ "name": name, "max_functions": max_functions }),
+                || {},
+            )?;
+        }
+        NamespaceCommand::List => {
+            let response = client
+                .list_namespaces(Request::new(ListNamespacesRequest {}))
+                .await
+                .context("Failed to list namespaces")?
+                .into_inner();
+
+            let value: Vec<_> = response
+                .namespaces
+                .iter()
+                .map(|namespace| {
+                    json!({
+                        "name": namespace.name,
+                        "functions": namespace.functions,
+                        "max_functions": namespace.max_functions,
+                    })
+                })
+                .collect();
+            output.print(&value, || {
+                for namespace in &response.namespaces {
+                    let quota = match namespace.max_functions {
+                        0 => "unlimited".to_string(),
+                        max => max.to_string(),
+                    };
+                    println!("{}\t{}/{}", namespace.name, namespace.functions, quota);
+                }
+            })?;
+        }
+        NamespaceCommand::SetQuota {
+            name,
+            max_functions,
+        } => {
+            client
+                .set_namespace_quota(Request::new(SetNamespaceQuotaRequest {
+                    name: name.clone(),
+                    max_functions,
+                }))
+                .await
+                .context("Failed to set namespace quota")?;
+
+            info!("Set quota of namespace '{}' to {}", name, max_functions);
+            output.print(
+                &json!({ "name": name, "max_functions": max_functions }),
+                || {},
+            )?;
+        }
+        NamespaceCommand::Delete { name } => {
+            client
+                .delete_namespace(Request::new(DeleteNamespaceRequest { name: name.clone() }))
+                .await
+                .context("Failed to delete namespace")?;
+
+            info!("Deleted namespace '{}'", name);
+            output.print(&json!({ "name": name, "deleted": true }), || {})?;
+        }
+    }
+
+    Ok(())
+}
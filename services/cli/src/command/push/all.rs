@@ -0,0 +1,375 @@
+use std::{
+    collections::HashSet,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tempfile::NamedTempFile;
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::{debug, info};
+
+use super::{
+    CONFIG_FILE, Function, PushOptions, build, deploy, is_skipped, local_digest, push_package,
+};
+use crate::{config::Connector, error::BadInput, output::Output};
+
+/// A `Nocti.toml` at the root may list the functions instead of them being searched
+#[derive(Debug, Deserialize)]
+struct RootManifest {
+    workspace: Option<Workspace>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Workspace {
+    /// Function directories, relative to the root
+    members: Vec<PathBuf>,
+}
+
+/// How far a function got
+enum State {
+    Built(NamedTempFile),
+    /// Built during a dry run, with the digest it would be pushed as
+    Digested(String),
+    Pushed(String),
+    Deployed(String),
+    Failed(&'static str, anyhow::Error),
+}
+
+/// Build and push every function under `root`, at most `jobs` at a time, and only
+/// deploy them once all of them made it to the registry
+pub async fn run(
+    root: &Path,
+    jobs: usize,
+    options: PushOptions,
+    namespace: Option<String>,
+    default_namespace: Option<String>,
+    connector: &Connector,
+) -> Result<()> {
+    let PushOptions {
+        compression,
+        signing_key,
+        output,
+        dry_run,
+    } = options;
+    let functions = discover(root)?
+        .iter()
+        .map(|path| Function::load(path).with_context(|| format!("Failed to load {:?}", path)))
+        .map(|function| function.map(Arc::new))
+        .collect::<Result<Vec<_>>>()?;
+    if functions.is_empty() {
+        bail!(BadInput(format!(
+            "no '{}' found under {:?}",
+            CONFIG_FILE, root
+        )));
+    }
+
+    let namespaces: Vec<String> = functions
+        .iter()
+        .map(|function| function.namespace(namespace.as_deref(), default_namespace.as_deref()))
+        .collect();
+    let mut seen = HashSet::new();
+    for (function, namespace) in functions.iter().zip(&namespaces) {
+        if !seen.insert((namespace, function.name())) {
+            bail!(BadInput(format!(
+                "function '{}' is defined more than once in {:?}",
+                function.name(),
+                root
+            )));
+        }
+    }
+    info!(
+        "Pushing {} functions with {} jobs: {}",
+        functions.len(),
+        jobs,
+        functions
+            .iter()
+            .map(|function| function.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let mut states: Vec<State> = in_parallel(jobs, functions.clone(), |function| async move {
+        build(&function).await
+    })
+    .await?
+    .into_iter()
+    .map(|result| match result {
+        Ok(archive) => State::Built(archive),
+        Err(e) => State::Failed("build", e),
+    })
+    .collect();
+    if has_failed(&states) {
+        print_summary(&functions, &states, output)?;
+        bail!("Not pushing anything, a build failed");
+    }
+    if dry_run {
+        for state in states.iter_mut() {
+            if let State::Built(archive) = state {
+                *state = match local_digest(archive).await {
+                    Ok(digest) => State::Digested(digest.to_string()),
+                    Err(e) => State::Failed("digest", e),
+                };
+            }
+        }
+        print_summary(&functions, &states, output)?;
+        if has_failed(&states) {
+            bail!("Not every digest could be computed");
+        }
+        return Ok(());
+    }
+
+    let signing_key = signing_key.map(Arc::new);
+    let built: Vec<_> = functions
+        .iter()
+        .cloned()
+        .zip(states.drain(..))
+        .map(|(function, state)| match state {
+            State::Built(archive) => (function, archive),
+            _ => unreachable!("every function was built"),
+        })
+        .collect();
+    states = in_parallel(jobs, built, |(function, archive)| {
+        let signing_key = signing_key.clone();
+        let connector = connector.clone();
+        async move {
+            push_package(
+                &function,
+                &archive,
+                compression,
+                signing_key.as_deref(),
+                &connector,
+            )
+            .await
+        }
+    })
+    .await?
+    .into_iter()
+    .map(|result| match result {
+        Ok(digest) => State::Pushed(digest),
+        Err(e) => State::Failed("push", e),
+    })
+    .collect();
+    if has_failed(&states) {
+        print_summary(&functions, &states, output)?;
+        bail!("Not deploying anything, a push failed");
+    }
+
+    // The mappings are updated last, so a failure above leaves every function as it was.
+    for ((function, namespace), state) in functions.iter().zip(namespaces).zip(states.iter_mut()) {
+        let State::Pushed(digest) = state else {
+            continue;
+        };
+        let digest = std::mem::take(digest);
+        *state = match deploy(
+            function,
+            function.name(),
+            digest.clone(),
+            namespace,
+            connector,
+        )
+        .await
+        {
+            Ok(()) => State::Deployed(digest),
+            Err(e) => State::Failed("deploy", e),
+        };
+    }
+
+    print_summary(&functions, &states, output)?;
+    if has_failed(&states) {
+        bail!("Not every function was deployed");
+    }
+    Ok(())
+}
+
+/// Directories of the functions under `root`, the members of a workspace manifest or
+/// every directory holding a `Nocti.toml`
+fn discover(root: &Path) -> Result<Vec<PathBuf>> {
+    let manifest_path = root.join(CONFIG_FILE);
+    if manifest_path.is_file() {
+        let content = std::fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read config file: {:?}", manifest_path))?;
+        let manifest: RootManifest = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {:?} as TOML", manifest_path))?;
+        if let Some(workspace) = manifest.workspace {
+            debug!("Using workspace members from {:?}", manifest_path);
+            return Ok(workspace
+                .members
+                .iter()
+                .map(|member| root.join(member))
+                .collect());
+        }
+    }
+
+    let mut functions = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        if dir.join(CONFIG_FILE).is_file() {
+            functions.push(dir.clone());
+            // Functions are not nested, except in a root that is a function itself.
+            if dir != root {
+                continue;
+            }
+        }
+
+        let entries =
+            std::fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))?;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if is_skipped(&name) {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                stack.push(entry.path());
+            }
+        }
+    }
+
+    functions.sort();
+    debug!("Discovered functions: {:?}", functions);
+    Ok(functions)
+}
+
+/// Run `task` on every item, at most `jobs` at a time, and return the results in the
+/// order of the items
+async fn in_parallel<I, T, F, Fut>(jobs: usize, items: Vec<I>, task: F) -> Result<Vec<Result<T>>>
+where
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    let count = items.len();
+    for (index, item) in items.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        let future = task(item);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            (index, future.await)
+        });
+    }
+
+    let mut results: Vec<Option<Result<T>>> = (0..count).map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = joined.context("Push task panicked")?;
+        results[index] = Some(result);
+    }
+    Ok(results
+        .into_iter()
+        .map(|result| result.expect("every task finished"))
+        .collect())
+}
+
+fn has_failed(states: &[State]) -> bool {
+    states
+        .iter()
+        .any(|state| matches!(state, State::Failed(..)))
+}
+
+fn print_summary(functions: &[Arc<Function>], states: &[State], output: Output) -> Result<()> {
+    let value: Vec<Value> = functions
+        .iter()
+        .zip(states)
+        .map(|(function, state)| match state {
+            State::Deployed(digest) => {
+                json!({ "name": function.name(), "status": "deployed", "digest": digest })
+            }
+            State::Digested(digest) => {
+                json!({ "name": function.name(), "status": "built", "digest": digest })
+            }
+            State::Built(_) | State::Pushed(_) => {
+                json!({ "name": function.name(), "status": "not deployed" })
+            }
+            State::Failed(stage, e) => json!({
+                "name": function.name(),
+                "status": format!("{} failed", stage),
+                "error": format!("{:#}", e),
+            }),
+        })
+        .collect();
+    output.print(&value, || {
+        for (function, state) in functions.iter().zip(states) {
+            match state {
+                State::Deployed(digest) => {
+                    println!("{}\tdeployed\t{}", function.name(), digest)
+                }
+                State::Digested(digest) => println!("{}\tbuilt\t{}", function.name(), digest),
+                State::Built(_) | State::Pushed(_) => {
+                    println!("{}\tnot deployed", function.name())
+                }
+                State::Failed(stage, e) => {
+                    println!("{}\t{} failed\t{:#}", function.name(), stage, e);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn function(dir: &Path, name: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join(CONFIG_FILE),
+            format!(
+                "[project]\nname = \"{}\"\n\n[build]\ntype = \"rust\"\n",
+                name
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_discover_searches_directories() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        function(&root.join("echo"), "echo");
+        function(&root.join("functions/billing"), "billing");
+        function(&root.join("echo/nested"), "nested");
+        function(&root.join("target/copy"), "copy");
+        function(&root.join(".git/copy"), "copy");
+
+        assert_eq!(
+            discover(root).unwrap(),
+            vec![root.join("echo"), root.join("functions/billing")]
+        );
+    }
+
+    #[test]
+    fn test_discover_uses_workspace_members() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        function(&root.join("echo"), "echo");
+        function(&root.join("unlisted"), "unlisted");
+        std::fs::write(
+            root.join(CONFIG_FILE),
+            "[workspace]\nmembers = [\"echo\"]\n",
+        )
+        .unwrap();
+
+        assert_eq!(discover(root).unwrap(), vec![root.join("echo")]);
+    }
+
+    #[tokio::test]
+    async fn test_in_parallel_keeps_order() {
+        let results = in_parallel(2, vec![30u64, 10, 20], |delay| async move {
+            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+            Ok(delay)
+        })
+        .await
+        .unwrap();
+
+        let results: Vec<u64> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(results, vec![30, 10, 20]);
+    }
+}
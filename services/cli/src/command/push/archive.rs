@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use package::{CURRENT_VERSION, Digest, compute_digest};
+use tempfile::NamedTempFile;
+use tokio_tar::HeaderMode;
+use tracing::debug;
+
+use crate::error::BadInput;
+
+/// Pack `dir` into an uncompressed tar archive that only depends on the file names,
+/// contents and executable bits. Entries are sorted and mtimes, owners and other
+/// mode bits are fixed, so the same build output gives the same archive on any host.
+pub(super) async fn pack(dir: &Path) -> Result<NamedTempFile> {
+    let archive = tempfile::Builder::new()
+        .prefix("nocti-package-")
+        .suffix(".tar")
+        .tempfile()
+        .context("Failed to create archive file")?;
+    let mut builder = tokio_tar::Builder::new(tokio::fs::File::from_std(
+        archive.reopen().context("Failed to open archive file")?,
+    ));
+    builder.mode(HeaderMode::Deterministic);
+
+    for path in sorted_entries(dir).await? {
+        let name = Path::new(".").join(path.strip_prefix(dir)?);
+        builder
+            .append_path_with_name(&path, &name)
+            .await
+            .with_context(|| format!("Failed to add {:?} to archive", name))?;
+    }
+    builder
+        .into_inner()
+        .await
+        .context("Failed to finalize archive")?
+        .sync_all()
+        .await
+        .context("Failed to write archive")?;
+
+    Ok(archive)
+}
+
+/// Digest of a packed archive, as the registry will compute it
+pub(super) async fn digest(archive: &Path) -> Result<Digest> {
+    let file = tokio::fs::File::open(archive)
+        .await
+        .context("Failed to open archive")?;
+    compute_digest(
+        tokio_tar::Archive::new(tokio::io::BufReader::new(file)),
+        CURRENT_VERSION,
+    )
+    .await
+    .context("Failed to compute package digest")
+}
+
+/// `dir` and everything below it, each directory followed by its entries in name
+/// order. Links are followed, like before the archive was normalised, and a link
+/// back to a directory it is in is refused.
+async fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+    // Every path comes with the resolved directories it was reached through.
+    let mut stack = vec![(dir.to_path_buf(), Vec::new())];
+    while let Some((path, mut ancestors)) = stack.pop() {
+        let is_dir = tokio::fs::metadata(&path)
+            .await
+            .with_context(|| format!("Failed to read {:?}", path))?
+            .is_dir();
+        entries.push(path.clone());
+        if !is_dir {
+            continue;
+        }
+
+        let resolved = tokio::fs::canonicalize(&path)
+            .await
+            .with_context(|| format!("Failed to resolve {:?}", path))?;
+        if ancestors.contains(&resolved) {
+            bail!(BadInput(format!(
+                "{:?} links back to {:?}, the build output must not contain symlink cycles",
+                path, resolved
+            )));
+        }
+        ancestors.push(resolved);
+
+        let mut children = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(&path)
+            .await
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            children.push(entry.path());
+        }
+        // Reversed, the stack pops them in order.
+        children.sort_by(|a, b| b.cmp(a));
+        stack.extend(children.into_iter().map(|child| (child, ancestors.clone())));
+    }
+    debug!("Packing {} entries", entries.len());
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use tempfile::TempDir;
+    use tokio_stream::StreamExt;
+
+    fn write(path: &Path, content: &str, mode: u32) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pack_is_reproducible() {
+        let first = TempDir::new().unwrap();
+        write(&first.path().join("bootstrap"), "binary", 0o700);
+        write(&first.path().join("assets/b.txt"), "b", 0o600);
+        write(&first.path().join("assets/a.txt"), "a", 0o664);
+
+        // Created in another order, with other modes and a later mtime.
+        let second = TempDir::new().unwrap();
+        write(&second.path().join("assets/a.txt"), "a", 0o644);
+        write(&second.path().join("assets/b.txt"), "b", 0o640);
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        write(&second.path().join("bootstrap"), "binary", 0o755);
+
+        let first = std::fs::read(pack(first.path()).await.unwrap().path()).unwrap();
+        let second = std::fs::read(pack(second.path()).await.unwrap().path()).unwrap();
+        assert!(first == second, "archives differ");
+    }
+
+    #[tokio::test]
+    async fn test_pack_normalises_entries() {
+        let dir = TempDir::new().unwrap();
+        write(&dir.path().join("z"), "z", 0o600);
+        write(&dir.path().join("bootstrap"), "binary", 0o750);
+
+        let archive = pack(dir.path()).await.unwrap();
+        let file = tokio::fs::File::open(archive.path()).await.unwrap();
+        let mut entries = tokio_tar::Archive::new(file).entries().unwrap();
+        let mut seen = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry.unwrap();
+            let header = entry.header();
+            assert_eq!(header.mtime().unwrap(), 0);
+            assert_eq!(header.uid().unwrap(), 0);
+            seen.push((
+                entry.path().unwrap().display().to_string(),
+                header.mode().unwrap(),
+            ));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                ("./".to_string(), 0o755),
+                ("bootstrap".to_string(), 0o755),
+                ("z".to_string(), 0o644),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pack_refuses_symlink_cycles() {
+        let dir = TempDir::new().unwrap();
+        write(&dir.path().join("bootstrap"), "binary", 0o755);
+        write(&dir.path().join("shared/data.txt"), "data", 0o644);
+        // Two links to the same directory are fine, they only repeat its content.
+        std::os::unix::fs::symlink("shared", dir.path().join("also-shared")).unwrap();
+        std::os::unix::fs::symlink("../shared", dir.path().join("shared/self")).unwrap();
+
+        let err = pack(dir.path()).await.unwrap_err();
+        assert!(err.is::<BadInput>(), "{:#}", err);
+
+        std::fs::remove_file(dir.path().join("shared/self")).unwrap();
+        pack(dir.path()).await.unwrap();
+    }
+}
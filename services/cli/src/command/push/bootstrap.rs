@@ -0,0 +1,249 @@
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use goblin::elf::{Elf, header};
+use tracing::debug;
+
+/// Name of the executable the worker starts
+pub(crate) const BOOTSTRAP: &str = "bootstrap";
+/// Where the worker puts the package inside the otherwise empty container
+const APP_ROOT: &str = "/app";
+/// Workers run on this architecture unless the project names another
+pub(super) const DEFAULT_ARCH: &str = "x86_64";
+
+/// Check that the build in `output` produced a `bootstrap` the worker can start on
+/// `arch`: an executable ELF for that architecture that is either static or finds
+/// its interpreter and libraries inside the package
+pub(super) fn check(output: &Path, arch: &str) -> Result<()> {
+    let machine = machine(arch)?;
+    let path = output.join(BOOTSTRAP);
+    let metadata = std::fs::metadata(&path)
+        .map_err(|_| anyhow::anyhow!("the build did not produce a '{}'", BOOTSTRAP))?;
+    if !metadata.is_file() {
+        bail!("'{}' is not a file", BOOTSTRAP);
+    }
+    // Only the owner's execute bit survives packing.
+    if metadata.permissions().mode() & 0o100 == 0 {
+        bail!("'{}' is not executable, run `chmod +x` on it", BOOTSTRAP);
+    }
+
+    let bytes = std::fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    if let Some(script) = bytes.strip_prefix(b"#!") {
+        return check_script(output, script);
+    }
+    let elf = Elf::parse(&bytes)
+        .map_err(|err| anyhow::anyhow!("'{}' is not an ELF executable: {}", BOOTSTRAP, err))?;
+
+    if !matches!(elf.header.e_type, header::ET_EXEC | header::ET_DYN) {
+        bail!("'{}' is an ELF file but not an executable", BOOTSTRAP);
+    }
+    if elf.header.e_machine != machine {
+        bail!(
+            "'{}' is built for {} but workers run {}, change the build target or set \
+            `arch` in the project",
+            BOOTSTRAP,
+            header::machine_to_str(elf.header.e_machine),
+            arch
+        );
+    }
+
+    if let Some(interpreter) = elf.interpreter
+        && package_path(output, interpreter).is_none_or(|path| !path.is_file())
+    {
+        bail!(
+            "'{}' is dynamically linked against {}, which is not in the package. \
+            Link it statically, e.g. with a musl target",
+            BOOTSTRAP,
+            interpreter
+        );
+    }
+
+    let search_dirs: Vec<PathBuf> = elf
+        .runpaths
+        .iter()
+        .chain(&elf.rpaths)
+        .flat_map(|paths| paths.split(':'))
+        .map(|dir| dir.replace("$ORIGIN", APP_ROOT))
+        .filter_map(|dir| package_path(output, &dir))
+        .collect();
+    let missing: Vec<&str> = elf
+        .libraries
+        .iter()
+        .copied()
+        .filter(|library| !search_dirs.iter().any(|dir| dir.join(library).is_file()))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "'{}' needs {}, which the package does not provide. Link it statically or \
+            ship the libraries with a RUNPATH of $ORIGIN",
+            BOOTSTRAP,
+            missing.join(", ")
+        );
+    }
+
+    debug!(
+        "'{}' is a {} executable for {}",
+        BOOTSTRAP,
+        if elf.interpreter.is_some() {
+            "dynamic"
+        } else {
+            "static"
+        },
+        arch
+    );
+    Ok(())
+}
+
+/// A script only runs when its interpreter comes with the package
+fn check_script(output: &Path, script: &[u8]) -> Result<()> {
+    let line = script
+        .split(|&byte| byte == b'\n')
+        .next()
+        .unwrap_or_default();
+    let line = String::from_utf8_lossy(line);
+    let interpreter = line.split_whitespace().next().unwrap_or_default();
+    if package_path(output, interpreter).is_none_or(|path| !path.is_file()) {
+        bail!(
+            "'{}' is a script for {}, which is not in the package",
+            BOOTSTRAP,
+            if interpreter.is_empty() {
+                "no interpreter"
+            } else {
+                interpreter
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Where the container path `path` is in the build output, if it is in the package
+fn package_path(output: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path).strip_prefix(APP_ROOT).ok()?;
+    Some(output.join(relative))
+}
+
+/// Rust target that builds a static binary for `arch`
+pub(super) fn musl_target(arch: &str) -> Result<&'static str> {
+    machine(arch)?;
+    Ok(match arch {
+        "x86_64" => "x86_64-unknown-linux-musl",
+        "aarch64" => "aarch64-unknown-linux-musl",
+        "x86" => "i686-unknown-linux-musl",
+        "arm" => "arm-unknown-linux-musleabihf",
+        _ => "riscv64gc-unknown-linux-musl",
+    })
+}
+
+fn machine(arch: &str) -> Result<u16> {
+    Ok(match arch {
+        "x86_64" => header::EM_X86_64,
+        "aarch64" => header::EM_AARCH64,
+        "x86" => header::EM_386,
+        "arm" => header::EM_ARM,
+        "riscv64" => header::EM_RISCV,
+        _ => bail!(
+            "unknown architecture '{}', use x86_64, aarch64, x86, arm or riscv64",
+            arch
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// The header of a static 64-bit little-endian executable, which is all a
+    /// binary without program headers needs
+    fn static_elf(machine: u16) -> Vec<u8> {
+        let mut elf = vec![0u8; 64];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[4] = 2; // 64-bit
+        elf[5] = 1; // little-endian
+        elf[6] = 1; // ELF version
+        elf[16..18].copy_from_slice(&header::ET_EXEC.to_le_bytes());
+        elf[18..20].copy_from_slice(&machine.to_le_bytes());
+        elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+        elf[52..54].copy_from_slice(&64u16.to_le_bytes()); // header size
+        elf
+    }
+
+    fn bootstrap(dir: &Path, content: &[u8], mode: u32) {
+        let path = dir.join(BOOTSTRAP);
+        std::fs::write(&path, content).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_static_elf() {
+        let dir = TempDir::new().unwrap();
+        bootstrap(dir.path(), &static_elf(header::EM_X86_64), 0o755);
+        check(dir.path(), "x86_64").unwrap();
+
+        let err = check(dir.path(), "aarch64").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("built for X86_64 but workers run aarch64"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_missing_or_not_executable() {
+        let dir = TempDir::new().unwrap();
+        assert!(check(dir.path(), "x86_64").is_err());
+
+        bootstrap(dir.path(), &static_elf(header::EM_X86_64), 0o644);
+        let err = check(dir.path(), "x86_64").unwrap_err();
+        assert!(err.to_string().contains("not executable"), "{}", err);
+    }
+
+    #[test]
+    fn test_script_needs_interpreter_in_package() {
+        let dir = TempDir::new().unwrap();
+        bootstrap(dir.path(), b"#!/bin/sh\necho hi\n", 0o755);
+        assert!(check(dir.path(), "x86_64").is_err());
+
+        bootstrap(dir.path(), b"#!/app/bin/sh\necho hi\n", 0o755);
+        std::fs::create_dir(dir.path().join("bin")).unwrap();
+        std::fs::write(dir.path().join("bin/sh"), "").unwrap();
+        check(dir.path(), "x86_64").unwrap();
+    }
+
+    #[test]
+    fn test_not_elf() {
+        let dir = TempDir::new().unwrap();
+        bootstrap(dir.path(), b"plain text", 0o755);
+        let err = check(dir.path(), "x86_64").unwrap_err();
+        assert!(err.to_string().contains("not an ELF"), "{}", err);
+    }
+
+    #[test]
+    fn test_dynamic_elf_needs_its_interpreter() {
+        // The test binary itself links against the system's libc.
+        let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        if Elf::parse(&exe).unwrap().interpreter.is_none() {
+            return;
+        }
+        let dir = TempDir::new().unwrap();
+        bootstrap(dir.path(), &exe, 0o755);
+        let err = check(dir.path(), std::env::consts::ARCH).unwrap_err();
+        assert!(err.to_string().contains("dynamically linked"), "{}", err);
+    }
+
+    #[test]
+    fn test_unknown_arch() {
+        assert!(machine("mips").is_err());
+        assert!(musl_target("mips").is_err());
+    }
+
+    #[test]
+    fn test_musl_target() {
+        assert_eq!(musl_target("x86_64").unwrap(), "x86_64-unknown-linux-musl");
+        assert_eq!(musl_target("x86").unwrap(), "i686-unknown-linux-musl");
+    }
+}
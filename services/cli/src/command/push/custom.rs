@@ -5,7 +5,7 @@ use tokio::process::Command;
 use tonic::async_trait;
 use tracing::{debug, info, warn};
 
-use super::BuildService;
+use super::{BuildService, bootstrap};
 
 /// Custom build configuration
 ///
@@ -13,10 +13,11 @@ use super::BuildService;
 /// Custom build scripts execute arbitrary shell commands with full system access.
 /// Only use trusted configuration files. The script runs with the same permissions
 /// as the build process.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct CustomBuild {
     /// Shell script or command to execute
-    /// The OUTPUT environment variable will contain the temp directory path
+    /// The OUTPUT environment variable will contain the temp directory path, ARCH the
+    /// architecture of the workers and RUST_TARGET the musl target for it
     script: String,
 
     /// Optional timeout in seconds (default: 300 seconds / 5 minutes)
@@ -31,6 +32,10 @@ pub struct CustomBuild {
     /// Shell to use (default: "sh" on Unix, "cmd" on Windows)
     #[serde(default = "default_shell")]
     shell: String,
+
+    /// Architecture of the workers, taken from the project
+    #[serde(skip, default = "default_arch")]
+    arch: String,
 }
 
 fn default_timeout() -> u64 {
@@ -45,7 +50,17 @@ fn default_shell() -> String {
     }
 }
 
+fn default_arch() -> String {
+    bootstrap::DEFAULT_ARCH.to_string()
+}
+
 impl CustomBuild {
+    /// Build for workers running on `arch`
+    pub(super) fn for_arch(mut self, arch: &str) -> Self {
+        self.arch = arch.to_string();
+        self
+    }
+
     /// Validate the custom build configuration
     fn validate(&self) -> anyhow::Result<()> {
         // Check script is not empty
@@ -125,6 +140,8 @@ impl BuildService for CustomBuild {
             project_path
         };
 
+        let target = bootstrap::musl_target(&self.arch)?;
+
         debug!("Working directory: {:?}", working_dir);
         debug!("Output directory (OUTPUT env): {:?}", temp_path);
 
@@ -140,6 +157,8 @@ impl BuildService for CustomBuild {
             .env("OUTPUT", &temp_path)
             .env("PROJECT_PATH", &working_dir)
             .env("TEMP_PATH", &temp_path)
+            .env("ARCH", &self.arch)
+            .env("RUST_TARGET", target)
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .kill_on_drop(true); // Ensure child is killed if this future is dropped
@@ -209,6 +228,7 @@ mod tests {
             timeout_seconds: 300,
             working_directory: None,
             shell: default_shell(),
+            arch: default_arch(),
         };
 
         assert!(build.validate().is_err());
@@ -221,6 +241,7 @@ mod tests {
             timeout_seconds: 0,
             working_directory: None,
             shell: default_shell(),
+            arch: default_arch(),
         };
 
         assert!(build.validate().is_err());
@@ -233,6 +254,7 @@ mod tests {
             timeout_seconds: 300,
             working_directory: None,
             shell: default_shell(),
+            arch: default_arch(),
         };
 
         assert!(build.validate().is_ok());
@@ -245,6 +267,7 @@ mod tests {
             timeout_seconds: 300,
             working_directory: None,
             shell: "sh".to_string(),
+            arch: default_arch(),
         };
 
         if !cfg!(target_os = "windows") {
@@ -262,6 +285,7 @@ mod tests {
             timeout_seconds: 10,
             working_directory: None,
             shell: default_shell(),
+            arch: default_arch(),
         };
 
         let result = build
@@ -276,4 +300,30 @@ mod tests {
         let output_file = temp_dir.path().join("test.txt");
         assert!(output_file.exists());
     }
+
+    #[tokio::test]
+    async fn test_script_gets_target_of_arch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_dir = tempfile::tempdir().unwrap();
+
+        let build = CustomBuild {
+            script: "echo \"$ARCH $RUST_TARGET\" > $OUTPUT/target.txt".to_string(),
+            timeout_seconds: 10,
+            working_directory: None,
+            shell: default_shell(),
+            arch: default_arch(),
+        }
+        .for_arch("aarch64");
+
+        build
+            .build(
+                project_dir.path().to_path_buf(),
+                temp_dir.path().to_path_buf(),
+            )
+            .await
+            .unwrap();
+
+        let written = std::fs::read_to_string(temp_dir.path().join("target.txt")).unwrap();
+        assert_eq!(written.trim(), "aarch64 aarch64-unknown-linux-musl");
+    }
 }
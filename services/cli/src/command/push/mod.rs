@@ -2,25 +2,34 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
 use custom::CustomBuild;
+use package::{Compression, Digest, SigningKey};
 use proto::api::{
     controlplane::{
         SetDigestToNameRequest, control_plane_service_client::ControlPlaneServiceClient,
     },
-    registry::{self, RegistryPushRequest},
+    registry,
 };
 use registry::registry_service_client::RegistryServiceClient;
 use rust::RustBuild;
 use serde::Deserialize;
-use tokio::io::{AsyncReadExt, duplex};
+use serde_json::json;
+use tempfile::{NamedTempFile, TempDir};
 use tonic::{Request, async_trait};
 use tracing::{debug, error, info};
 
 use crate::command::push::rust::RustBuildConfig;
+use crate::{config::Connector, error::BadInput, output::Output};
 
+pub mod all;
+mod archive;
+pub(super) mod bootstrap;
 mod custom;
 mod rust;
+mod upload;
 
 const CONFIG_FILE: &str = "Nocti.toml";
+/// Build output and dependencies, never searched for functions or watched
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
 
 #[async_trait]
 trait BuildService {
@@ -30,25 +39,19 @@ trait BuildService {
 #[derive(Debug, Deserialize)]
 struct Project {
     name: String,
+    namespace: Option<String>,
+    /// Architecture of the workers, checked against the built `bootstrap`
+    arch: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Config {
     project: Project,
     build: Build,
-    #[serde(default = "default_registry_url")]
-    registry_url: String,
-    #[serde(default = "default_control_plane_url")]
-    control_plane_url: String,
-}
-
-fn default_registry_url() -> String {
-    std::env::var("NOCTI_REGISTRY_URL").unwrap_or_else(|_| "http://localhost:50001".to_string())
-}
-
-fn default_control_plane_url() -> String {
-    std::env::var("NOCTI_CONTROL_PLANE_URL")
-        .unwrap_or_else(|_| "http://localhost:50002".to_string())
+    /// Registry of this project when neither a flag nor the CLI config sets one
+    registry_url: Option<String>,
+    /// Control plane of this project when neither a flag nor the CLI config sets one
+    control_plane_url: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -60,42 +63,177 @@ enum Build {
     Rust(RustBuildConfig),
 }
 
-pub async fn run(path: &str) -> Result<()> {
+/// Whether a directory called `name` is skipped, hidden ones are too
+pub(super) fn is_skipped(name: &str) -> bool {
+    name.starts_with('.') || SKIPPED_DIRS.contains(&name)
+}
+
+/// A function project: its directory and parsed `Nocti.toml`
+pub(super) struct Function {
+    path: PathBuf,
+    config: Config,
+}
+
+impl Function {
+    pub(super) fn load(project_path: &Path) -> Result<Self> {
+        // Validate project path
+        if !project_path.is_dir() {
+            error!("Provided path is invalid: {:?}", project_path);
+            bail!(BadInput(
+                "path does not exist or is not a directory".to_string()
+            ));
+        }
+
+        // Validate config file exists
+        let config_file_path = project_path.join(CONFIG_FILE);
+        if !config_file_path.is_file() {
+            error!("Missing config file at: {:?}", config_file_path);
+            bail!(BadInput(format!(
+                "'{}' does not exist or is not a file",
+                CONFIG_FILE
+            )));
+        }
+
+        // Load and parse config
+        info!("Loading project config from: {:?}", config_file_path);
+        let config_content = std::fs::read_to_string(&config_file_path).with_context(|| {
+            BadInput(format!(
+                "Failed to read config file: {:?}",
+                config_file_path
+            ))
+        })?;
+
+        let config: Config = toml::from_str(&config_content)
+            .with_context(|| BadInput(format!("Failed to parse {:?} as TOML", config_file_path)))?;
+
+        debug!("Parsed config: {:?}", config);
+        Ok(Self {
+            path: project_path.to_path_buf(),
+            config,
+        })
+    }
+
+    pub(super) fn name(&self) -> &str {
+        &self.config.project.name
+    }
+
+    fn arch(&self) -> &str {
+        self.config
+            .project
+            .arch
+            .as_deref()
+            .unwrap_or(bootstrap::DEFAULT_ARCH)
+    }
+
+    /// `namespace` comes from the command line and wins over the project, which in
+    /// turn wins over `default_namespace` from the CLI config.
+    pub(super) fn namespace(
+        &self,
+        namespace: Option<&str>,
+        default_namespace: Option<&str>,
+    ) -> String {
+        namespace
+            .or(self.config.project.namespace.as_deref())
+            .or(default_namespace)
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+/// Settings of `push` and `push all`
+pub struct PushOptions {
+    pub compression: Compression,
+    pub signing_key: Option<SigningKey>,
+    pub output: Output,
+    /// Only build and print the digest, without pushing or deploying
+    pub dry_run: bool,
+}
+
+/// `namespace` comes from the command line and wins over the project, which in turn
+/// wins over `default_namespace` from the CLI config.
+pub async fn run(
+    path: &str,
+    options: PushOptions,
+    namespace: Option<String>,
+    default_namespace: Option<String>,
+    connector: &Connector,
+) -> Result<()> {
+    let PushOptions {
+        compression,
+        signing_key,
+        output,
+        dry_run,
+    } = options;
     let project_path = Path::new(path);
     info!("Running push command on path: {:?}", project_path);
 
-    // Validate project path
-    if !project_path.is_dir() {
-        error!("Provided path is invalid: {:?}", project_path);
-        bail!("path does not exist or is not a directory");
+    let function = Function::load(project_path)?;
+    let archive = build(&function).await?;
+    if dry_run {
+        let digest = local_digest(&archive).await?.to_string();
+        info!("Dry run, not pushing {}", digest);
+        return output.print(
+            &json!({ "name": function.name(), "digest": digest }),
+            || println!("{}", digest),
+        );
     }
+    let digest = push_package(
+        &function,
+        &archive,
+        compression,
+        signing_key.as_ref(),
+        connector,
+    )
+    .await?;
+
+    let namespace = function.namespace(namespace.as_deref(), default_namespace.as_deref());
+    deploy(
+        &function,
+        function.name(),
+        digest.clone(),
+        namespace.clone(),
+        connector,
+    )
+    .await?;
+    output.print(
+        &json!({ "name": function.name(), "namespace": namespace, "digest": digest }),
+        || println!("{}\t{}", function.name(), digest),
+    )
+}
 
-    // Validate config file exists
-    let config_file_path = project_path.join(CONFIG_FILE);
-    if !config_file_path.is_file() {
-        error!("Missing config file at: {:?}", config_file_path);
-        bail!("'{}' does not exist or is not a file", CONFIG_FILE);
-    }
+/// Build `function` and pack the output into an uncompressed tar archive
+pub(super) async fn build(function: &Function) -> Result<NamedTempFile> {
+    let temp_dir = build_output(function).await?;
+    bootstrap::check(temp_dir.path(), function.arch())
+        .with_context(|| format!("'{}' cannot run on a worker", function.name()))?;
 
-    // Load and parse config
-    info!("Loading project config from: {:?}", config_file_path);
-    let config_content = std::fs::read_to_string(&config_file_path)
-        .with_context(|| format!("Failed to read config file: {:?}", config_file_path))?;
+    // The archive is chunked uncompressed, so unchanged files give unchanged chunks
+    // whatever the compression.
+    info!("Creating tar archive...");
+    let archive = archive::pack(temp_dir.path()).await?;
+    debug!("Tarball creation completed successfully");
 
-    let config: Config =
-        toml::from_str(&config_content).context("Failed to parse config file as TOML")?;
+    Ok(archive)
+}
 
-    debug!("Parsed config: {:?}", config);
+/// Digest the registry will give the archive of `function`
+pub(super) async fn local_digest(archive: &NamedTempFile) -> Result<Digest> {
+    archive::digest(archive.path()).await
+}
 
+/// Build `function` into a temporary directory, holding the `bootstrap` among others
+pub(super) async fn build_output(function: &Function) -> Result<TempDir> {
     // Create build service
-    let buildservice: Box<dyn BuildService + Send + Sync> = match config.build {
+    let buildservice: Box<dyn BuildService + Send + Sync> = match &function.config.build {
         Build::Custom(cb) => {
             debug!("Using custom build");
-            Box::new(cb)
+            Box::new(cb.clone().for_arch(function.arch()))
         }
         Build::Rust(rb_config) => {
             debug!("Using Rust build with config: {:?}", rb_config);
-            Box::new(RustBuild::from(rb_config))
+            Box::new(RustBuild::from(
+                rb_config.clone().for_arch(function.arch())?,
+            ))
         }
     };
 
@@ -110,100 +248,106 @@ pub async fn run(path: &str) -> Result<()> {
     debug!("Temporary directory created at: {:?}", temp_path);
 
     // Run the build
-    info!("Starting build...");
+    info!("Starting build of '{}'...", function.name());
     buildservice
-        .build(project_path.to_path_buf(), temp_path.clone())
+        .build(function.path.clone(), temp_path.clone())
         .await
         .context("Build failed")?;
-    info!("Build completed successfully");
-
-    // Create tar archive and stream it
-    let (writer, mut reader) = duplex(8 * 1024);
-    info!("Creating in-memory tar archive...");
+    info!("Build of '{}' completed successfully", function.name());
 
-    let tar_task = tokio::spawn(async move {
-        let temp_path = temp_dir.path();
+    Ok(temp_dir)
+}
 
-        let mut builder = tokio_tar::Builder::new(writer);
-        if let Err(e) = builder.append_dir_all(".", temp_path).await {
-            error!("Failed to add directory to tar: {}", e);
-            return Err(anyhow::anyhow!("tar append_dir_all error: {}", e));
-        }
-        if let Err(e) = builder.finish().await {
-            error!("Failed to finalize tar archive: {}", e);
-            return Err(anyhow::anyhow!("tar finish error: {}", e));
-        }
-        debug!("Tarball creation completed successfully");
-        Ok(())
+/// Sign and push the archive of `function` to its registry and return the digest
+pub(super) async fn push_package(
+    function: &Function,
+    archive: &NamedTempFile,
+    compression: Compression,
+    signing_key: Option<&SigningKey>,
+    connector: &Connector,
+) -> Result<String> {
+    let config = &function.config;
+
+    // Computed locally, so an upload can be skipped and the signature made before it.
+    let digest = local_digest(archive).await?;
+    let signature = signing_key.map(|key| {
+        info!("Signing {} with {}", digest, key.public_key());
+        key.sign(&digest)
     });
 
-    // Create a stream of RegistryPushRequest from reader
-    let outbound = async_stream::stream! {
-        let mut buf = [0u8; 8192];
-        loop {
-            match reader.read(&mut buf).await {
-                Ok(0) => {
-                    debug!("Finished reading all tar data");
-                    break;
-                }
-                Ok(n) => {
-                    debug!("Read {} bytes from tar stream", n);
-                    let req = RegistryPushRequest {
-                        data: buf[..n].to_vec(),
-                    };
-                    yield req;
-                }
-                Err(e) => {
-                    error!("Error reading from tar stream: {}", e);
-                    break;
-                }
-            }
-        }
-    };
-
     // Connect to registry and push
+    let registry_url = connector.registry_url_or(config.registry_url.as_deref());
+    info!("Connecting to RegistryService at {}...", registry_url);
+    let registry_channel = connector
+        .connect(registry_url)
+        .await
+        .with_context(|| format!("Failed to connect to RegistryService at {}", registry_url))?;
+    let mut registry_client = RegistryServiceClient::new(registry_channel);
+
+    if upload::exists(&mut registry_client, &digest, signature.as_ref()).await? {
+        info!("Registry already has {}, skipping upload", digest);
+        return Ok(digest.to_string());
+    }
+
+    info!(
+        "Sending package to registry ({} compression)...",
+        compression
+    );
+    let stored = upload::upload(
+        &mut registry_client,
+        archive.path(),
+        compression,
+        signature.as_ref(),
+    )
+    .await?;
+    debug!("Registry responded with digest: {}", stored);
+    if stored != digest.to_string() {
+        bail!(
+            "Registry stored the package as {} but it was built as {}",
+            stored,
+            digest
+        );
+    }
+
+    Ok(stored)
+}
+
+/// Point `name` at `digest` on the control plane of `function`
+pub(super) async fn deploy(
+    function: &Function,
+    name: &str,
+    digest: String,
+    namespace: String,
+    connector: &Connector,
+) -> Result<()> {
+    let config = &function.config;
+    let key = name.to_string();
     info!(
-        "Connecting to RegistryService at {}...",
-        config.registry_url
+        "Associating digest with project key: {} (namespace: {})",
+        key,
+        if namespace.is_empty() {
+            "default"
+        } else {
+            &namespace
+        }
     );
-    let mut registry_client = RegistryServiceClient::connect(config.registry_url.clone())
+
+    let control_plane_url = connector.control_plane_url_or(config.control_plane_url.as_deref());
+    let control_plane_channel = connector
+        .connect(control_plane_url)
         .await
         .with_context(|| {
             format!(
-                "Failed to connect to RegistryService at {}",
-                config.registry_url
+                "Failed to connect to ControlPlaneService at {}",
+                control_plane_url
             )
         })?;
-
-    info!("Sending tar data to registry...");
-    let response = registry_client
-        .push(Request::new(outbound))
-        .await
-        .context("Failed to push to registry")?
-        .into_inner();
-
-    debug!("Registry responded with digest: {}", response.digest);
-
-    // Wait for tar task to complete
-    tar_task.await.context("Tar creation task panicked")??;
-
-    // Associate digest with project name
-    let key = config.project.name;
-    info!("Associating digest with project key: {}", key);
-
-    let mut control_plane_client =
-        ControlPlaneServiceClient::connect(config.control_plane_url.clone())
-            .await
-            .with_context(|| {
-                format!(
-                    "Failed to connect to ControlPlaneService at {}",
-                    config.control_plane_url
-                )
-            })?;
+    let mut control_plane_client = ControlPlaneServiceClient::new(control_plane_channel);
 
     let request = SetDigestToNameRequest {
         key: key.clone(),
-        digest: response.digest,
+        digest,
+        namespace,
     };
 
     let response = control_plane_client
@@ -7,9 +7,9 @@ use tokio::process::Command;
 use tonic::async_trait;
 use tracing::debug;
 
-use super::BuildService;
+use super::{BuildService, bootstrap};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct RustBuildConfig {
     /// Target triple (e.g., "x86_64-unknown-linux-musl")
     #[serde(default)]
@@ -32,6 +32,16 @@ fn default_profile() -> String {
     "release".to_string()
 }
 
+impl RustBuildConfig {
+    /// Build for workers running on `arch`, unless a target is set explicitly
+    pub(super) fn for_arch(mut self, arch: &str) -> anyhow::Result<Self> {
+        if self.target.is_none() {
+            self.target = Some(bootstrap::musl_target(arch)?.to_string());
+        }
+        Ok(self)
+    }
+}
+
 impl From<RustBuildConfig> for RustBuild {
     fn from(config: RustBuildConfig) -> Self {
         let profile = match config.profile.to_lowercase().as_str() {
@@ -406,6 +416,31 @@ mod tests {
         assert_eq!(build.binary_name, Some("my-binary".to_string()));
     }
 
+    fn config(target: Option<&str>) -> RustBuildConfig {
+        RustBuildConfig {
+            target: target.map(str::to_string),
+            profile: default_profile(),
+            package_name: None,
+            binary_name: None,
+        }
+    }
+
+    #[test]
+    fn test_target_of_arch() {
+        let build = RustBuild::from(config(None).for_arch("aarch64").unwrap());
+        assert_eq!(build.target, Some("aarch64-unknown-linux-musl".to_string()));
+
+        // An explicit target wins over the arch.
+        let build = RustBuild::from(
+            config(Some("aarch64-unknown-linux-gnu"))
+                .for_arch("x86_64")
+                .unwrap(),
+        );
+        assert_eq!(build.target, Some("aarch64-unknown-linux-gnu".to_string()));
+
+        assert!(config(None).for_arch("mips").is_err());
+    }
+
     #[test]
     fn test_default_rust_build() {
         let build = RustBuild::default();
@@ -0,0 +1,196 @@
+use std::{collections::HashMap, io::SeekFrom, path::Path, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use auth::ClientAuth;
+use package::{Chunk, Compression, Digest, Signature, chunk_archive};
+use proto::api::registry::{
+    RegistryCommitRequest, RegistryHasRequest, RegistryMissingChunksRequest, RegistryUploadChunk,
+    registry_service_client::RegistryServiceClient,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::oneshot,
+};
+use tonic::{Code, Request, Status, service::interceptor::InterceptedService, transport::Channel};
+use tracing::{debug, error, info, warn};
+
+type Client = RegistryServiceClient<InterceptedService<Channel, ClientAuth>>;
+
+/// Uploads are retried this often, every attempt only sends what is still missing
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Whether the registry already stores `digest`, in which case it keeps `signature`
+/// with it and the upload can be skipped
+pub async fn exists(
+    client: &mut Client,
+    digest: &Digest,
+    signature: Option<&Signature>,
+) -> Result<bool> {
+    let response = client
+        .has(Request::new(RegistryHasRequest {
+            digest: digest.to_string(),
+            signature: signature.map(ToString::to_string).unwrap_or_default(),
+        }))
+        .await;
+    match response {
+        Ok(response) => Ok(response.into_inner().exists),
+        // Registries from before `Has` get the full upload.
+        Err(status) if status.code() == Code::Unimplemented => {
+            debug!("Registry cannot tell whether it has {}", digest);
+            Ok(false)
+        }
+        Err(status) => Err(status).context("Failed to ask registry for the package"),
+    }
+}
+
+/// Push the uncompressed archive at `archive` in chunks, sending only the chunks the
+/// registry does not have yet, and return its digest
+pub async fn upload(
+    client: &mut Client,
+    archive: &Path,
+    compression: Compression,
+    signature: Option<&Signature>,
+) -> Result<String> {
+    let file = std::fs::File::open(archive).context("Failed to open archive")?;
+    let chunks: Vec<Chunk> = tokio::task::spawn_blocking(move || {
+        chunk_archive(std::io::BufReader::new(file)).collect::<std::io::Result<_>>()
+    })
+    .await
+    .context("Chunking task panicked")?
+    .context("Failed to chunk archive")?;
+    debug!("Split archive into {} chunks", chunks.len());
+
+    let mut attempt = 1;
+    loop {
+        match try_upload(client, archive, &chunks, compression, signature).await {
+            Ok(digest) => return Ok(digest),
+            Err(e) if attempt < MAX_ATTEMPTS && is_retryable(&e) => {
+                warn!("Upload attempt {} failed, resuming: {:#}", attempt, e);
+                tokio::time::sleep(RETRY_DELAY).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn try_upload(
+    client: &mut Client,
+    archive: &Path,
+    chunks: &[Chunk],
+    compression: Compression,
+    signature: Option<&Signature>,
+) -> Result<String> {
+    let hashes: Vec<String> = chunks.iter().map(|chunk| chunk.hash.clone()).collect();
+    let missing = client
+        .missing_chunks(Request::new(RegistryMissingChunksRequest {
+            chunks: hashes.clone(),
+        }))
+        .await
+        .context("Failed to ask registry for missing chunks")?
+        .into_inner()
+        .missing;
+
+    let by_hash: HashMap<&str, &Chunk> = chunks
+        .iter()
+        .map(|chunk| (chunk.hash.as_str(), chunk))
+        .collect();
+    let mut to_send = Vec::with_capacity(missing.len());
+    for hash in &missing {
+        match by_hash.get(hash.as_str()) {
+            Some(chunk) => to_send.push((*chunk).clone()),
+            None => bail!("registry asked for unknown chunk {}", hash),
+        }
+    }
+
+    let bytes: usize = to_send.iter().map(|chunk| chunk.length).sum();
+    info!(
+        "Uploading {} of {} chunks ({} bytes)",
+        to_send.len(),
+        chunks.len(),
+        bytes
+    );
+
+    if !to_send.is_empty() {
+        let mut file = File::open(archive)
+            .await
+            .context("Failed to open archive")?;
+        // A client stream cannot carry an error, so a failed read ends it early and
+        // is reported once the registry has answered.
+        let (read_error_tx, mut read_error_rx) = oneshot::channel();
+        let outbound = async_stream::stream! {
+            for chunk in to_send {
+                match read_chunk(&mut file, &chunk, compression).await {
+                    Ok(data) => {
+                        debug!("Sending chunk {} ({} bytes)", chunk.hash, data.len());
+                        yield RegistryUploadChunk {
+                            hash: chunk.hash,
+                            data,
+                            compression: compression.into(),
+                        };
+                    }
+                    Err(e) => {
+                        error!("Error reading chunk {}: {}", chunk.hash, e);
+                        let _ = read_error_tx.send(
+                            anyhow::Error::new(e).context(format!("Failed to read chunk {}", chunk.hash)),
+                        );
+                        break;
+                    }
+                }
+            }
+        };
+
+        let response = client
+            .upload_chunks(Request::new(outbound))
+            .await
+            .context("Failed to upload chunks")?
+            .into_inner();
+        if let Ok(e) = read_error_rx.try_recv() {
+            return Err(e);
+        }
+        debug!("Registry stored {} chunks", response.stored);
+    }
+
+    let response = client
+        .commit(Request::new(RegistryCommitRequest {
+            chunks: hashes,
+            compression: compression.into(),
+            signature: signature.map(ToString::to_string).unwrap_or_default(),
+        }))
+        .await
+        .context("Failed to commit package")?
+        .into_inner();
+    Ok(response.digest)
+}
+
+/// Broken connections and chunks collected in the meantime are worth another
+/// attempt, a rejected package or key is not
+fn is_retryable(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<Status>() {
+        Some(status) => !matches!(
+            status.code(),
+            Code::InvalidArgument | Code::PermissionDenied | Code::Unauthenticated
+        ),
+        None => false,
+    }
+}
+
+/// Read `chunk` from the archive and compress it for sending
+async fn read_chunk(
+    file: &mut File,
+    chunk: &Chunk,
+    compression: Compression,
+) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![0; chunk.length];
+    file.seek(SeekFrom::Start(chunk.offset)).await?;
+    file.read_exact(&mut data).await?;
+
+    let mut compressed = Vec::new();
+    let mut encoder = compression.encoder(&mut compressed);
+    encoder.write_all(&data).await?;
+    encoder.shutdown().await?;
+    drop(encoder);
+    Ok(compressed)
+}
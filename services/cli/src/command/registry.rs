@@ -0,0 +1,144 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use package::Signature;
+use proto::api::registry::{
+    RegistryDeleteRequest, RegistryListRequest, RegistryStatRequest,
+    registry_service_client::RegistryServiceClient,
+};
+use serde_json::{Value, json};
+use tonic::Request;
+use tracing::{debug, info};
+
+use crate::{config::Connector, output::Output};
+
+#[derive(Subcommand, Debug)]
+pub enum RegistryCommand {
+    /// List the stored packages with their size and upload time
+    List {
+        /// Packages fetched per request
+        #[arg(long, default_value_t = 100)]
+        page_size: u32,
+    },
+    /// Show the size, entry count and top-level files of a package
+    Stat { digest: String },
+    /// Delete a package no function is deployed with
+    Delete {
+        digest: String,
+        /// Delete the package even if a function still points at it
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+pub async fn run(command: RegistryCommand, output: Output, connector: &Connector) -> Result<()> {
+    let url = connector.registry_url();
+    debug!("Connecting to RegistryService at {}", url);
+    let mut client = RegistryServiceClient::new(
+        connector
+            .connect(url)
+            .await
+            .with_context(|| format!("Failed to connect to RegistryService at {}", url))?,
+    );
+
+    match command {
+        RegistryCommand::List { page_size } => {
+            let mut page_token = String::new();
+            let mut packages = Vec::new();
+            loop {
+                let response = client
+                    .list(Request::new(RegistryListRequest {
+                        page_size,
+                        page_token,
+                    }))
+                    .await
+                    .context("Failed to list packages")?
+                    .into_inner();
+
+                packages.extend(response.packages);
+
+                if response.next_page_token.is_empty() {
+                    break;
+                }
+                page_token = response.next_page_token;
+            }
+
+            let value: Vec<_> = packages
+                .iter()
+                .map(|package| {
+                    json!({
+                        "digest": package.digest,
+                        "size_bytes": package.size_bytes,
+                        "created_at": package.created_at,
+                    })
+                })
+                .collect();
+            output.print(&value, || {
+                for package in &packages {
+                    println!(
+                        "{}\t{}\t{}",
+                        package.digest, package.size_bytes, package.created_at
+                    );
+                }
+            })?;
+        }
+        RegistryCommand::Stat { digest } => {
+            let response = client
+                .stat(Request::new(RegistryStatRequest { digest }))
+                .await
+                .context("Failed to stat package")?
+                .into_inner();
+
+            let signed_by: Vec<_> = response
+                .signatures
+                .iter()
+                .map(|signature| {
+                    signature
+                        .parse::<Signature>()
+                        .map(|signature| signature.public_key().to_string())
+                })
+                .collect();
+            let mut value = json!({
+                "entries": response.entries,
+                "files": response.top_level,
+                "signed_by": signed_by
+                    .iter()
+                    .flatten()
+                    .collect::<Vec<_>>(),
+            });
+            if let Some(package) = &response.package {
+                value["digest"] = Value::from(package.digest.clone());
+                value["size_bytes"] = Value::from(package.size_bytes);
+                value["created_at"] = Value::from(package.created_at);
+            }
+            output.print(&value, || {
+                if let Some(package) = &response.package {
+                    println!("digest:\t{}", package.digest);
+                    println!("size:\t{}", package.size_bytes);
+                    println!("created:\t{}", package.created_at);
+                }
+                println!("entries:\t{}", response.entries);
+                println!("files:\t{}", response.top_level.join(" "));
+                for (signature, signed_by) in response.signatures.iter().zip(&signed_by) {
+                    match signed_by {
+                        Ok(public_key) => println!("signed by:\t{}", public_key),
+                        Err(_) => println!("signature:\t{}", signature),
+                    }
+                }
+            })?;
+        }
+        RegistryCommand::Delete { digest, force } => {
+            client
+                .delete(Request::new(RegistryDeleteRequest {
+                    digest: digest.clone(),
+                    force,
+                }))
+                .await
+                .context("Failed to delete package")?;
+
+            info!("Deleted package {}", digest);
+            output.print(&json!({ "digest": digest, "deleted": true }), || {})?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::{
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use proto::api::{
+    action::{
+        InvokeRequest, function_runner_service_client::FunctionRunnerServiceClient, invoke_result,
+    },
+    worker::{ExecuteSuccess, ProblemDetails, execute_response},
+};
+use sdk::SOCKET_PATH_ENV;
+use tokio::process::{Child, Command};
+use tonic::transport::Channel;
+use tracing::{debug, info};
+
+use super::invoke::{Payload, parse_metadata, print_outcome};
+use super::push::{Function, bootstrap::BOOTSTRAP, build_output};
+use crate::{error::BadInput, output::Output};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+const STARTUP_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Build the function at `path`, start its `bootstrap` as a plain process and invoke
+/// it once, without a registry, control plane or worker
+pub async fn run(
+    path: &str,
+    payload: Payload,
+    metadata: Vec<String>,
+    raw: bool,
+    output: Output,
+) -> Result<()> {
+    let body = payload.read().await?;
+    let metadata = parse_metadata(metadata)?;
+
+    let function = Function::load(Path::new(path))?;
+    let build = build_output(&function).await?;
+    let bootstrap = build.path().join(BOOTSTRAP);
+    if !bootstrap.is_file() {
+        bail!(BadInput(format!(
+            "The build did not produce a '{}'",
+            BOOTSTRAP
+        )));
+    }
+
+    let socket_path = build.path().join("app.sock");
+    info!("Starting '{}' locally", function.name());
+    let mut child = start(&bootstrap, build.path(), &socket_path)?;
+    let mut client = wait_for_handler(&mut child, &socket_path).await?;
+
+    debug!("Invoking handler with {} bytes", body.len());
+    let result = client
+        .invoke(tonic::Request::new(InvokeRequest {
+            payload: body,
+            metadata,
+        }))
+        .await
+        .context("Handler invocation failed")?
+        .into_inner()
+        .result;
+
+    // Stopped before the build output is removed.
+    let _ = child.kill().await;
+
+    print_outcome(function.name(), Some(outcome(result)?), raw, output)
+}
+
+/// The handler's result as the worker would report it, so it prints the same way
+fn outcome(result: Option<invoke_result::Result>) -> Result<execute_response::Outcome> {
+    Ok(match result {
+        Some(invoke_result::Result::Success(success)) => {
+            execute_response::Outcome::Success(ExecuteSuccess {
+                body: success.output,
+            })
+        }
+        Some(invoke_result::Result::Problem(problem)) => {
+            execute_response::Outcome::Problem(ProblemDetails {
+                r#type: problem.r#type,
+                detail: problem.detail,
+                ..Default::default()
+            })
+        }
+        None => bail!("Handler returned no result"),
+    })
+}
+
+/// Start `bootstrap` in `dir`. Its output goes to stderr, so stdout only holds the
+/// result.
+fn start(bootstrap: &Path, dir: &Path, socket_path: &Path) -> Result<Child> {
+    let stderr = std::io::stderr().as_fd().try_clone_to_owned()?;
+    Command::new(bootstrap)
+        .current_dir(dir)
+        .env(SOCKET_PATH_ENV, socket_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(stderr))
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {:?}", bootstrap))
+}
+
+/// Connect to the handler once it listens on `socket_path`
+async fn wait_for_handler(
+    child: &mut Child,
+    socket_path: &Path,
+) -> Result<FunctionRunnerServiceClient<Channel>> {
+    let uri = format!("unix://{}", socket_path.display());
+    let attempts = STARTUP_TIMEOUT.as_millis() / STARTUP_RETRY_INTERVAL.as_millis();
+    for _ in 0..attempts {
+        if let Some(status) = child.try_wait()? {
+            bail!("Handler exited before it was ready: {}", status);
+        }
+        if socket_path.exists()
+            && let Ok(client) = FunctionRunnerServiceClient::connect(uri.clone()).await
+        {
+            debug!(uri, "Handler is ready");
+            return Ok(client);
+        }
+        tokio::time::sleep(STARTUP_RETRY_INTERVAL).await;
+    }
+    bail!(
+        "Handler did not listen on {} within {:?}",
+        PathBuf::from(socket_path).display(),
+        STARTUP_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use proto::api::action::{Problem, Success};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_outcome_of_success() {
+        let result = invoke_result::Result::Success(Success {
+            output: b"hello".to_vec(),
+        });
+        match outcome(Some(result)).unwrap() {
+            execute_response::Outcome::Success(success) => assert_eq!(success.body, b"hello"),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_outcome_of_problem() {
+        let result = invoke_result::Result::Problem(Problem {
+            r#type: "invalid-input".to_string(),
+            detail: "name is missing".to_string(),
+        });
+        match outcome(Some(result)).unwrap() {
+            execute_response::Outcome::Problem(problem) => {
+                assert_eq!(problem.r#type, "invalid-input");
+                assert_eq!(problem.detail, "name is missing");
+            }
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        assert!(outcome(None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handler_exiting_before_it_listens() {
+        let dir = TempDir::new().unwrap();
+        let bootstrap = dir.path().join(BOOTSTRAP);
+        std::fs::write(&bootstrap, "#!/bin/sh\nexit 1\n").unwrap();
+        std::fs::set_permissions(&bootstrap, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let socket_path = dir.path().join("app.sock");
+
+        let mut child = start(&bootstrap, dir.path(), &socket_path).unwrap();
+        let err = wait_for_handler(&mut child, &socket_path)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exited before"), "{:#}", err);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use package::SigningKey;
+use serde_json::json;
+use tracing::info;
+
+use crate::{error::BadInput, output::Output};
+
+#[derive(Subcommand, Debug)]
+pub enum SigningKeyCommand {
+    /// Create a key for signing packages and print its public key
+    Generate {
+        /// File the private key is written to, readable only by you
+        path: PathBuf,
+        /// Replace an existing key file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the public key of a private key file, for the workers' trusted keys
+    Public { path: PathBuf },
+}
+
+pub fn run(command: SigningKeyCommand, output: Output) -> Result<()> {
+    match command {
+        SigningKeyCommand::Generate { path, force } => {
+            let key = SigningKey::generate();
+
+            let mut options = OpenOptions::new();
+            options.write(true).mode(0o600);
+            match force {
+                true => options.create(true).truncate(true),
+                false => options.create_new(true),
+            };
+            let mut file = options
+                .open(&path)
+                .with_context(|| format!("Failed to create key file {:?}", path))?;
+            writeln!(file, "{}", key.to_hex())
+                .with_context(|| format!("Failed to write key file {:?}", path))?;
+
+            info!("Wrote private key to {:?}", path);
+            print_public_key(&key, output)?;
+        }
+        SigningKeyCommand::Public { path } => {
+            print_public_key(&load(&path)?, output)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn print_public_key(key: &SigningKey, output: Output) -> Result<()> {
+    let public_key = key.public_key().to_string();
+    output.print(&json!({ "public_key": public_key }), || {
+        println!("{}", public_key)
+    })
+}
+
+pub fn load(path: &Path) -> Result<SigningKey> {
+    std::fs::read_to_string(path)
+        .with_context(|| BadInput(format!("Failed to read signing key {:?}", path)))?
+        .parse()
+        .with_context(|| BadInput(format!("Signing key {:?} is not a valid key", path)))
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use auth::ClientAuth;
+use serde::Deserialize;
+use tls::TlsConfig;
+use tonic::{service::interceptor::InterceptedService, transport::Channel};
+use tracing::debug;
+
+use crate::error::BadInput;
+
+const CONFIG_DIR: &str = "noctiforge";
+const CONFIG_FILE: &str = "config.toml";
+
+const DEFAULT_REGISTRY_URL: &str = "http://localhost:50001";
+const DEFAULT_CONTROL_PLANE_URL: &str = "http://localhost:50002";
+const DEFAULT_WORKER_URL: &str = "http://[::1]:50003";
+
+/// User level settings, read from `$NOCTI_CONFIG` or
+/// `$XDG_CONFIG_HOME/noctiforge/config.toml`. The settings at the top of the file
+/// apply to every profile, a selected `[profiles.<name>]` table overrides them.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct CliConfig {
+    api_key: Option<String>,
+    namespace: Option<String>,
+    tls_ca: Option<PathBuf>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    /// Private key `push` signs packages with
+    signing_key: Option<PathBuf>,
+    registry_url: Option<String>,
+    control_plane_url: Option<String>,
+    worker_url: Option<String>,
+}
+
+/// The profile tables of the config file, kept apart so the settings can be shared
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct Profiles {
+    /// Profile used when none is selected
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, CliConfig>,
+}
+
+/// Settings given on the command line, taking precedence over the config file
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub api_key: Option<String>,
+    pub tls_ca: Option<PathBuf>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub registry_url: Option<String>,
+    pub control_plane_url: Option<String>,
+    pub worker_url: Option<String>,
+}
+
+/// Opens connections to the services with the configured API key and TLS settings
+#[derive(Clone)]
+pub struct Connector {
+    auth: ClientAuth,
+    tls: TlsConfig,
+    /// Set by a flag or the config file, `None` until a project or the default picks one
+    registry_url: Option<String>,
+    control_plane_url: Option<String>,
+    worker_url: String,
+}
+
+impl Connector {
+    pub async fn connect(&self, url: &str) -> Result<InterceptedService<Channel, ClientAuth>> {
+        let channel = self.tls.endpoint(url)?.connect().await?;
+        Ok(InterceptedService::new(channel, self.auth.clone()))
+    }
+
+    pub fn registry_url(&self) -> &str {
+        self.registry_url_or(None)
+    }
+
+    pub fn control_plane_url(&self) -> &str {
+        self.control_plane_url_or(None)
+    }
+
+    /// Registry from the command line or config file, else `project`, else the default
+    pub fn registry_url_or<'a>(&'a self, project: Option<&'a str>) -> &'a str {
+        self.registry_url
+            .as_deref()
+            .or(project)
+            .unwrap_or(DEFAULT_REGISTRY_URL)
+    }
+
+    /// Control plane from the command line or config file, else `project`, else the
+    /// default
+    pub fn control_plane_url_or<'a>(&'a self, project: Option<&'a str>) -> &'a str {
+        self.control_plane_url
+            .as_deref()
+            .or(project)
+            .unwrap_or(DEFAULT_CONTROL_PLANE_URL)
+    }
+
+    pub fn worker_url(&self) -> &str {
+        &self.worker_url
+    }
+}
+
+impl CliConfig {
+    /// Read the config file with `profile` applied
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        let path = config_path().filter(|path| path.is_file());
+        let Some(path) = path else {
+            debug!("No CLI config file found");
+            if let Some(profile) = profile {
+                bail!(BadInput(format!(
+                    "profile '{}' selected but there is no config file",
+                    profile
+                )));
+            }
+            return Ok(Self::default());
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| BadInput(format!("Failed to read config file: {:?}", path)))?;
+        let (base, profiles) = Self::parse(&content)
+            .with_context(|| BadInput(format!("Failed to parse config file: {:?}", path)))?;
+        base.with_profile(profiles, profile)
+    }
+
+    fn parse(content: &str) -> Result<(Self, Profiles)> {
+        let mut base: toml::Table = toml::from_str(content)?;
+        let mut profiles = toml::Table::new();
+        for key in ["default_profile", "profiles"] {
+            if let Some(value) = base.remove(key) {
+                profiles.insert(key.to_string(), value);
+            }
+        }
+        let base: CliConfig = toml::Value::Table(base).try_into()?;
+        let profiles: Profiles = toml::Value::Table(profiles).try_into()?;
+        Ok((base, profiles))
+    }
+
+    /// Apply `profile`, or the `default_profile` of the file when none is given
+    fn with_profile(self, mut profiles: Profiles, profile: Option<&str>) -> Result<Self> {
+        let Some(name) = profile.or(profiles.default_profile.as_deref()) else {
+            return Ok(self);
+        };
+        let Some(selected) = profiles.profiles.remove(name) else {
+            let known: Vec<_> = profiles.profiles.keys().map(String::as_str).collect();
+            bail!(BadInput(format!(
+                "unknown profile '{}', known profiles: {}",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )));
+        };
+        debug!("Using profile '{}'", name);
+        Ok(selected.or(self))
+    }
+
+    /// Settings of `self`, falling back to `base` for the unset ones
+    fn or(self, base: Self) -> Self {
+        Self {
+            api_key: self.api_key.or(base.api_key),
+            namespace: self.namespace.or(base.namespace),
+            tls_ca: self.tls_ca.or(base.tls_ca),
+            tls_cert: self.tls_cert.or(base.tls_cert),
+            tls_key: self.tls_key.or(base.tls_key),
+            signing_key: self.signing_key.or(base.signing_key),
+            registry_url: self.registry_url.or(base.registry_url),
+            control_plane_url: self.control_plane_url.or(base.control_plane_url),
+            worker_url: self.worker_url.or(base.worker_url),
+        }
+    }
+
+    /// Namespace used when neither the command line nor the project picks one
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    pub fn signing_key(&self) -> Option<&Path> {
+        self.signing_key.as_deref()
+    }
+
+    pub fn connector(self, overrides: Overrides) -> Result<Connector> {
+        let key = overrides.api_key.or(self.api_key);
+        let auth = ClientAuth::new(key.as_deref())
+            .context(BadInput("API key is not a valid header value".to_string()))?;
+
+        let tls = TlsConfig {
+            cert: overrides.tls_cert.or(self.tls_cert),
+            key: overrides.tls_key.or(self.tls_key),
+            ca: overrides.tls_ca.or(self.tls_ca),
+            require_client_cert: false,
+        };
+
+        Ok(Connector {
+            auth,
+            tls,
+            registry_url: overrides.registry_url.or(self.registry_url),
+            control_plane_url: overrides.control_plane_url.or(self.control_plane_url),
+            worker_url: overrides
+                .worker_url
+                .or(self.worker_url)
+                .unwrap_or_else(|| DEFAULT_WORKER_URL.to_string()),
+        })
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("NOCTI_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok()?;
+    Some(config_home.join(CONFIG_DIR).join(CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+api_key = "shared"
+namespace = "team-a"
+default_profile = "local"
+
+[profiles.local]
+registry_url = "http://127.0.0.1:50001"
+
+[profiles.staging]
+api_key = "staging"
+registry_url = "https://registry.staging:50001"
+tls_ca = "/etc/noctiforge/ca.pem"
+"#;
+
+    fn load(profile: Option<&str>) -> Result<CliConfig> {
+        let (base, profiles) = CliConfig::parse(CONFIG)?;
+        base.with_profile(profiles, profile)
+    }
+
+    #[test]
+    fn test_profile_overrides_shared_settings() {
+        let config = load(Some("staging")).unwrap();
+        assert_eq!(config.api_key.as_deref(), Some("staging"));
+        assert_eq!(config.namespace.as_deref(), Some("team-a"));
+        assert_eq!(
+            config.registry_url.as_deref(),
+            Some("https://registry.staging:50001")
+        );
+        assert_eq!(config.tls_ca, Some(PathBuf::from("/etc/noctiforge/ca.pem")));
+    }
+
+    #[test]
+    fn test_default_profile() {
+        let config = load(None).unwrap();
+        assert_eq!(config.api_key.as_deref(), Some("shared"));
+        assert_eq!(
+            config.registry_url.as_deref(),
+            Some("http://127.0.0.1:50001")
+        );
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        let err = load(Some("prod")).unwrap_err();
+        assert!(err.to_string().contains("local, staging"));
+    }
+
+    #[test]
+    fn test_unknown_setting_is_rejected() {
+        assert!(CliConfig::parse("[profiles.local]\nregistry = \"x\"\n").is_err());
+        assert!(CliConfig::parse("api_kye = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn test_overrides_win() {
+        let config = load(Some("staging")).unwrap();
+        let connector = config
+            .connector(Overrides {
+                registry_url: Some("http://override:1".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(connector.registry_url(), "http://override:1");
+        assert_eq!(connector.control_plane_url(), DEFAULT_CONTROL_PLANE_URL);
+    }
+
+    #[test]
+    fn test_project_urls_only_replace_defaults() {
+        let project = Some("http://project:1");
+
+        let connector = load(Some("staging"))
+            .unwrap()
+            .connector(Overrides {
+                control_plane_url: Some("http://flag:2".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            connector.registry_url_or(project),
+            "https://registry.staging:50001"
+        );
+        assert_eq!(connector.control_plane_url_or(project), "http://flag:2");
+
+        let connector = CliConfig::default()
+            .connector(Overrides::default())
+            .unwrap();
+        assert_eq!(connector.registry_url_or(project), "http://project:1");
+        assert_eq!(
+            connector.control_plane_url_or(None),
+            DEFAULT_CONTROL_PLANE_URL
+        );
+    }
+}
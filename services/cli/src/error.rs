@@ -0,0 +1,102 @@
+use std::fmt;
+
+use tonic::Code;
+
+/// Exit codes that tell scripts why the CLI failed. Command line errors reported by
+/// clap exit with `BAD_INPUT` as well.
+pub mod exit_code {
+    pub const FAILURE: u8 = 1;
+    /// Invalid arguments, payload, config or project
+    pub const BAD_INPUT: u8 = 2;
+    /// The function ran and returned a problem
+    pub const PROBLEM: u8 = 3;
+    /// A service could not be reached
+    pub const TRANSPORT: u8 = 4;
+    /// A service refused or failed the request
+    pub const REJECTED: u8 = 5;
+}
+
+/// Something the user gave the CLI is wrong, used as error or as context of one
+#[derive(Debug)]
+pub struct BadInput(pub String);
+
+impl fmt::Display for BadInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BadInput {}
+
+/// A function returned a problem instead of output
+#[derive(Debug)]
+pub struct ProblemOutcome {
+    pub name: String,
+    pub r#type: String,
+}
+
+impl fmt::Display for ProblemOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' failed with {}", self.name, self.r#type)
+    }
+}
+
+impl std::error::Error for ProblemOutcome {}
+
+/// Exit code for a failed command
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    if error.downcast_ref::<BadInput>().is_some() {
+        return exit_code::BAD_INPUT;
+    }
+    if error.downcast_ref::<ProblemOutcome>().is_some() {
+        return exit_code::PROBLEM;
+    }
+    for cause in error.chain() {
+        if cause.is::<tonic::transport::Error>() {
+            return exit_code::TRANSPORT;
+        }
+        if let Some(status) = cause.downcast_ref::<tonic::Status>() {
+            return match status.code() {
+                Code::Unavailable | Code::DeadlineExceeded => exit_code::TRANSPORT,
+                Code::InvalidArgument => exit_code::BAD_INPUT,
+                _ => exit_code::REJECTED,
+            };
+        }
+    }
+    exit_code::FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let bad_input = Err::<(), _>(anyhow::Error::new(BadInput("no".to_string())))
+            .context("Failed to load")
+            .unwrap_err();
+        assert_eq!(exit_code(&bad_input), exit_code::BAD_INPUT);
+
+        let bad_input = Err::<(), _>(anyhow::anyhow!("missing file"))
+            .context(BadInput("Failed to read payload".to_string()))
+            .unwrap_err();
+        assert_eq!(exit_code(&bad_input), exit_code::BAD_INPUT);
+
+        let problem = anyhow::Error::new(ProblemOutcome {
+            name: "echo".to_string(),
+            r#type: "about:blank".to_string(),
+        });
+        assert_eq!(exit_code(&problem), exit_code::PROBLEM);
+
+        let status = |code| {
+            Err::<(), _>(tonic::Status::new(code, "x"))
+                .context("Worker execute call failed")
+                .unwrap_err()
+        };
+        assert_eq!(exit_code(&status(Code::Unavailable)), exit_code::TRANSPORT);
+        assert_eq!(exit_code(&status(Code::NotFound)), exit_code::REJECTED);
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), exit_code::FAILURE);
+    }
+}
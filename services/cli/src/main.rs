@@ -1,8 +1,17 @@
-use anyhow::Result;
+use std::process::ExitCode;
 
 mod command;
+mod config;
+mod error;
+mod output;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    command::run().await
+async fn main() -> ExitCode {
+    match command::run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(error::exit_code(&e))
+        }
+    }
 }
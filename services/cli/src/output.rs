@@ -0,0 +1,25 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// How commands print their results on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Output {
+    /// Lines meant for people
+    #[default]
+    Text,
+    Json,
+    Yaml,
+}
+
+impl Output {
+    /// Print `value` as JSON or YAML, or let `text` print it as text
+    pub fn print<T: Serialize>(self, value: &T, text: impl FnOnce()) -> Result<()> {
+        match self {
+            Output::Text => text(),
+            Output::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            Output::Yaml => print!("{}", serde_yaml_ng::to_string(value)?),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+use sdk::{Context, Problem};
+
+#[derive(serde::Deserialize)]
+struct Request {
+    name: String,
+}
+
+async fn handler(req: Request, _: Context) -> Result<String, Problem> {
+    if req.name.is_empty() {
+        return Err(Problem {
+            r#type: "{{name}}/empty_name".to_string(),
+            detail: "name is empty".to_string(),
+        });
+    }
+
+    Ok(format!("Hello, {}!", req.name))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    sdk::start(handler).await
+}
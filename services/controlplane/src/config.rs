@@ -1,18 +1,134 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 
-// TODO: share this location with registry
-pub const DB_PATH: &str = "/var/lib/noctiforge/controlplane/digests.db";
+use clap::Parser;
+use serde::Deserialize;
+use tls::TlsConfig;
+
+const DEFAULT_ADDR: &str = "[::1]:50002";
+const DEFAULT_DATA_DIR: &str = "/var/lib/noctiforge/controlplane";
+const DB_FILE: &str = "digests.db";
+
+/// Command line arguments. Every flag can also be set through the environment
+/// and takes precedence over the config file.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Path to a TOML config file
+    #[arg(short, long, env = "CONTROLPLANE_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Address the gRPC server listens on
+    #[arg(long, env = "CONTROLPLANE_ADDR")]
+    addr: Option<SocketAddr>,
+
+    /// Directory holding the control plane database
+    #[arg(long, env = "CONTROLPLANE_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Require an API key on every request
+    #[arg(long, env = "AUTH_ENABLED")]
+    auth: bool,
+
+    /// API key that is granted the admin scope on startup. Best passed only
+    /// through the environment, so it stays out of the process list
+    #[arg(long, env = "CONTROLPLANE_ADMIN_KEY", hide_env_values = true)]
+    admin_key: Option<String>,
+
+    /// PEM certificate presented to peers, enables TLS on the server
+    #[arg(long, env = "TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key belonging to `--tls-cert`
+    #[arg(long, env = "TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificate used to verify servers and, with `--tls-client-auth`, clients
+    #[arg(long, env = "TLS_CA")]
+    tls_ca: Option<PathBuf>,
+
+    /// Require clients to present a certificate signed by `--tls-ca`
+    #[arg(long, env = "TLS_CLIENT_AUTH")]
+    tls_client_auth: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    addr: Option<SocketAddr>,
+    data_dir: Option<PathBuf>,
+    auth: Option<bool>,
+    admin_key: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tls_ca: Option<PathBuf>,
+    tls_client_auth: Option<bool>,
+}
 
 pub struct ServerConfig {
     pub addr: SocketAddr,
+    pub data_dir: PathBuf,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+}
+
+pub struct AuthConfig {
+    pub enabled: bool,
+    pub admin_key: Option<String>,
 }
 
 impl ServerConfig {
-    pub fn from_env() -> Self {
-        let addr = std::env::var("SERVER_ADDR")
-            .unwrap_or_else(|_| "[::1]:50002".to_string())
-            .parse()
-            .expect("Invalid server address");
-        Self { addr }
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_args(Args::parse())
     }
+
+    fn from_args(args: Args) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = match &args.config {
+            Some(path) => read_config_file(path)?,
+            None => FileConfig::default(),
+        };
+
+        let addr = match args.addr.or(file.addr) {
+            Some(addr) => addr,
+            None => DEFAULT_ADDR.parse()?,
+        };
+
+        let data_dir = args
+            .data_dir
+            .or(file.data_dir)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
+
+        let auth = AuthConfig {
+            enabled: args.auth || file.auth.unwrap_or(false),
+            admin_key: args.admin_key.or(file.admin_key),
+        };
+
+        let tls = TlsConfig {
+            cert: args.tls_cert.or(file.tls_cert),
+            key: args.tls_key.or(file.tls_key),
+            ca: args.tls_ca.or(file.tls_ca),
+            require_client_cert: args.tls_client_auth || file.tls_client_auth.unwrap_or(false),
+        };
+
+        Ok(Self {
+            addr,
+            data_dir,
+            auth,
+            tls,
+        })
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.data_dir.join(DB_FILE)
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<FileConfig, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+    let config = toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse config file {}: {}", path.display(), e))?;
+    Ok(config)
 }
@@ -1,5 +1,4 @@
-use std::path::Path;
-
+use auth::ServerAuth;
 use proto::api::controlplane::control_plane_service_server::ControlPlaneServiceServer;
 use tonic::transport::Server;
 use tracing::info;
@@ -12,14 +11,39 @@ mod services;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt().with_target(false).init();
 
-    let config = config::ServerConfig::from_env();
-    let control_plane = server::ControlPlane::new(Path::new(config::DB_PATH)).await?;
+    let config = config::ServerConfig::load()?;
+
+    tokio::fs::create_dir_all(&config.data_dir)
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to create data directory {}: {}",
+                config.data_dir.display(),
+                e
+            )
+        })?;
+
+    let db_path = config.db_path();
+    let control_plane = server::ControlPlane::new(&db_path, &config.auth).await?;
 
     info!("ControlPlaneService listening on {}", config.addr);
-    info!("Database at: {}", config::DB_PATH);
+    info!("Database at: {}", db_path.display());
+    info!("API key authentication enabled: {}", config.auth.enabled);
+
+    let mut server = Server::builder();
+    if let Some(tls) = config.tls.server()? {
+        info!(
+            "TLS enabled, client certificates required: {}",
+            config.tls.require_client_cert
+        );
+        server = server.tls_config(tls)?;
+    }
 
-    Server::builder()
-        .add_service(ControlPlaneServiceServer::new(control_plane))
+    server
+        .add_service(ControlPlaneServiceServer::with_interceptor(
+            control_plane,
+            ServerAuth::new(config.auth.enabled),
+        ))
         .serve(config.addr)
         .await?;
 
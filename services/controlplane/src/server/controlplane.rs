@@ -1,28 +1,128 @@
-use std::path::Path;
+use std::{path::Path, pin::Pin};
 
-use proto::api::controlplane::{
-    GetDigestByNameRequest, GetDigestByNameResponse, SetDigestToNameRequest,
-    SetDigestToNameResponse, control_plane_service_server::ControlPlaneService,
+use auth::{ALL_NAMESPACES, ApiKey, Scope};
+use proto::{
+    api::controlplane::{
+        AuthorizeRequest, AuthorizeResponse, CreateApiKeyRequest, CreateApiKeyResponse,
+        CreateNamespaceRequest, CreateNamespaceResponse, DeleteNamespaceRequest,
+        DeleteNamespaceResponse, DeploymentEvent, GetDigestByNameRequest, GetDigestByNameResponse,
+        ListApiKeysRequest, ListApiKeysResponse, ListFunctionsRequest, ListFunctionsResponse,
+        ListNamespacesRequest, ListNamespacesResponse, ListReferencedDigestsRequest,
+        ListReferencedDigestsResponse, RevokeApiKeyRequest, RevokeApiKeyResponse,
+        SetDigestToNameRequest, SetDigestToNameResponse, SetNamespaceQuotaRequest,
+        SetNamespaceQuotaResponse, WatchDeploymentsRequest,
+        control_plane_service_server::ControlPlaneService,
+    },
+    namespace::{self, DEFAULT_NAMESPACE},
+};
+use tokio::sync::broadcast;
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{BroadcastStream, errors::BroadcastStreamRecvError},
 };
 use tonic::{Request, Response, Status};
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    config::AuthConfig,
+    services::{self, ApiKeyService, DigestService, NamespaceService},
+};
 
-use crate::services::DigestService;
+/// Number of deployment events buffered per watcher before it is considered lagging
+const DEPLOYMENT_EVENT_CAPACITY: usize = 256;
 
 pub struct ControlPlane {
     digest_service: DigestService,
+    api_key_service: ApiKeyService,
+    namespace_service: NamespaceService,
+    deployments: broadcast::Sender<DeploymentEvent>,
+    auth_enabled: bool,
 }
 
 impl ControlPlane {
-    pub async fn new(db_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new(
+        db_path: &Path,
+        auth: &AuthConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let pool = services::connect(db_path).await?;
+        let namespace_service = NamespaceService::new(pool.clone()).await?;
+        let api_key_service = ApiKeyService::new(pool.clone()).await?;
+
+        if let Some(admin_key) = &auth.admin_key {
+            api_key_service.bootstrap_admin(admin_key).await?;
+        }
+        if auth.enabled && api_key_service.is_empty().await? {
+            warn!(
+                "Authentication is enabled but no API keys exist, set an admin key to create one"
+            );
+        }
+
+        let (deployments, _) = broadcast::channel(DEPLOYMENT_EVENT_CAPACITY);
         Ok(Self {
-            digest_service: DigestService::new(db_path).await?,
+            digest_service: DigestService::new(pool).await?,
+            api_key_service,
+            namespace_service,
+            deployments,
+            auth_enabled: auth.enabled,
         })
     }
+
+    /// Enforce `scope` on `resource` in `namespace` for the API key sent with `request`
+    async fn check_access<T>(
+        &self,
+        request: &Request<T>,
+        namespace: &str,
+        scope: Scope,
+        resource: &str,
+    ) -> Result<(), Status> {
+        if !self.auth_enabled {
+            return Ok(());
+        }
+
+        let key = api_key(request)?;
+        self.api_key_service
+            .authorize(&key.0, namespace, scope, resource)
+            .await
+            .map(|_| ())
+    }
+
+    /// Resources the API key sent with `request` is limited to, every resource when
+    /// keys are not required
+    async fn granted_resources<T>(&self, request: &Request<T>) -> Result<Vec<String>, Status> {
+        if !self.auth_enabled {
+            return Ok(vec!["*".to_string()]);
+        }
+
+        let key = api_key(request)?;
+        self.api_key_service.resources_of(&key.0).await
+    }
+}
+
+/// Owned namespace and function name of a request
+fn resolve(namespace: &str, key: &str) -> Result<(String, String), Status> {
+    let (namespace, key) = namespace::resolve(namespace, key).map_err(Status::invalid_argument)?;
+    Ok((namespace.to_string(), key.to_string()))
+}
+
+fn namespace_or_default(namespace: String) -> String {
+    match namespace.is_empty() {
+        true => DEFAULT_NAMESPACE.to_string(),
+        false => namespace,
+    }
+}
+
+fn api_key<T>(request: &Request<T>) -> Result<&ApiKey, Status> {
+    request
+        .extensions()
+        .get::<ApiKey>()
+        .ok_or_else(|| Status::unauthenticated("missing API key"))
 }
 
 #[tonic::async_trait]
 impl ControlPlaneService for ControlPlane {
+    type WatchDeploymentsStream =
+        Pin<Box<dyn Stream<Item = Result<DeploymentEvent, Status>> + Send + 'static>>;
+
     #[instrument(
         name = "Get digest to name",
         skip(self, request),
@@ -32,16 +132,24 @@ impl ControlPlaneService for ControlPlane {
         &self,
         request: Request<GetDigestByNameRequest>,
     ) -> Result<Response<GetDigestByNameResponse>, Status> {
-        let req = request.into_inner();
+        let (namespace, key) = resolve(&request.get_ref().namespace, &request.get_ref().key)?;
+        self.check_access(&request, &namespace, Scope::Invoke, &key)
+            .await?;
         debug!(
-            key = %req.key,
+            namespace = %namespace,
+            key = %key,
             "Received request to set digest"
         );
-        let result = self.digest_service.get_digest_by_name(&req.key).await;
+        let result = self
+            .digest_service
+            .get_digest_by_name(&namespace, &key)
+            .await;
 
         match &result {
-            Ok(_) => info!(key = %req.key, "Successfully retrieved digest"),
-            Err(e) => debug!(key = %req.key, status = ?e.code(), "Failed to retrieve digest"),
+            Ok(_) => info!(namespace = %namespace, key = %key, "Successfully retrieved digest"),
+            Err(e) => {
+                debug!(namespace = %namespace, key = %key, status = ?e.code(), "Failed to retrieve digest")
+            }
         }
 
         result
@@ -56,22 +164,199 @@ impl ControlPlaneService for ControlPlane {
         &self,
         request: Request<SetDigestToNameRequest>,
     ) -> Result<Response<SetDigestToNameResponse>, Status> {
+        let (namespace, key) = resolve(&request.get_ref().namespace, &request.get_ref().key)?;
+        self.check_access(&request, &namespace, Scope::Deploy, &key)
+            .await?;
         let req = request.into_inner();
         debug!(
-            key = %req.key,
+            namespace = %namespace,
+            key = %key,
             digest_length = req.digest.len(),
             "Received request to set digest"
         );
         let result = self
             .digest_service
-            .set_digest_by_name(&req.key, &req.digest)
+            .set_digest_by_name(&namespace, &key, &req.digest)
             .await;
 
         match &result {
-            Ok(_) => info!(key = %req.key, "Successfully set digest"),
-            Err(e) => debug!(key = %req.key, status = ?e.code(), "Failed to set digest"),
+            Ok(_) => {
+                info!(namespace = %namespace, key = %key, "Successfully set digest");
+                // Sending only fails when nobody is watching, which is fine.
+                let watchers = self
+                    .deployments
+                    .send(DeploymentEvent {
+                        key: key.clone(),
+                        digest: req.digest.clone(),
+                        namespace: namespace.clone(),
+                    })
+                    .unwrap_or(0);
+                debug!(namespace = %namespace, key = %key, watchers, "Published deployment event");
+            }
+            Err(e) => {
+                debug!(namespace = %namespace, key = %key, status = ?e.code(), "Failed to set digest")
+            }
         }
 
         result
     }
+
+    #[instrument(name = "List functions", skip(self, request))]
+    async fn list_functions(
+        &self,
+        request: Request<ListFunctionsRequest>,
+    ) -> Result<Response<ListFunctionsResponse>, Status> {
+        let namespace = namespace_or_default(request.get_ref().namespace.clone());
+        self.check_access(&request, &namespace, Scope::Invoke, "")
+            .await?;
+        self.digest_service.list_functions(&namespace).await
+    }
+
+    #[instrument(name = "Watch deployments", skip(self, request))]
+    async fn watch_deployments(
+        &self,
+        request: Request<WatchDeploymentsRequest>,
+    ) -> Result<Response<Self::WatchDeploymentsStream>, Status> {
+        // Watchers see the deployments of every namespace.
+        self.check_access(&request, ALL_NAMESPACES, Scope::Invoke, "")
+            .await?;
+        info!("New deployment watcher connected");
+
+        let stream = BroadcastStream::new(self.deployments.subscribe()).map(|event| {
+            event.map_err(|BroadcastStreamRecvError::Lagged(skipped)| {
+                // The watcher can no longer trust its view, so end the stream and let it
+                // start over from a clean cache.
+                warn!(skipped, "Deployment watcher lagged behind");
+                Status::data_loss(format!("watcher missed {} deployment events", skipped))
+            })
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    #[instrument(name = "List referenced digests", skip(self, request))]
+    async fn list_referenced_digests(
+        &self,
+        request: Request<ListReferencedDigestsRequest>,
+    ) -> Result<Response<ListReferencedDigestsResponse>, Status> {
+        // Packages are shared by every namespace, so only a global admin sees them all.
+        self.check_access(&request, ALL_NAMESPACES, Scope::Admin, "")
+            .await?;
+        self.digest_service.referenced_digests().await
+    }
+
+    #[instrument(
+        name = "Authorize",
+        skip(self, request),
+        fields(scope = %request.get_ref().scope, resource = %request.get_ref().resource)
+    )]
+    async fn authorize(
+        &self,
+        request: Request<AuthorizeRequest>,
+    ) -> Result<Response<AuthorizeResponse>, Status> {
+        // Always checked, other services rely on this even when the control plane
+        // itself does not require keys.
+        let key = api_key(&request)?;
+        let req = request.get_ref();
+        let scope: Scope = req.scope.parse().map_err(Status::invalid_argument)?;
+
+        let key_name = self
+            .api_key_service
+            .authorize(&key.0, &req.namespace, scope, &req.resource)
+            .await?;
+
+        Ok(Response::new(AuthorizeResponse { key_name }))
+    }
+
+    #[instrument(name = "Create API key", skip(self, request), fields(name = %request.get_ref().name))]
+    async fn create_api_key(
+        &self,
+        request: Request<CreateApiKeyRequest>,
+    ) -> Result<Response<CreateApiKeyResponse>, Status> {
+        let namespace = namespace_or_default(request.get_ref().namespace.clone());
+        self.check_access(&request, &namespace, Scope::Admin, "")
+            .await?;
+        if namespace != ALL_NAMESPACES && !self.namespace_service.exists(&namespace).await? {
+            return Err(Status::not_found(format!(
+                "namespace '{}' not found",
+                namespace
+            )));
+        }
+
+        let granted = self.granted_resources(&request).await?;
+        let req = request.into_inner();
+        self.api_key_service
+            .create(&req.name, &namespace, req.scopes, req.resources, &granted)
+            .await
+    }
+
+    #[instrument(name = "List API keys", skip(self, request))]
+    async fn list_api_keys(
+        &self,
+        request: Request<ListApiKeysRequest>,
+    ) -> Result<Response<ListApiKeysResponse>, Status> {
+        let namespace = namespace_or_default(request.get_ref().namespace.clone());
+        self.check_access(&request, &namespace, Scope::Admin, "")
+            .await?;
+        self.api_key_service.list(&namespace).await
+    }
+
+    #[instrument(name = "Revoke API key", skip(self, request), fields(name = %request.get_ref().name))]
+    async fn revoke_api_key(
+        &self,
+        request: Request<RevokeApiKeyRequest>,
+    ) -> Result<Response<RevokeApiKeyResponse>, Status> {
+        let namespace = namespace_or_default(request.get_ref().namespace.clone());
+        self.check_access(&request, &namespace, Scope::Admin, "")
+            .await?;
+        self.api_key_service
+            .revoke(&namespace, &request.get_ref().name)
+            .await
+    }
+
+    #[instrument(name = "Create namespace", skip(self, request), fields(name = %request.get_ref().name))]
+    async fn create_namespace(
+        &self,
+        request: Request<CreateNamespaceRequest>,
+    ) -> Result<Response<CreateNamespaceResponse>, Status> {
+        self.check_access(&request, ALL_NAMESPACES, Scope::Admin, "")
+            .await?;
+        let req = request.get_ref();
+        self.namespace_service
+            .create(&req.name, req.max_functions)
+            .await
+    }
+
+    #[instrument(name = "List namespaces", skip(self, request))]
+    async fn list_namespaces(
+        &self,
+        request: Request<ListNamespacesRequest>,
+    ) -> Result<Response<ListNamespacesResponse>, Status> {
+        self.check_access(&request, ALL_NAMESPACES, Scope::Admin, "")
+            .await?;
+        self.namespace_service.list().await
+    }
+
+    #[instrument(name = "Set namespace quota", skip(self, request), fields(name = %request.get_ref().name))]
+    async fn set_namespace_quota(
+        &self,
+        request: Request<SetNamespaceQuotaRequest>,
+    ) -> Result<Response<SetNamespaceQuotaResponse>, Status> {
+        self.check_access(&request, ALL_NAMESPACES, Scope::Admin, "")
+            .await?;
+        let req = request.get_ref();
+        self.namespace_service
+            .set_quota(&req.name, req.max_functions)
+            .await
+    }
+
+    #[instrument(name = "Delete namespace", skip(self, request), fields(name = %request.get_ref().name))]
+    async fn delete_namespace(
+        &self,
+        request: Request<DeleteNamespaceRequest>,
+    ) -> Result<Response<DeleteNamespaceResponse>, Status> {
+        self.check_access(&request, ALL_NAMESPACES, Scope::Admin, "")
+            .await?;
+        self.namespace_service.delete(&request.get_ref().name).await
+    }
 }
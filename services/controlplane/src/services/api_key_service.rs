@@ -0,0 +1,403 @@
+use auth::{ALL_NAMESPACES, Scope, hash_key, is_allowed, namespace_matches, resources_within};
+use proto::api::controlplane::{
+    ApiKeyInfo, CreateApiKeyResponse, ListApiKeysResponse, RevokeApiKeyResponse,
+};
+use rand::RngCore;
+use sqlx::SqlitePool;
+use tonic::{Response, Status};
+use tracing::{debug, error, info, instrument, warn};
+
+const KEY_PREFIX: &str = "nf_";
+const KEY_BYTES: usize = 32;
+const BOOTSTRAP_KEY_NAME: &str = "admin";
+
+pub struct ApiKeyService {
+    pool: SqlitePool,
+}
+
+impl ApiKeyService {
+    #[instrument(skip(pool))]
+    pub async fn new(pool: SqlitePool) -> Result<Self, Box<dyn std::error::Error>> {
+        info!("Initializing ApiKeyService");
+
+        debug!("Creating api_keys table if not exists");
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_keys (
+                namespace TEXT NOT NULL DEFAULT 'default',
+                name TEXT NOT NULL,
+                key_hash TEXT NOT NULL UNIQUE,
+                scopes TEXT NOT NULL,
+                resources TEXT NOT NULL,
+                created_at INTEGER DEFAULT (strftime('%s', 'now')),
+                PRIMARY KEY (namespace, name)
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to create api_keys table");
+            e
+        })?;
+
+        info!("ApiKeyService initialized successfully");
+        Ok(Self { pool })
+    }
+
+    /// Store `key` as an unrestricted admin key, replacing a previous bootstrap key
+    #[instrument(skip(self, key))]
+    pub async fn bootstrap_admin(&self, key: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO api_keys (name, key_hash, scopes, resources, namespace)
+            VALUES (?, ?, ?, '*', ?)
+            "#,
+        )
+        .bind(BOOTSTRAP_KEY_NAME)
+        .bind(hash_key(key))
+        .bind(Scope::Admin.as_str())
+        .bind(ALL_NAMESPACES)
+        .execute(&self.pool)
+        .await?;
+
+        info!(name = BOOTSTRAP_KEY_NAME, "Bootstrap admin key configured");
+        Ok(())
+    }
+
+    pub async fn is_empty(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let (count,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM api_keys")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count == 0)
+    }
+
+    /// Check that `key` holds `scope` on `resource` in `namespace` and return the name
+    /// of the key
+    #[instrument(skip(self, key), fields(namespace = %namespace, scope = %scope, resource = %resource))]
+    pub async fn authorize(
+        &self,
+        key: &str,
+        namespace: &str,
+        scope: Scope,
+        resource: &str,
+    ) -> Result<String, Status> {
+        let row = sqlx::query_as::<_, (String, String, String, String)>(
+            "SELECT name, scopes, resources, namespace FROM api_keys WHERE key_hash = ?",
+        )
+        .bind(hash_key(key))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Database query failed");
+            Status::internal(format!("Database error: {}", e))
+        })?;
+
+        let Some((name, scopes, resources, key_namespace)) = row else {
+            warn!("Unknown API key");
+            return Err(Status::unauthenticated("invalid API key"));
+        };
+
+        let scopes: Vec<Scope> = split_list(&scopes)
+            .iter()
+            .filter_map(|s| s.parse().ok())
+            .collect();
+
+        if !namespace_matches(&key_namespace, namespace, resource) {
+            warn!(key_name = %name, key_namespace = %key_namespace, "API key belongs to another namespace");
+            return Err(Status::permission_denied(format!(
+                "API key '{}' is not valid in namespace '{}'",
+                name, namespace
+            )));
+        }
+
+        if !is_allowed(&scopes, &split_list(&resources), scope, resource) {
+            warn!(key_name = %name, "API key lacks permission");
+            return Err(Status::permission_denied(format!(
+                "API key '{}' may not {} {}",
+                name,
+                scope,
+                if resource.is_empty() {
+                    "here"
+                } else {
+                    resource
+                }
+            )));
+        }
+
+        debug!(key_name = %name, "API key authorized");
+        Ok(name)
+    }
+
+    /// Resources the key `key` is limited to
+    pub async fn resources_of(&self, key: &str) -> Result<Vec<String>, Status> {
+        let row =
+            sqlx::query_as::<_, (String,)>("SELECT resources FROM api_keys WHERE key_hash = ?")
+                .bind(hash_key(key))
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| {
+                    error!(error = %e, "Database query failed");
+                    Status::internal(format!("Database error: {}", e))
+                })?;
+        let (resources,) = row.ok_or_else(|| Status::unauthenticated("invalid API key"))?;
+        Ok(split_list(&resources))
+    }
+
+    /// Create a key in `namespace`, limited to resources within `granted`, the
+    /// resources of the key creating it
+    #[instrument(skip(self, scopes, resources, granted), fields(name = %name, namespace = %namespace))]
+    pub async fn create(
+        &self,
+        name: &str,
+        namespace: &str,
+        scopes: Vec<String>,
+        resources: Vec<String>,
+        granted: &[String],
+    ) -> Result<Response<CreateApiKeyResponse>, Status> {
+        if name.trim().is_empty() {
+            return Err(Status::invalid_argument("API key name cannot be empty"));
+        }
+
+        if scopes.is_empty() {
+            return Err(Status::invalid_argument("at least one scope is required"));
+        }
+        for scope in &scopes {
+            scope.parse::<Scope>().map_err(Status::invalid_argument)?;
+        }
+
+        let resources = if resources.is_empty() {
+            vec!["*".to_string()]
+        } else {
+            resources
+        };
+        if resources.iter().any(|r| r.is_empty() || r.contains(',')) {
+            return Err(Status::invalid_argument(
+                "resources must be non-empty and cannot contain ','",
+            ));
+        }
+        if let Some(resource) = resources.iter().find(|r| !resources_within(granted, r)) {
+            return Err(Status::permission_denied(format!(
+                "cannot grant '{}', the calling key is limited to {}",
+                resource,
+                granted.join(",")
+            )));
+        }
+
+        let key = generate_key();
+
+        let result = sqlx::query(
+            "INSERT INTO api_keys (name, key_hash, scopes, resources, namespace) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(name)
+        .bind(hash_key(&key))
+        .bind(scopes.join(","))
+        .bind(resources.join(","))
+        .bind(namespace)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => {
+                info!("API key created");
+                Ok(Response::new(CreateApiKeyResponse {
+                    name: name.to_string(),
+                    key,
+                }))
+            }
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(
+                Status::already_exists(format!("API key '{}' already exists", name)),
+            ),
+            Err(e) => {
+                error!(error = %e, "Database insert failed");
+                Err(Status::internal(format!("Database error: {}", e)))
+            }
+        }
+    }
+
+    /// List the keys of `namespace`, or of every namespace for [`ALL_NAMESPACES`]
+    #[instrument(skip(self))]
+    pub async fn list(&self, namespace: &str) -> Result<Response<ListApiKeysResponse>, Status> {
+        let rows = sqlx::query_as::<_, (String, String, String, i64, String)>(
+            r#"
+            SELECT name, scopes, resources, created_at, namespace FROM api_keys
+            WHERE ?1 = '*' OR namespace = ?1
+            ORDER BY namespace, name
+            "#,
+        )
+        .bind(namespace)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Database query failed");
+            Status::internal(format!("Database error: {}", e))
+        })?;
+
+        let keys = rows
+            .into_iter()
+            .map(
+                |(name, scopes, resources, created_at, namespace)| ApiKeyInfo {
+                    name,
+                    scopes: split_list(&scopes),
+                    resources: split_list(&resources),
+                    created_at,
+                    namespace,
+                },
+            )
+            .collect();
+
+        Ok(Response::new(ListApiKeysResponse { keys }))
+    }
+
+    #[instrument(skip(self), fields(namespace = %namespace, name = %name))]
+    pub async fn revoke(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Response<RevokeApiKeyResponse>, Status> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE namespace = ? AND name = ?")
+            .bind(namespace)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!(error = %e, "Database delete failed");
+                Status::internal(format!("Database error: {}", e))
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(Status::not_found(format!(
+                "API key '{}' not found in namespace '{}'",
+                name, namespace
+            )));
+        }
+
+        info!("API key revoked");
+        Ok(Response::new(RevokeApiKeyResponse {}))
+    }
+}
+
+fn generate_key() -> String {
+    let mut bytes = [0u8; KEY_BYTES];
+    rand::rng().fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", KEY_PREFIX, hex)
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database;
+
+    fn all() -> Vec<String> {
+        vec!["*".to_string()]
+    }
+
+    async fn create(
+        service: &ApiKeyService,
+        namespace: &str,
+        name: &str,
+    ) -> Result<String, Status> {
+        let response = service
+            .create(
+                name,
+                namespace,
+                vec!["push".to_string()],
+                Vec::new(),
+                &all(),
+            )
+            .await?;
+        Ok(response.into_inner().key)
+    }
+
+    #[tokio::test]
+    async fn test_names_are_scoped_by_namespace() {
+        let service = ApiKeyService::new(database::memory().await).await.unwrap();
+        let key_a = create(&service, "team-a", "ci").await.unwrap();
+        let key_b = create(&service, "team-b", "ci").await.unwrap();
+        let err = create(&service, "team-a", "ci").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+
+        let listed = service.list("team-a").await.unwrap().into_inner().keys;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].namespace, "team-a");
+        assert_eq!(service.list("*").await.unwrap().into_inner().keys.len(), 2);
+
+        service.revoke("team-b", "ci").await.unwrap();
+        let err = service.revoke("team-b", "ci").await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        assert!(
+            service
+                .authorize(&key_b, "team-b", Scope::Push, "echo")
+                .await
+                .is_err()
+        );
+        service
+            .authorize(&key_a, "team-a", Scope::Push, "echo")
+            .await
+            .unwrap();
+
+        let err = service
+            .authorize(&key_a, "team-b", Scope::Push, "echo")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_function_without_namespace_is_denied() {
+        let service = ApiKeyService::new(database::memory().await).await.unwrap();
+        let key = service
+            .create(
+                "invoker",
+                "team-a",
+                vec!["invoke".to_string()],
+                Vec::new(),
+                &all(),
+            )
+            .await
+            .unwrap()
+            .into_inner()
+            .key;
+
+        // What a worker asked for before `/echo` was rejected there.
+        let err = service
+            .authorize(&key, "", Scope::Invoke, "echo")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        service
+            .authorize(&key, "team-a", Scope::Invoke, "echo")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cannot_grant_more_than_the_caller() {
+        let service = ApiKeyService::new(database::memory().await).await.unwrap();
+        let granted = vec!["billing-*".to_string()];
+        let create = |resources: &[&str]| {
+            service.create(
+                "ci",
+                "team-a",
+                vec!["push".to_string()],
+                resources.iter().map(|r| r.to_string()).collect(),
+                &granted,
+            )
+        };
+
+        let err = create(&[]).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        let err = create(&["billing-api", "orders"]).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+        create(&["billing-api"]).await.unwrap();
+    }
+}
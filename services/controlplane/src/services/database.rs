@@ -0,0 +1,50 @@
+use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
+use std::path::Path;
+use tracing::{debug, error, info, instrument};
+
+/// Open the control plane database, creating the file when needed
+#[instrument(skip(db_path), fields(db_path = %db_path.display()))]
+pub async fn connect(db_path: &Path) -> Result<SqlitePool, Box<dyn std::error::Error>> {
+    let parent = db_path
+        .parent()
+        .ok_or("Database path has no parent directory")?;
+
+    if !parent.exists() {
+        error!(parent_dir = %parent.display(), "Parent directory does not exist");
+        return Err(format!("Parent directory does not exist: {}", parent.display()).into());
+    }
+
+    debug!(parent_dir = %parent.display(), "Parent directory exists");
+
+    let database_url = format!("sqlite://{}?mode=rwc", db_path.display());
+    debug!(database_url = %database_url, "Connecting to database");
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect(&database_url)
+        .await?;
+    info!("Database connection established");
+
+    Ok(pool)
+}
+
+/// Whether `table` already has `column`, used to migrate databases created by older versions
+pub async fn has_column(pool: &SqlitePool, table: &str, column: &str) -> Result<bool, sqlx::Error> {
+    let (count,) =
+        sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(pool)
+            .await?;
+    Ok(count > 0)
+}
+
+/// A private in-memory database, one connection so every query sees the same one
+#[cfg(test)]
+pub async fn memory() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
@@ -1,45 +1,36 @@
-use proto::api::controlplane::{GetDigestByNameResponse, SetDigestToNameResponse};
-use sqlx::{SqlitePool, sqlite::SqlitePoolOptions};
-use std::path::Path;
+use package::Digest;
+use proto::api::controlplane::{
+    FunctionInfo, GetDigestByNameResponse, ListFunctionsResponse, ListReferencedDigestsResponse,
+    SetDigestToNameResponse,
+};
+use sqlx::SqlitePool;
 use tonic::{Response, Status};
 use tracing::{debug, error, info, instrument, warn};
 
+use crate::services::database::has_column;
+
+/// Previous digests kept per function, so a rollback target is never collected
+const HISTORY_LENGTH: i64 = 10;
+
 pub struct DigestService {
     pool: SqlitePool,
 }
 
 impl DigestService {
-    #[instrument(skip(db_path), fields(db_path = %db_path.display()))]
-    pub async fn new(db_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+    #[instrument(skip(pool))]
+    pub async fn new(pool: SqlitePool) -> Result<Self, Box<dyn std::error::Error>> {
         info!("Initializing DigestService");
-        let parent = db_path
-            .parent()
-            .ok_or("Database path has no parent directory")?;
-
-        if !parent.exists() {
-            error!(parent_dir = %parent.display(), "Parent directory does not exist");
-            return Err(format!("Parent directory does not exist: {}", parent.display()).into());
-        }
-
-        debug!(parent_dir = %parent.display(), "Parent directory exists");
-
-        let database_url = format!("sqlite://{}?mode=rwc", db_path.display());
-        debug!(database_url = %database_url, "Connecting to database");
-
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(&database_url)
-            .await?;
-        info!("Database connection established");
 
         debug!("Creating digests table if not exists");
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS digests (
-                name TEXT PRIMARY KEY,
+                namespace TEXT NOT NULL DEFAULT 'default',
+                name TEXT NOT NULL,
                 digest TEXT NOT NULL,
                 created_at INTEGER DEFAULT (strftime('%s', 'now')),
-                updated_at INTEGER DEFAULT (strftime('%s', 'now'))
+                updated_at INTEGER DEFAULT (strftime('%s', 'now')),
+                PRIMARY KEY (namespace, name)
             )
             "#,
         )
@@ -50,24 +41,51 @@ impl DigestService {
             e
         })?;
 
+        if !has_column(&pool, "digests", "namespace").await? {
+            migrate_to_namespaces(&pool).await?;
+        }
+
+        debug!("Creating deployments table if not exists");
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS deployments (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                namespace TEXT NOT NULL,
+                name TEXT NOT NULL,
+                digest TEXT NOT NULL,
+                deployed_at INTEGER DEFAULT (strftime('%s', 'now'))
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to create deployments table");
+            e
+        })?;
+
         info!("DigestService initialized successfully");
         Ok(Self { pool })
     }
 
-    #[instrument(skip(self), fields(key = %key))]
+    #[instrument(skip(self), fields(namespace = %namespace, key = %key))]
     pub async fn get_digest_by_name(
         &self,
+        namespace: &str,
         key: &str,
     ) -> Result<Response<GetDigestByNameResponse>, Status> {
         debug!("Fetching digest from database");
-        let result = sqlx::query_as::<_, (String,)>("SELECT digest FROM digests WHERE name = ?")
-            .bind(key)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| {
-                error!(error = %e, "Database query failed");
-                Status::internal(format!("Database error: {}", e))
-            })?;
+        let result = sqlx::query_as::<_, (String,)>(
+            "SELECT digest FROM digests WHERE namespace = ? AND name = ?",
+        )
+        .bind(namespace)
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Database query failed");
+            Status::internal(format!("Database error: {}", e))
+        })?;
 
         match result {
             Some((digest,)) => {
@@ -77,39 +95,294 @@ impl DigestService {
             None => {
                 warn!("Digest not found");
                 Err(Status::not_found(format!(
-                    "Digest not found for name: {}",
-                    key
+                    "Digest not found for name: {}/{}",
+                    namespace, key
                 )))
             }
         }
     }
 
-    #[instrument(skip(self, digest), fields(key = %key, digest_length = digest.len()))]
+    /// Point `key` at `digest`, enforcing the function quota of the namespace when
+    /// `key` is new
+    #[instrument(
+        skip(self, digest),
+        fields(namespace = %namespace, key = %key, digest_length = digest.len())
+    )]
     pub async fn set_digest_by_name(
         &self,
+        namespace: &str,
         key: &str,
         digest: &str,
     ) -> Result<Response<SetDigestToNameResponse>, Status> {
+        if key.is_empty() || key.contains('/') {
+            return Err(Status::invalid_argument(format!(
+                "invalid function name '{}'",
+                key
+            )));
+        }
+        digest
+            .parse::<Digest>()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let mut tx = self.pool.begin().await.map_err(database_error)?;
+
+        let quota =
+            sqlx::query_as::<_, (u32,)>("SELECT max_functions FROM namespaces WHERE name = ?")
+                .bind(namespace)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(database_error)?;
+
+        let Some((max_functions,)) = quota else {
+            warn!("Namespace does not exist");
+            return Err(Status::not_found(format!(
+                "namespace '{}' not found",
+                namespace
+            )));
+        };
+
+        if max_functions > 0 {
+            let (exists, count) = sqlx::query_as::<_, (bool, u32)>(
+                r#"
+                SELECT
+                    EXISTS(SELECT 1 FROM digests WHERE namespace = ? AND name = ?),
+                    (SELECT COUNT(*) FROM digests WHERE namespace = ?)
+                "#,
+            )
+            .bind(namespace)
+            .bind(key)
+            .bind(namespace)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(database_error)?;
+
+            if !exists && count >= max_functions {
+                warn!(max_functions, "Namespace function quota reached");
+                return Err(Status::resource_exhausted(format!(
+                    "namespace '{}' is limited to {} functions",
+                    namespace, max_functions
+                )));
+            }
+        }
+
         debug!("Upserting digest into database");
         sqlx::query(
             r#"
-            INSERT INTO digests (name, digest, updated_at)
-            VALUES (?, ?, strftime('%s', 'now'))
-            ON CONFLICT(name) DO UPDATE SET
+            INSERT INTO digests (namespace, name, digest, updated_at)
+            VALUES (?, ?, ?, strftime('%s', 'now'))
+            ON CONFLICT(namespace, name) DO UPDATE SET
                 digest = excluded.digest,
                 updated_at = strftime('%s', 'now')
             "#,
         )
+        .bind(namespace)
         .bind(key)
         .bind(digest)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
         .map_err(|e| {
             error!(error = %e, "Database upsert failed");
             Status::internal(format!("Database error: {}", e))
         })?;
 
+        sqlx::query("INSERT INTO deployments (namespace, name, digest) VALUES (?, ?, ?)")
+            .bind(namespace)
+            .bind(key)
+            .bind(digest)
+            .execute(&mut *tx)
+            .await
+            .map_err(database_error)?;
+        sqlx::query(
+            r#"
+            DELETE FROM deployments WHERE namespace = ?1 AND name = ?2 AND id NOT IN (
+                SELECT id FROM deployments WHERE namespace = ?1 AND name = ?2
+                ORDER BY id DESC LIMIT ?3
+            )
+            "#,
+        )
+        .bind(namespace)
+        .bind(key)
+        .bind(HISTORY_LENGTH)
+        .execute(&mut *tx)
+        .await
+        .map_err(database_error)?;
+
+        tx.commit().await.map_err(database_error)?;
+
         info!("Digest set successfully");
         Ok(Response::new(SetDigestToNameResponse { success: true }))
     }
+
+    /// List the functions of `namespace`, or of every namespace for `*`
+    #[instrument(skip(self))]
+    pub async fn list_functions(
+        &self,
+        namespace: &str,
+    ) -> Result<Response<ListFunctionsResponse>, Status> {
+        let rows = sqlx::query_as::<_, (String, String, String, i64, i64)>(
+            r#"
+            SELECT name, digest, namespace, created_at, updated_at FROM digests
+            WHERE ?1 = '*' OR namespace = ?1
+            ORDER BY namespace, name
+            "#,
+        )
+        .bind(namespace)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+
+        let functions = rows
+            .into_iter()
+            .map(
+                |(name, digest, namespace, created_at, updated_at)| FunctionInfo {
+                    name,
+                    digest,
+                    namespace,
+                    created_at,
+                    updated_at,
+                },
+            )
+            .collect();
+
+        Ok(Response::new(ListFunctionsResponse { functions }))
+    }
+
+    /// Digests currently deployed under any name plus the recent history of every
+    /// function
+    #[instrument(skip(self))]
+    pub async fn referenced_digests(
+        &self,
+    ) -> Result<Response<ListReferencedDigestsResponse>, Status> {
+        let rows = sqlx::query_as::<_, (String,)>(
+            "SELECT digest FROM digests UNION SELECT digest FROM deployments ORDER BY digest",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+
+        let digests = rows.into_iter().map(|(digest,)| digest).collect();
+        Ok(Response::new(ListReferencedDigestsResponse { digests }))
+    }
+}
+
+/// Move a table keyed by name only into the default namespace
+async fn migrate_to_namespaces(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    info!("Migrating digests table to namespaces");
+    let mut tx = pool.begin().await?;
+    sqlx::query("ALTER TABLE digests RENAME TO digests_unscoped")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE digests (
+            namespace TEXT NOT NULL DEFAULT 'default',
+            name TEXT NOT NULL,
+            digest TEXT NOT NULL,
+            created_at INTEGER DEFAULT (strftime('%s', 'now')),
+            updated_at INTEGER DEFAULT (strftime('%s', 'now')),
+            PRIMARY KEY (namespace, name)
+        )
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO digests (namespace, name, digest, created_at, updated_at)
+        SELECT 'default', name, digest, created_at, updated_at FROM digests_unscoped
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DROP TABLE digests_unscoped")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+fn database_error(e: sqlx::Error) -> Status {
+    error!(error = %e, "Database query failed");
+    Status::internal(format!("Database error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{database, namespace_service::NamespaceService};
+
+    fn digest(n: u8) -> String {
+        format!("v2:{}", format!("{:02x}", n).repeat(32))
+    }
+
+    #[tokio::test]
+    async fn test_quota_only_limits_new_functions() {
+        let pool = database::memory().await;
+        let namespaces = NamespaceService::new(pool.clone()).await.unwrap();
+        let digests = DigestService::new(pool).await.unwrap();
+        namespaces.create("team-a", 1).await.unwrap();
+
+        digests
+            .set_digest_by_name("team-a", "echo", &digest(1))
+            .await
+            .unwrap();
+        let err = digests
+            .set_digest_by_name("team-a", "greeter", &digest(2))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+
+        // Redeploying an existing function stays possible.
+        digests
+            .set_digest_by_name("team-a", "echo", &digest(3))
+            .await
+            .unwrap();
+        // The default namespace has no quota.
+        digests
+            .set_digest_by_name("default", "greeter", &digest(2))
+            .await
+            .unwrap();
+
+        namespaces.set_quota("team-a", 0).await.unwrap();
+        digests
+            .set_digest_by_name("team-a", "greeter", &digest(2))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_namespace() {
+        let pool = database::memory().await;
+        NamespaceService::new(pool.clone()).await.unwrap();
+        let digests = DigestService::new(pool).await.unwrap();
+
+        let err = digests
+            .set_digest_by_name("team-b", "echo", &digest(1))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_digest() {
+        let pool = database::memory().await;
+        NamespaceService::new(pool.clone()).await.unwrap();
+        let digests = DigestService::new(pool).await.unwrap();
+
+        for digest in ["", "v2:1", "latest"] {
+            let err = digests
+                .set_digest_by_name("default", "echo", digest)
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        }
+        assert!(
+            digests
+                .referenced_digests()
+                .await
+                .unwrap()
+                .into_inner()
+                .digests
+                .is_empty()
+        );
+    }
 }
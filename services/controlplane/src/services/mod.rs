@@ -1,2 +1,8 @@
+mod api_key_service;
+mod database;
 mod digest_service;
+mod namespace_service;
+pub use api_key_service::ApiKeyService;
+pub use database::connect;
 pub use digest_service::DigestService;
+pub use namespace_service::NamespaceService;
@@ -0,0 +1,184 @@
+use proto::{
+    api::controlplane::{
+        CreateNamespaceResponse, DeleteNamespaceResponse, ListNamespacesResponse, NamespaceInfo,
+        SetNamespaceQuotaResponse,
+    },
+    namespace::{self, DEFAULT_NAMESPACE},
+};
+use sqlx::SqlitePool;
+use tonic::{Response, Status};
+use tracing::{debug, error, info, instrument};
+
+pub struct NamespaceService {
+    pool: SqlitePool,
+}
+
+impl NamespaceService {
+    #[instrument(skip(pool))]
+    pub async fn new(pool: SqlitePool) -> Result<Self, Box<dyn std::error::Error>> {
+        info!("Initializing NamespaceService");
+
+        debug!("Creating namespaces table if not exists");
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS namespaces (
+                name TEXT PRIMARY KEY,
+                max_functions INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER DEFAULT (strftime('%s', 'now'))
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to create namespaces table");
+            e
+        })?;
+
+        sqlx::query("INSERT OR IGNORE INTO namespaces (name) VALUES (?)")
+            .bind(DEFAULT_NAMESPACE)
+            .execute(&pool)
+            .await?;
+
+        info!("NamespaceService initialized successfully");
+        Ok(Self { pool })
+    }
+
+    pub async fn exists(&self, name: &str) -> Result<bool, Status> {
+        let row = sqlx::query_as::<_, (String,)>("SELECT name FROM namespaces WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(database_error)?;
+        Ok(row.is_some())
+    }
+
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn create(
+        &self,
+        name: &str,
+        max_functions: u32,
+    ) -> Result<Response<CreateNamespaceResponse>, Status> {
+        namespace::validate(name).map_err(Status::invalid_argument)?;
+
+        let result = sqlx::query("INSERT INTO namespaces (name, max_functions) VALUES (?, ?)")
+            .bind(name)
+            .bind(max_functions)
+            .execute(&self.pool)
+            .await;
+
+        match result {
+            Ok(_) => {
+                info!(max_functions, "Namespace created");
+                Ok(Response::new(CreateNamespaceResponse {}))
+            }
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Err(
+                Status::already_exists(format!("namespace '{}' already exists", name)),
+            ),
+            Err(e) => Err(database_error(e)),
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn list(&self) -> Result<Response<ListNamespacesResponse>, Status> {
+        let rows = sqlx::query_as::<_, (String, u32, u32, i64)>(
+            r#"
+            SELECT n.name, n.max_functions, COUNT(d.name), n.created_at
+            FROM namespaces n
+            LEFT JOIN digests d ON d.namespace = n.name
+            GROUP BY n.name
+            ORDER BY n.name
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(database_error)?;
+
+        let namespaces = rows
+            .into_iter()
+            .map(
+                |(name, max_functions, functions, created_at)| NamespaceInfo {
+                    name,
+                    max_functions,
+                    functions,
+                    created_at,
+                },
+            )
+            .collect();
+
+        Ok(Response::new(ListNamespacesResponse { namespaces }))
+    }
+
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn set_quota(
+        &self,
+        name: &str,
+        max_functions: u32,
+    ) -> Result<Response<SetNamespaceQuotaResponse>, Status> {
+        let result = sqlx::query("UPDATE namespaces SET max_functions = ? WHERE name = ?")
+            .bind(max_functions)
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(database_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(not_found(name));
+        }
+
+        info!(max_functions, "Namespace quota updated");
+        Ok(Response::new(SetNamespaceQuotaResponse {}))
+    }
+
+    /// Delete an empty namespace. Functions and keys have to be removed first.
+    #[instrument(skip(self), fields(name = %name))]
+    pub async fn delete(&self, name: &str) -> Result<Response<DeleteNamespaceResponse>, Status> {
+        if name == DEFAULT_NAMESPACE {
+            return Err(Status::failed_precondition(
+                "the default namespace cannot be deleted",
+            ));
+        }
+
+        let (functions, keys) = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM digests WHERE namespace = ?),
+                (SELECT COUNT(*) FROM api_keys WHERE namespace = ?)
+            "#,
+        )
+        .bind(name)
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(database_error)?;
+
+        if functions > 0 || keys > 0 {
+            return Err(Status::failed_precondition(format!(
+                "namespace '{}' still has {} functions and {} API keys",
+                name, functions, keys
+            )));
+        }
+
+        let result = sqlx::query("DELETE FROM namespaces WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(database_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(not_found(name));
+        }
+
+        info!("Namespace deleted");
+        Ok(Response::new(DeleteNamespaceResponse {}))
+    }
+}
+
+fn not_found(name: &str) -> Status {
+    Status::not_found(format!("namespace '{}' not found", name))
+}
+
+fn database_error(e: sqlx::Error) -> Status {
+    error!(error = %e, "Database query failed");
+    Status::internal(format!("Database error: {}", e))
+}
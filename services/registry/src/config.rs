@@ -0,0 +1,234 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use package::Limits;
+use serde::Deserialize;
+use tls::TlsConfig;
+
+use crate::store::S3Config;
+
+const DEFAULT_ADDR: &str = "[::1]:50001";
+const DEFAULT_DATA_DIR: &str = "/var/lib/noctiforge/registry";
+const DEFAULT_CONTROLPLANE_URL: &str = "http://localhost:50002";
+const DEFAULT_GC_GRACE_PERIOD_SECS: u64 = 24 * 60 * 60;
+
+/// Command line arguments. Every flag can also be set through the environment
+/// and takes precedence over the config file.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Path to a TOML config file
+    #[arg(short, long, env = "REGISTRY_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Address the gRPC server listens on
+    #[arg(long, env = "REGISTRY_ADDR")]
+    addr: Option<SocketAddr>,
+
+    /// Directory the package archives are stored in, and uploads are checked in
+    /// with any storage
+    #[arg(long, env = "REGISTRY_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Where package archives are stored
+    #[arg(long, env = "REGISTRY_STORAGE", value_enum)]
+    storage: Option<StorageKind>,
+
+    /// Bucket the archives are stored in with `--storage s3`
+    #[arg(long, env = "S3_BUCKET")]
+    s3_bucket: Option<String>,
+
+    /// URL of an S3-compatible service such as MinIO, instead of AWS
+    #[arg(long, env = "S3_ENDPOINT")]
+    s3_endpoint: Option<String>,
+
+    /// Region of the bucket
+    #[arg(long, env = "S3_REGION")]
+    s3_region: Option<String>,
+
+    /// Key prefix the archives are stored under
+    #[arg(long, env = "S3_PREFIX")]
+    s3_prefix: Option<String>,
+
+    /// URL of the control plane, used to check API keys and deployed digests
+    #[arg(long, env = "REGISTRY_CONTROLPLANE_URL")]
+    controlplane_url: Option<String>,
+
+    /// Require an API key on every request
+    #[arg(long, env = "AUTH_ENABLED")]
+    auth: bool,
+
+    /// API key the registry uses towards the control plane, needs admin in every
+    /// namespace for garbage collection
+    #[arg(long, env = "REGISTRY_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Seconds between garbage collection runs. Off unless set, as it deletes every
+    /// package that is not deployed within `--gc-grace-period` of being pushed
+    #[arg(long, env = "GC_INTERVAL")]
+    gc_interval: Option<u64>,
+
+    /// Seconds an unreferenced package is kept before it is collected
+    #[arg(long, env = "GC_GRACE_PERIOD")]
+    gc_grace_period: Option<u64>,
+
+    /// Largest unpacked size in bytes a pushed package may have
+    #[arg(long, env = "MAX_PACKAGE_SIZE")]
+    max_package_size: Option<u64>,
+
+    /// PEM certificate presented to peers, enables TLS on the server
+    #[arg(long, env = "TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key belonging to `--tls-cert`
+    #[arg(long, env = "TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificate used to verify servers and, with `--tls-client-auth`, clients
+    #[arg(long, env = "TLS_CA")]
+    tls_ca: Option<PathBuf>,
+
+    /// Require clients to present a certificate signed by `--tls-ca`
+    #[arg(long, env = "TLS_CLIENT_AUTH")]
+    tls_client_auth: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    addr: Option<SocketAddr>,
+    data_dir: Option<PathBuf>,
+    storage: Option<StorageKind>,
+    s3_bucket: Option<String>,
+    s3_endpoint: Option<String>,
+    s3_region: Option<String>,
+    s3_prefix: Option<String>,
+    controlplane_url: Option<String>,
+    auth: Option<bool>,
+    api_key: Option<String>,
+    gc_interval: Option<u64>,
+    gc_grace_period: Option<u64>,
+    max_package_size: Option<u64>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tls_ca: Option<PathBuf>,
+    tls_client_auth: Option<bool>,
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum StorageKind {
+    #[default]
+    Local,
+    S3,
+}
+
+pub enum Storage {
+    /// Archives are files in the data directory
+    Local,
+    S3(S3Config),
+}
+
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    pub data_dir: PathBuf,
+    pub storage: Storage,
+    pub controlplane_url: String,
+    pub auth: bool,
+    pub api_key: Option<String>,
+    /// `None` when garbage collection is disabled
+    pub gc_interval: Option<Duration>,
+    pub gc_grace_period: Duration,
+    pub limits: Limits,
+    pub tls: TlsConfig,
+}
+
+impl ServerConfig {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_args(Args::parse())
+    }
+
+    fn from_args(args: Args) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = match &args.config {
+            Some(path) => read_config_file(path)?,
+            None => FileConfig::default(),
+        };
+
+        let addr = match args.addr.or(file.addr) {
+            Some(addr) => addr,
+            None => DEFAULT_ADDR.parse()?,
+        };
+
+        let data_dir = args
+            .data_dir
+            .or(file.data_dir)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
+
+        let storage = match args.storage.or(file.storage).unwrap_or_default() {
+            StorageKind::Local => Storage::Local,
+            StorageKind::S3 => Storage::S3(S3Config {
+                bucket: args
+                    .s3_bucket
+                    .or(file.s3_bucket)
+                    .ok_or("--s3-bucket is required with --storage s3")?,
+                endpoint: args.s3_endpoint.or(file.s3_endpoint),
+                region: args.s3_region.or(file.s3_region),
+                prefix: args.s3_prefix.or(file.s3_prefix),
+            }),
+        };
+
+        let controlplane_url = args
+            .controlplane_url
+            .or(file.controlplane_url)
+            .unwrap_or_else(|| DEFAULT_CONTROLPLANE_URL.to_string());
+
+        let gc_interval = args
+            .gc_interval
+            .or(file.gc_interval)
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs);
+
+        let gc_grace_period = Duration::from_secs(
+            args.gc_grace_period
+                .or(file.gc_grace_period)
+                .unwrap_or(DEFAULT_GC_GRACE_PERIOD_SECS),
+        );
+
+        let mut limits = Limits::default();
+        if let Some(max) = args.max_package_size.or(file.max_package_size) {
+            limits.max_total_size = max;
+        }
+
+        let tls = TlsConfig {
+            cert: args.tls_cert.or(file.tls_cert),
+            key: args.tls_key.or(file.tls_key),
+            ca: args.tls_ca.or(file.tls_ca),
+            require_client_cert: args.tls_client_auth || file.tls_client_auth.unwrap_or(false),
+        };
+
+        Ok(Self {
+            addr,
+            data_dir,
+            storage,
+            controlplane_url,
+            auth: args.auth || file.auth.unwrap_or(false),
+            api_key: args.api_key.or(file.api_key),
+            gc_interval,
+            gc_grace_period,
+            limits,
+            tls,
+        })
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<FileConfig, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+    let config = toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse config file {}: {}", path.display(), e))?;
+    Ok(config)
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use auth::ClientAuth;
+use proto::api::controlplane::{
+    ListReferencedDigestsRequest, control_plane_service_client::ControlPlaneServiceClient,
+};
+use tokio::time::interval;
+use tonic::{Request, transport::Channel};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::store::{BlobMeta, BlobStore};
+
+/// Mark and sweep collection of packages no function points at anymore
+pub struct GarbageCollector {
+    store: Arc<dyn BlobStore>,
+    controlplane: Channel,
+    auth: ClientAuth,
+    /// Packages younger than this are kept, they may be pushed but not deployed yet
+    grace_period: Duration,
+}
+
+impl GarbageCollector {
+    pub fn new(
+        store: Arc<dyn BlobStore>,
+        controlplane: Channel,
+        auth: ClientAuth,
+        grace_period: Duration,
+    ) -> Self {
+        Self {
+            store,
+            controlplane,
+            auth,
+            grace_period,
+        }
+    }
+
+    /// Collect every `period` until the process exits
+    pub fn spawn(self, period: Duration) {
+        info!(
+            period_secs = period.as_secs(),
+            grace_period_secs = self.grace_period.as_secs(),
+            "Starting garbage collector"
+        );
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                ticker.tick().await;
+                if let Err(err) = self.collect().await {
+                    error!(error = %err, "Garbage collection failed");
+                }
+            }
+        });
+    }
+
+    /// Delete unreferenced packages, and chunks none of the remaining packages is
+    /// made of, older than the grace period and return how many packages were
+    /// deleted. Nothing is deleted when the referenced digests are unknown.
+    #[instrument(skip(self))]
+    pub async fn collect(&self) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        // Listed before asking the control plane, so a package pushed and deployed
+        // in between is never considered.
+        let stored = self.store.list().await?;
+
+        let mut client = ControlPlaneServiceClient::with_interceptor(
+            self.controlplane.clone(),
+            self.auth.clone(),
+        );
+        let referenced: HashSet<String> = client
+            .list_referenced_digests(Request::new(ListReferencedDigestsRequest {}))
+            .await?
+            .into_inner()
+            .digests
+            .into_iter()
+            .collect();
+
+        self.sweep(stored, &referenced).await
+    }
+
+    /// Delete what `collect` would, knowing the `stored` packages and the
+    /// `referenced` digests
+    async fn sweep(
+        &self,
+        stored: Vec<BlobMeta>,
+        referenced: &HashSet<String>,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let now = SystemTime::now();
+        let mut deleted = 0;
+        let mut kept: HashSet<&String> = stored.iter().map(|blob| &blob.digest).collect();
+        for blob in stored
+            .iter()
+            .filter(|blob| !referenced.contains(&blob.digest))
+        {
+            let digest = &blob.digest;
+            if self.is_recent(now, blob.modified) {
+                debug!(digest = %digest, "Keeping recent package");
+                continue;
+            }
+            // Pushed again or checked with `Has` since the listing, about to be deployed.
+            match self.store.stat(digest).await {
+                Ok(Some(current)) if self.is_recent(now, current.modified) => {
+                    debug!(digest = %digest, "Keeping package touched since listing");
+                    continue;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!(digest = %digest, error = %err, "Failed to check package");
+                    continue;
+                }
+            }
+
+            match self.store.delete(digest).await {
+                Ok(true) => {
+                    info!(digest = %digest, "Collected unreferenced package");
+                    kept.remove(digest);
+                    deleted += 1;
+                }
+                Ok(false) => {}
+                Err(err) => warn!(digest = %digest, error = %err, "Failed to collect package"),
+            }
+        }
+
+        // Chunks of the packages still stored are reachable, they make pushing a
+        // new version cheap. Others are kept for the grace period, an interrupted
+        // push resumes from them.
+        let mut reachable = HashSet::new();
+        for blob in &stored {
+            if kept.contains(&blob.digest) {
+                reachable.extend(self.store.chunk_list(&blob.digest).await?);
+            }
+        }
+        let mut deleted_chunks = 0;
+        for chunk in self.store.list_chunks().await? {
+            if reachable.contains(&chunk.hash) || self.is_recent(now, chunk.modified) {
+                continue;
+            }
+            match self.store.delete_chunk(&chunk.hash).await {
+                Ok(()) => deleted_chunks += 1,
+                Err(err) => warn!(hash = %chunk.hash, error = %err, "Failed to collect chunk"),
+            }
+        }
+
+        info!(
+            stored = stored.len(),
+            referenced = referenced.len(),
+            deleted,
+            deleted_chunks,
+            "Garbage collection finished"
+        );
+        Ok(deleted)
+    }
+
+    fn is_recent(&self, now: SystemTime, modified: SystemTime) -> bool {
+        now.duration_since(modified).unwrap_or_default() < self.grace_period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::Path};
+
+    use package::Compression;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::store::LocalStore;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn digest(n: u8) -> String {
+        format!("v2:{}", format!("{:02x}", n).repeat(32))
+    }
+
+    fn chunk(n: u8) -> String {
+        format!("{:02x}", n).repeat(32)
+    }
+
+    fn collector(store: Arc<LocalStore>, grace_period: Duration) -> GarbageCollector {
+        GarbageCollector::new(
+            store,
+            Channel::from_static("http://127.0.0.1:1").connect_lazy(),
+            ClientAuth::new(None).unwrap(),
+            grace_period,
+        )
+    }
+
+    async fn put(store: &LocalStore, dir: &Path, digest: &str, chunks: &[String]) {
+        let mut upload = tempfile::NamedTempFile::new_in(dir).unwrap();
+        upload.write_all(b"archive").unwrap();
+        store.put(digest, Compression::Gzip, upload).await.unwrap();
+        for hash in chunks {
+            store.put_chunk(hash, b"chunk".to_vec()).await.unwrap();
+        }
+        store.put_chunk_list(digest, chunks).await.unwrap();
+    }
+
+    /// Set the modification time of every file in and below `dir` to `age` ago
+    fn set_age(dir: &Path, age: Duration) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                set_age(&path, age);
+            } else {
+                std::fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(SystemTime::now() - age)
+                    .unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sweeps_unreferenced_after_grace_period() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(LocalStore::new(dir.path().to_path_buf()));
+        put(&store, dir.path(), &digest(1), &[chunk(1), chunk(2)]).await;
+        put(&store, dir.path(), &digest(2), &[chunk(2), chunk(3)]).await;
+        store
+            .put_chunk(&chunk(4), b"orphan".to_vec())
+            .await
+            .unwrap();
+        let referenced = HashSet::from([digest(1)]);
+
+        // Everything is within the grace period.
+        let gc = collector(store.clone(), HOUR);
+        assert_eq!(
+            gc.sweep(store.list().await.unwrap(), &referenced)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(store.list_chunks().await.unwrap().len(), 4);
+
+        set_age(dir.path(), 2 * HOUR);
+        assert_eq!(
+            gc.sweep(store.list().await.unwrap(), &referenced)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(store.stat(&digest(1)).await.unwrap().is_some());
+        assert!(store.stat(&digest(2)).await.unwrap().is_none());
+        // Chunks of the referenced package stay, the others go.
+        let mut chunks: Vec<_> = store
+            .list_chunks()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|chunk| chunk.hash)
+            .collect();
+        chunks.sort();
+        assert_eq!(chunks, [chunk(1), chunk(2)]);
+    }
+
+    #[tokio::test]
+    async fn test_keeps_package_touched_since_listing() {
+        let dir = TempDir::new().unwrap();
+        let store = Arc::new(LocalStore::new(dir.path().to_path_buf()));
+        put(&store, dir.path(), &digest(1), &[chunk(1)]).await;
+        set_age(dir.path(), 2 * HOUR);
+
+        let stored = store.list().await.unwrap();
+        store.touch(&stored[0]).await.unwrap();
+        let gc = collector(store.clone(), HOUR);
+        assert_eq!(gc.sweep(stored, &HashSet::new()).await.unwrap(), 0);
+        assert!(store.stat(&digest(1)).await.unwrap().is_some());
+        assert!(store.has_chunk(&chunk(1)).await.unwrap());
+    }
+}
@@ -1,22 +1,92 @@
+use std::{sync::Arc, time::Duration};
+
+use auth::{ClientAuth, RemoteAuthorizer, ServerAuth};
 use proto::api::registry::registry_service_server::RegistryServiceServer;
 use tonic::transport::Server;
 use tracing::info;
 
-mod path;
+mod config;
+mod gc;
 mod registry;
+mod store;
+
+/// How long a successful API key check is trusted before asking the control plane again
+const AUTH_CACHE_TTL: Duration = Duration::from_secs(30);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt().with_target(false).init();
 
-    let addr = "[::1]:50001".parse().unwrap();
-    let file_engine = registry::LocalBackend::default();
+    let config = config::ServerConfig::load()?;
+
+    tokio::fs::create_dir_all(&config.data_dir)
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to create data directory {}: {}",
+                config.data_dir.display(),
+                e
+            )
+        })?;
+
+    // Used to check API keys and which packages are still deployed. It connects on
+    // first use.
+    let controlplane = config
+        .tls
+        .endpoint(config.controlplane_url.clone())?
+        .connect_lazy();
+    let authorizer = config
+        .auth
+        .then(|| RemoteAuthorizer::new(controlplane.clone(), AUTH_CACHE_TTL));
+
+    let store: Arc<dyn store::BlobStore> = match &config.storage {
+        config::Storage::Local => {
+            info!("Storing packages at: {}", config.data_dir.display());
+            Arc::new(store::LocalStore::new(config.data_dir.clone()))
+        }
+        config::Storage::S3(s3) => {
+            info!("Storing packages in bucket: {}", s3.bucket);
+            Arc::new(store::S3Store::new(s3)?)
+        }
+    };
+
+    if let Some(period) = config.gc_interval {
+        let auth = ClientAuth::new(config.api_key.as_deref())?;
+        gc::GarbageCollector::new(
+            store.clone(),
+            controlplane.clone(),
+            auth,
+            config.gc_grace_period,
+        )
+        .spawn(period);
+    }
+
+    let file_engine = registry::Registry::new(
+        config.data_dir.clone(),
+        store,
+        controlplane,
+        authorizer,
+        config.limits,
+    );
+
+    info!("RegistryServiceServer listening on {}", config.addr);
+    info!("API key authentication enabled: {}", config.auth);
 
-    info!("RegistryServiceServer listening on {}", addr);
+    let mut server = Server::builder();
+    if let Some(tls) = config.tls.server()? {
+        info!(
+            "TLS enabled, client certificates required: {}",
+            config.tls.require_client_cert
+        );
+        server = server.tls_config(tls)?;
+    }
 
-    Server::builder()
-        .add_service(RegistryServiceServer::new(file_engine))
-        .serve(addr)
+    server
+        .add_service(RegistryServiceServer::with_interceptor(
+            file_engine,
+            ServerAuth::new(config.auth),
+        ))
+        .serve(config.addr)
         .await?;
 
     Ok(())
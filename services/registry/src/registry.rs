@@ -1,28 +1,262 @@
-use std::{io::Cursor, pin::Pin};
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Component, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
 
-use proto::api::registry::{
-    RegistryPullRequest, RegistryPullResponse, RegistryPushRequest, RegistryPushResponse,
-    registry_service_server::RegistryService,
+use auth::{ALL_NAMESPACES, ApiKey, ClientAuth, RemoteAuthorizer, Scope};
+use package::{
+    BoxedReader, CURRENT_VERSION, Compression, Digest, Limits, MAX_CHUNK_SIZE, Signature,
+    chunk_hash, compute_digest, is_chunk_hash, validate_archive,
+};
+use proto::api::{
+    controlplane::{
+        ListReferencedDigestsRequest, control_plane_service_client::ControlPlaneServiceClient,
+    },
+    registry::{
+        PackageInfo, RegistryCommitRequest, RegistryDeleteRequest, RegistryDeleteResponse,
+        RegistryHasRequest, RegistryHasResponse, RegistryListRequest, RegistryListResponse,
+        RegistryMissingChunksRequest, RegistryMissingChunksResponse, RegistryPullRequest,
+        RegistryPullResponse, RegistryPushRequest, RegistryPushResponse, RegistryStatRequest,
+        RegistryStatResponse, RegistryUploadChunk, RegistryUploadChunksResponse,
+        registry_service_server::RegistryService,
+    },
 };
-use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
 use tokio::{
-    fs::{read, write},
-    io::AsyncReadExt,
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, copy, duplex},
+    task::{JoinHandle, JoinSet},
 };
 use tokio_stream::{Stream, StreamExt};
 use tokio_tar::Archive;
-use tonic::{Request, Response, Result, Status, Streaming};
+use tokio_util::io::ReaderStream;
+use tonic::{Request, Response, Result, Status, Streaming, transport::Channel};
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::path::get_registry_path;
+use crate::store::{BlobMeta, BlobStore};
 
 const CHUNK_SIZE: usize = 64 * 1024;
+/// Bytes buffered between the upload and the task hashing it, and between the
+/// stored archive and a pull converting its compression
+const HASH_BUFFER_SIZE: usize = 256 * 1024;
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+/// Chunk lookups in flight at once, each is a request with object storage
+const CHUNK_LOOKUPS: usize = 16;
+
+pub struct Registry {
+    /// Uploads are written here while they are checked
+    upload_dir: PathBuf,
+    store: Arc<dyn BlobStore>,
+    controlplane: Channel,
+    authorizer: Option<RemoteAuthorizer>,
+    limits: Limits,
+}
+
+impl Registry {
+    pub fn new(
+        upload_dir: PathBuf,
+        store: Arc<dyn BlobStore>,
+        controlplane: Channel,
+        authorizer: Option<RemoteAuthorizer>,
+        limits: Limits,
+    ) -> Self {
+        Self {
+            upload_dir,
+            store,
+            controlplane,
+            authorizer,
+            limits,
+        }
+    }
+
+    /// Packages are not tied to a namespace, a key valid in any namespace can read
+    /// them while deleting one needs a key valid in all of them
+    async fn check_access<T>(
+        &self,
+        request: &Request<T>,
+        namespace: &str,
+        scope: Scope,
+    ) -> Result<(), Status> {
+        match &self.authorizer {
+            Some(authorizer) => authorizer.authorize(request, namespace, scope, "").await,
+            None => Ok(()),
+        }
+    }
+
+    /// Stored archive of `digest`
+    async fn find(&self, digest: &str) -> Result<BlobMeta, Status> {
+        self.store
+            .stat(digest)
+            .await
+            .map_err(read_error)?
+            .ok_or_else(|| Status::not_found(format!("digest {} not found", digest)))
+    }
+
+    async fn touch(&self, blob: &BlobMeta) -> Result<(), Status> {
+        self.store.touch(blob).await.map_err(|err| {
+            error!(digest = %blob.digest, error = %err, "Failed to touch archive");
+            Status::internal(format!("failed to touch archive: {:?}", err))
+        })
+    }
+
+    async fn open(&self, blob: &BlobMeta) -> Result<BoxedReader<'static>, Status> {
+        self.store.open(blob).await.map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => {
+                Status::not_found(format!("digest {} not found", blob.digest))
+            }
+            _ => read_error(err),
+        })
+    }
+
+    /// Removed on drop, so a failed upload never leaves a partial file behind
+    fn upload_file(&self) -> Result<(NamedTempFile, File), Status> {
+        let temp = tempfile::Builder::new()
+            .prefix(".upload-")
+            .suffix(".tar")
+            .tempfile_in(&self.upload_dir)
+            .map_err(|err| {
+                error!(error = %err, "Failed to create upload file");
+                Status::internal(format!("failed to create upload file: {:?}", err))
+            })?;
+        let file =
+            File::from_std(temp.reopen().map_err(|err| {
+                Status::internal(format!("failed to open upload file: {:?}", err))
+            })?);
+        Ok((temp, file))
+    }
 
-#[derive(Default)]
-pub struct LocalBackend {}
+    /// Check a received archive and store it under the digest `hash_task` computed,
+    /// along with `signature` if the package was signed. `archive_size` is the
+    /// compressed size the unpacked size is compared with.
+    async fn store_upload(
+        &self,
+        temp: NamedTempFile,
+        compression: Compression,
+        archive_size: u64,
+        hash_task: JoinHandle<std::io::Result<Digest>>,
+        signature: Option<Signature>,
+    ) -> Result<String, Status> {
+        debug!("Waiting for digest");
+        let computed = hash_task
+            .await
+            .map_err(|err| Status::internal(format!("digest task failed: {}", err)))?
+            .map_err(|err| {
+                error!(error = %err, "Invalid tar archive received");
+                Status::invalid_argument(format!("invalid tar archive: {}", err))
+            })?;
+        let digest = computed.to_string();
+
+        info!(digest = %digest, "Computed digest successfully");
+
+        // Only checks that the signature belongs to this package, workers decide
+        // which keys they trust.
+        if let Some(signature) = &signature {
+            signature.verify(&computed).map_err(|err| {
+                warn!(digest = %digest, key = %signature.public_key(), "Rejected signature");
+                Status::invalid_argument(format!("rejected signature: {}", err))
+            })?;
+        }
+
+        debug!("Validating archive");
+        let archive = File::open(temp.path())
+            .await
+            .map_err(|err| Status::internal(format!("failed to open upload file: {:?}", err)))?;
+        validate_archive(
+            Archive::new(compression.decoder(BufReader::new(archive))),
+            archive_size,
+            &self.limits,
+        )
+        .await
+        .map_err(|err| {
+            warn!(digest = %digest, error = %err, "Rejected unsafe archive");
+            Status::invalid_argument(format!("rejected archive: {}", err))
+        })?;
+
+        if let Ok(blob) = self.find(&digest).await {
+            info!(digest = %digest, "Digest already exists in registry, skipping write");
+            self.touch(&blob).await?;
+            self.add_signature(&digest, signature).await?;
+            return Ok(digest);
+        }
+
+        debug!(
+            digest = %digest,
+            compression = %compression,
+            size_bytes = archive_size,
+            "Storing archive"
+        );
+        self.store
+            .put(&digest, compression, temp)
+            .await
+            .map_err(|err| {
+                error!(digest = %digest, error = %err, "Failed to store archive");
+                Status::internal(format!("failed to write store path: {:?}", err))
+            })?;
+
+        info!(digest = %digest, "Successfully written to registry");
+        self.add_signature(&digest, signature).await?;
+        Ok(digest)
+    }
+
+    /// Keep `signature` next to the package, once per key
+    async fn add_signature(
+        &self,
+        digest: &str,
+        signature: Option<Signature>,
+    ) -> Result<(), Status> {
+        let Some(signature) = signature else {
+            return Ok(());
+        };
+        let mut signatures = self.store.signatures(digest).await.map_err(read_error)?;
+        if signatures
+            .iter()
+            .filter_map(|stored| stored.parse::<Signature>().ok())
+            .any(|stored| stored.public_key() == signature.public_key())
+        {
+            debug!(digest = %digest, key = %signature.public_key(), "Package already signed by key");
+            return Ok(());
+        }
+
+        signatures.push(signature.to_string());
+        self.store
+            .put_signatures(digest, &signatures)
+            .await
+            .map_err(write_error)?;
+        info!(digest = %digest, key = %signature.public_key(), "Stored package signature");
+        Ok(())
+    }
+
+    /// Ask the control plane which digests are deployed, on behalf of the caller
+    async fn referenced_digests<T>(&self, request: &Request<T>) -> Result<Vec<String>, Status> {
+        let key = request
+            .extensions()
+            .get::<ApiKey>()
+            .map(|key| key.0.as_str());
+        let auth =
+            ClientAuth::new(key).map_err(|_| Status::unauthenticated("malformed API key"))?;
+        let mut client =
+            ControlPlaneServiceClient::with_interceptor(self.controlplane.clone(), auth);
+
+        let response = client
+            .list_referenced_digests(Request::new(ListReferencedDigestsRequest {}))
+            .await
+            .map_err(|err| {
+                warn!(error = %err, "Failed to list referenced digests");
+                Status::unavailable(format!(
+                    "cannot check whether the digest is in use: {}",
+                    err.message()
+                ))
+            })?;
+        Ok(response.into_inner().digests)
+    }
+}
 
 #[tonic::async_trait]
-impl RegistryService for LocalBackend {
+impl RegistryService for Registry {
     type PullStream =
         Pin<Box<dyn Stream<Item = Result<RegistryPullResponse, Status>> + Send + 'static>>;
 
@@ -35,39 +269,71 @@ impl RegistryService for LocalBackend {
         &self,
         request: Request<RegistryPullRequest>,
     ) -> Result<Response<Self::PullStream>, Status> {
+        self.check_access(&request, "", Scope::Invoke).await?;
         let req = request.into_inner();
-        let request_path = get_registry_path(&req.digest);
+        let digest = parse_digest(&req.digest)?.to_string();
+        let blob = self.find(&digest).await?;
+        let stored = blob.compression;
 
-        debug!(path = %request_path.display(), "Reading tar from registry path");
+        // Unknown formats are skipped, a client asking for nothing gets a plain tar.
+        let accept: Vec<Compression> = req
+            .accept
+            .iter()
+            .filter_map(|&value| Compression::try_from(value).ok())
+            .collect();
+        let compression = match accept.contains(&stored) {
+            true => stored,
+            false => accept.first().copied().unwrap_or_default(),
+        };
 
-        let data = read(&request_path).await.map_err(|err| {
-            error!(
-                path = %request_path.display(),
-                error = %err,
-                "Failed to read from registry path"
-            );
-            Status::internal(format!("failed to read store path: {:?}", err))
-        })?;
-        let data_size = data.len();
-        let chunk_count = data_size.div_ceil(CHUNK_SIZE);
+        let signatures = self.store.signatures(&digest).await.map_err(read_error)?;
+
+        debug!("Opening stored archive");
+        let archive = self.open(&blob).await?;
+        let size_bytes = blob.size;
 
         info!(
-            digest = %req.digest,
-            size_bytes = data_size,
-            chunk_count = chunk_count,
+            digest = %digest,
+            size_bytes,
+            stored = %stored,
+            compression = %compression,
             chunk_size = CHUNK_SIZE,
-            "Successfully read tar, streaming chunks"
+            "Streaming tar chunks"
         );
 
-        let stream = tokio_stream::iter(
-            data.chunks(CHUNK_SIZE)
-                .map(|chunk| {
-                    Ok(RegistryPullResponse {
-                        data: chunk.to_vec(),
-                    })
+        let reader = if compression == stored {
+            archive
+        } else {
+            // Converted while streaming, the stored archive is left as it is.
+            let (writer, reader) = duplex(HASH_BUFFER_SIZE);
+            tokio::spawn(async move {
+                let mut decoder = stored.decoder(BufReader::new(archive));
+                let mut encoder = compression.encoder(writer);
+                let result = match copy(&mut decoder, &mut encoder).await {
+                    Ok(_) => encoder.shutdown().await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    warn!(error = %err, "Failed to convert compression");
+                }
+            });
+            Box::pin(reader) as BoxedReader<'static>
+        };
+
+        let wire_compression: i32 = compression.into();
+        let mut signatures = Some(signatures);
+        let stream = ReaderStream::with_capacity(reader, CHUNK_SIZE).map(move |chunk| {
+            chunk
+                .map(|data| RegistryPullResponse {
+                    data: data.to_vec(),
+                    compression: wire_compression,
+                    signatures: signatures.take().unwrap_or_default(),
                 })
-                .collect::<Vec<_>>(),
-        );
+                .map_err(|err| {
+                    error!(error = %err, "Failed to read tar chunk");
+                    Status::internal(format!("failed to read store path: {:?}", err))
+                })
+        });
 
         Ok(Response::new(Box::pin(stream)))
     }
@@ -77,117 +343,504 @@ impl RegistryService for LocalBackend {
         &self,
         request: Request<Streaming<RegistryPushRequest>>,
     ) -> Result<Response<RegistryPushResponse>, Status> {
+        self.check_access(&request, "", Scope::Push).await?;
         debug!("Starting to receive push stream");
 
-        let mut request_data: Vec<u8> = vec![];
+        let (temp, mut file) = self.upload_file()?;
+
         let mut request_stream = request.into_inner();
+        let first = match request_stream.next().await {
+            Some(first) => first.map_err(|err| {
+                error!(error = %err, "Failed to receive stream chunk");
+                Status::internal(err.to_string())
+            })?,
+            None => {
+                warn!("Received empty data");
+                return Err(Status::invalid_argument("missing `data` field"));
+            }
+        };
+        let compression =
+            Compression::try_from(first.compression).map_err(Status::invalid_argument)?;
+        let signature = parse_signature(&first.signature)?;
+        debug!(compression = %compression, signed = signature.is_some(), "Receiving package");
+
+        // The archive is hashed while it is received instead of being read back.
+        let (hash_writer, hash_task) = spawn_hasher(compression, self.limits);
+        let mut hash_writer = Some(hash_writer);
+
+        // Checked while receiving, so an oversized upload never fills the disk.
+        let max_size = self.limits.max_compressed_size();
         let mut chunk_count = 0;
+        let mut total_bytes = 0;
+        let mut next: Option<Result<RegistryPushRequest, Status>> = Some(Ok(first));
 
-        while let Some(request) = request_stream.next().await {
+        while let Some(request) = next {
             let request = request.map_err(|err| {
                 error!(error = %err, "Failed to receive stream chunk");
                 Status::internal(err.to_string())
             })?;
 
             chunk_count += 1;
-            request_data.extend_from_slice(&request.data);
+            total_bytes += request.data.len() as u64;
+            if total_bytes > max_size {
+                warn!(total_bytes, "Rejected oversized upload");
+                return Err(too_large(max_size));
+            }
+            file.write_all(&request.data).await.map_err(write_error)?;
+            feed_hasher(&mut hash_writer, &request.data).await;
 
             if chunk_count % 10 == 0 {
                 debug!(
                     chunks_received = chunk_count,
-                    total_bytes = request_data.len(),
-                    "Receiving data..."
+                    total_bytes, "Receiving data..."
                 );
             }
+            next = request_stream.next().await;
         }
 
         info!(
             total_chunks = chunk_count,
-            total_bytes = request_data.len(),
-            "Completed receiving all chunks"
+            total_bytes, "Completed receiving all chunks"
         );
 
-        if request_data.is_empty() {
+        // Closing the pipe lets the hashing task see the end of the archive.
+        drop(hash_writer);
+        file.flush().await.map_err(write_error)?;
+
+        if total_bytes == 0 {
             warn!("Received empty data");
             return Err(Status::invalid_argument("missing `data` field"));
         }
 
-        debug!("Validating tar archive");
-        let cursor = Cursor::new(&request_data);
-        let mut archive = Archive::new(cursor);
-        if let Err(err) = archive.entries() {
-            error!(error = %err, "Invalid tar archive received");
+        drop(file);
+        let digest = self
+            .store_upload(temp, compression, total_bytes, hash_task, signature)
+            .await?;
+        Ok(Response::new(RegistryPushResponse { digest }))
+    }
+
+    #[instrument(name = "Registry missing chunks", skip(self, request))]
+    async fn missing_chunks(
+        &self,
+        request: Request<RegistryMissingChunksRequest>,
+    ) -> Result<Response<RegistryMissingChunksResponse>, Status> {
+        self.check_access(&request, "", Scope::Push).await?;
+        let chunks = request.into_inner().chunks;
+        if let Some(hash) = chunks.iter().find(|hash| !is_chunk_hash(hash)) {
             return Err(Status::invalid_argument(format!(
-                "invalid tar archive: {}",
-                err
+                "invalid chunk hash '{}'",
+                hash
             )));
         }
 
-        debug!("Computing digest");
-        let digest = get_digest(archive).await.map_err(|err| {
-            error!(error = %err, "Failed to compute digest");
-            err
-        })?;
+        let mut seen = HashSet::new();
+        let requested: Vec<String> = chunks
+            .into_iter()
+            .filter(|hash| seen.insert(hash.clone()))
+            .collect();
+        let stored = stored_chunks(&self.store, &requested)
+            .await
+            .map_err(read_error)?;
+        let missing: Vec<String> = requested
+            .into_iter()
+            .filter(|hash| !stored.contains(hash))
+            .collect();
 
-        info!(digest = %digest, "Computed digest successfully");
+        debug!(
+            stored = stored.len(),
+            missing = missing.len(),
+            "Checked chunks"
+        );
+        Ok(Response::new(RegistryMissingChunksResponse { missing }))
+    }
 
-        let request_path = get_registry_path(&digest);
+    #[instrument(name = "Registry upload chunks", skip(self, request))]
+    async fn upload_chunks(
+        &self,
+        request: Request<Streaming<RegistryUploadChunk>>,
+    ) -> Result<Response<RegistryUploadChunksResponse>, Status> {
+        self.check_access(&request, "", Scope::Push).await?;
+        let mut request_stream = request.into_inner();
 
-        if request_path.exists() {
-            info!(
-                digest = %digest,
-                path = %request_path.display(),
-                "Digest already exists in registry, skipping write"
-            );
-        } else {
-            debug!(
-                digest = %digest,
-                path = %request_path.display(),
-                size_bytes = request_data.len(),
-                "Writing tar to registry"
-            );
-
-            write(&request_path, &request_data).await.map_err(|err| {
-                error!(
-                    path = %request_path.display(),
-                    error = %err,
-                    "Failed to write to registry path"
-                );
-                Status::internal(format!("failed to write store path: {:?}", err))
+        // Every chunk is stored as soon as it is checked, so an interrupted upload
+        // keeps what was sent.
+        let mut stored = 0;
+        while let Some(chunk) = request_stream.next().await {
+            let chunk = chunk.map_err(|err| {
+                error!(error = %err, "Failed to receive stream chunk");
+                Status::internal(err.to_string())
             })?;
+            if !is_chunk_hash(&chunk.hash) {
+                return Err(Status::invalid_argument(format!(
+                    "invalid chunk hash '{}'",
+                    chunk.hash
+                )));
+            }
+            let compression =
+                Compression::try_from(chunk.compression).map_err(Status::invalid_argument)?;
+
+            let mut data = Vec::new();
+            compression
+                .decoder(&chunk.data[..])
+                .take(MAX_CHUNK_SIZE as u64 + 1)
+                .read_to_end(&mut data)
+                .await
+                .map_err(|err| {
+                    Status::invalid_argument(format!("invalid chunk {}: {}", chunk.hash, err))
+                })?;
+            if data.len() > MAX_CHUNK_SIZE as usize {
+                return Err(Status::invalid_argument(format!(
+                    "chunk {} is larger than {} bytes",
+                    chunk.hash, MAX_CHUNK_SIZE
+                )));
+            }
+            if chunk_hash(&data) != chunk.hash {
+                warn!(hash = %chunk.hash, "Chunk does not match its hash");
+                return Err(Status::invalid_argument(format!(
+                    "chunk {} does not match its hash",
+                    chunk.hash
+                )));
+            }
+
+            self.store
+                .put_chunk(&chunk.hash, data)
+                .await
+                .map_err(write_error)?;
+            stored += 1;
+        }
 
-            info!(
-                digest = %digest,
-                path = %request_path.display(),
-                "Successfully written to registry"
-            );
+        info!(stored, "Stored chunks");
+        Ok(Response::new(RegistryUploadChunksResponse { stored }))
+    }
+
+    #[instrument(
+        name = "Registry commit",
+        skip(self, request),
+        fields(chunks = request.get_ref().chunks.len())
+    )]
+    async fn commit(
+        &self,
+        request: Request<RegistryCommitRequest>,
+    ) -> Result<Response<RegistryPushResponse>, Status> {
+        self.check_access(&request, "", Scope::Push).await?;
+        let req = request.into_inner();
+        let compression =
+            Compression::try_from(req.compression).map_err(Status::invalid_argument)?;
+        let signature = parse_signature(&req.signature)?;
+        if req.chunks.is_empty() {
+            return Err(Status::invalid_argument("missing `chunks` field"));
+        }
+        if let Some(hash) = req.chunks.iter().find(|hash| !is_chunk_hash(hash)) {
+            return Err(Status::invalid_argument(format!(
+                "invalid chunk hash '{}'",
+                hash
+            )));
         }
 
+        // The same chunk may be listed many times, so the size is checked while
+        // assembling rather than only when validating.
+        let max_size = self.limits.max_archive_size();
+
+        let (temp, file) = self.upload_file()?;
+        let (hash_writer, hash_task) = spawn_hasher(Compression::None, self.limits);
+        let mut hash_writer = Some(hash_writer);
+        let mut encoder = compression.encoder(file);
+
+        let mut total_bytes = 0;
+        for hash in &req.chunks {
+            let data = self
+                .store
+                .get_chunk(hash)
+                .await
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::NotFound => Status::failed_precondition(format!(
+                        "chunk {} is missing, upload it first",
+                        hash
+                    )),
+                    _ => read_error(err),
+                })?;
+            total_bytes += data.len() as u64;
+            if total_bytes > max_size {
+                warn!(total_bytes, "Rejected oversized chunk list");
+                return Err(too_large(max_size));
+            }
+            encoder.write_all(&data).await.map_err(write_error)?;
+            feed_hasher(&mut hash_writer, &data).await;
+        }
+
+        // Closing the pipe lets the hashing task see the end of the archive.
+        drop(hash_writer);
+        encoder.shutdown().await.map_err(write_error)?;
+        drop(encoder);
+
+        let archive_size = temp
+            .as_file()
+            .metadata()
+            .map_err(|err| Status::internal(format!("failed to read upload file: {:?}", err)))?
+            .len();
+        info!(
+            total_bytes,
+            archive_size,
+            compression = %compression,
+            "Assembled archive from chunks"
+        );
+
+        let digest = self
+            .store_upload(temp, compression, archive_size, hash_task, signature)
+            .await?;
+        // Garbage collection keeps the chunks of stored packages for later pushes,
+        // it only knows them from this list.
+        self.store
+            .put_chunk_list(&digest, &req.chunks)
+            .await
+            .map_err(|err| {
+                error!(digest = %digest, error = %err, "Failed to store chunk list");
+                Status::internal(format!("failed to store chunk list: {:?}", err))
+            })?;
         Ok(Response::new(RegistryPushResponse { digest }))
     }
+
+    #[instrument(
+        name = "Registry has",
+        skip(self, request),
+        fields(digest = %request.get_ref().digest)
+    )]
+    async fn has(
+        &self,
+        request: Request<RegistryHasRequest>,
+    ) -> Result<Response<RegistryHasResponse>, Status> {
+        self.check_access(&request, "", Scope::Push).await?;
+        let req = request.into_inner();
+        let digest = parse_digest(&req.digest)?;
+        let signature = parse_signature(&req.signature)?;
+        let key = digest.to_string();
+
+        let blob = self.store.stat(&key).await.map_err(read_error)?;
+        let exists = blob.is_some();
+        if let Some(blob) = blob {
+            // The client skips the upload and deploys next, GC must not take the
+            // package in between.
+            self.touch(&blob).await?;
+            if let Some(signature) = &signature {
+                signature.verify(&digest).map_err(|err| {
+                    warn!(digest = %key, key = %signature.public_key(), "Rejected signature");
+                    Status::invalid_argument(format!("rejected signature: {}", err))
+                })?;
+            }
+            self.add_signature(&key, signature).await?;
+        }
+
+        debug!(exists, "Checked digest");
+        Ok(Response::new(RegistryHasResponse { exists }))
+    }
+
+    #[instrument(name = "Registry list", skip(self, request))]
+    async fn list(
+        &self,
+        request: Request<RegistryListRequest>,
+    ) -> Result<Response<RegistryListResponse>, Status> {
+        self.check_access(&request, "", Scope::Invoke).await?;
+        let req = request.into_inner();
+        let page_size = match req.page_size as usize {
+            0 => DEFAULT_PAGE_SIZE,
+            size => size.min(MAX_PAGE_SIZE),
+        };
+
+        let blobs = self.store.list().await.map_err(read_error)?;
+        let start = blobs.partition_point(|blob| blob.digest.as_str() <= req.page_token.as_str());
+        let page = &blobs[start..blobs.len().min(start + page_size)];
+        let packages: Vec<PackageInfo> = page.iter().map(package_info).collect();
+
+        let next_page_token = match start + page.len() < blobs.len() {
+            true => page
+                .last()
+                .map(|blob| blob.digest.clone())
+                .unwrap_or_default(),
+            false => String::new(),
+        };
+
+        debug!(packages = packages.len(), "Listed packages");
+        Ok(Response::new(RegistryListResponse {
+            packages,
+            next_page_token,
+        }))
+    }
+
+    #[instrument(
+        name = "Registry stat",
+        skip(self, request),
+        fields(digest = %request.get_ref().digest)
+    )]
+    async fn stat(
+        &self,
+        request: Request<RegistryStatRequest>,
+    ) -> Result<Response<RegistryStatResponse>, Status> {
+        self.check_access(&request, "", Scope::Invoke).await?;
+        let digest = parse_digest(&request.get_ref().digest)?.to_string();
+        let blob = self.find(&digest).await?;
+        let signatures = self.store.signatures(&digest).await.map_err(read_error)?;
+
+        let file = self.open(&blob).await?;
+        let mut archive = Archive::new(blob.compression.decoder(BufReader::new(file)));
+        let mut entries = archive.entries().map_err(read_error)?;
+
+        let mut count = 0;
+        let mut top_level = BTreeSet::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry.map_err(read_error)?;
+            count += 1;
+            let path = entry.path().map_err(read_error)?;
+            if let Some(Component::Normal(name)) = path
+                .components()
+                .find(|component| !matches!(component, Component::CurDir))
+            {
+                top_level.insert(name.to_string_lossy().to_string());
+            }
+        }
+
+        Ok(Response::new(RegistryStatResponse {
+            package: Some(package_info(&blob)),
+            entries: count,
+            top_level: top_level.into_iter().collect(),
+            signatures,
+        }))
+    }
+
+    #[instrument(
+        name = "Registry delete",
+        skip(self, request),
+        fields(digest = %request.get_ref().digest, force = request.get_ref().force)
+    )]
+    async fn delete(
+        &self,
+        request: Request<RegistryDeleteRequest>,
+    ) -> Result<Response<RegistryDeleteResponse>, Status> {
+        self.check_access(&request, ALL_NAMESPACES, Scope::Admin)
+            .await?;
+        let digest = parse_digest(&request.get_ref().digest)?.to_string();
+
+        if !request.get_ref().force {
+            let referenced = self.referenced_digests(&request).await?;
+            if referenced.contains(&digest) {
+                warn!(digest = %digest, "Refusing to delete a deployed digest");
+                return Err(Status::failed_precondition(format!(
+                    "digest {} is still deployed, use force to delete it anyway",
+                    digest
+                )));
+            }
+        }
+
+        let removed = self.store.delete(&digest).await.map_err(|err| {
+            error!(digest = %digest, error = %err, "Failed to delete package");
+            Status::internal(format!("failed to delete store path: {:?}", err))
+        })?;
+        if !removed {
+            return Err(Status::not_found(format!("digest {} not found", digest)));
+        }
+
+        info!(digest = %digest, "Deleted package");
+        Ok(Response::new(RegistryDeleteResponse {}))
+    }
+}
+
+/// Hash the archive written into the returned pipe, uncompressing it first. It
+/// fails as soon as the archive unpacks to more than `limits` allow.
+fn spawn_hasher(
+    compression: Compression,
+    limits: Limits,
+) -> (DuplexStream, JoinHandle<std::io::Result<Digest>>) {
+    let (hash_writer, hash_reader) = duplex(HASH_BUFFER_SIZE);
+    let hash_task = tokio::spawn(async move {
+        let archive =
+            Archive::new(limits.limit_reader(compression.decoder(BufReader::new(hash_reader))));
+        compute_digest(archive, CURRENT_VERSION).await
+    });
+    (hash_writer, hash_task)
+}
+
+/// Forward `data` to the hashing task. Once the task stops reading, because the
+/// archive ended or turned out to be invalid, the rest is only written to disk.
+async fn feed_hasher(writer: &mut Option<DuplexStream>, data: &[u8]) {
+    if let Some(w) = writer
+        && w.write_all(data).await.is_err()
+    {
+        debug!("Digest task stopped reading");
+        *writer = None;
+    }
+}
+
+fn package_info(blob: &BlobMeta) -> PackageInfo {
+    let created_at = blob
+        .created
+        .duration_since(UNIX_EPOCH)
+        .map(|age| age.as_secs() as i64)
+        .unwrap_or_default();
+
+    PackageInfo {
+        digest: blob.digest.clone(),
+        size_bytes: blob.size,
+        created_at,
+        compression: blob.compression.into(),
+    }
 }
 
-async fn get_digest<T: std::marker::Unpin + tokio::io::AsyncRead>(
-    mut archive: Archive<T>,
-) -> Result<String> {
-    let mut hasher = Sha256::new();
+/// Only well formed digests can name a file in the registry
+fn parse_digest(digest: &str) -> Result<Digest, Status> {
+    digest
+        .parse()
+        .map_err(|err: package::InvalidDigest| Status::invalid_argument(err.to_string()))
+}
 
-    let mut entries = archive.entries()?;
-    while let Some(file) = entries.next().await {
-        let mut entry = file?;
-        let path = entry.path()?.to_string_lossy().to_string();
+/// An empty signature means the package is unsigned
+fn parse_signature(signature: &str) -> Result<Option<Signature>, Status> {
+    if signature.is_empty() {
+        return Ok(None);
+    }
+    signature
+        .parse()
+        .map(Some)
+        .map_err(|err: package::SignatureError| Status::invalid_argument(err.to_string()))
+}
 
-        if entry.header().entry_type().is_file() {
-            let mut buf = Vec::new();
-            entry.read_to_end(&mut buf).await?;
-            hasher.update(b"file:"); // prefix to differentiate files/folders
-            hasher.update(path.as_bytes());
-            hasher.update(&buf);
-        } else if entry.header().entry_type().is_dir() {
-            hasher.update(b"dir:"); // prefix for directories
-            hasher.update(path.as_bytes());
+/// Which of `hashes` are stored, looked up a few at a time. Stored chunks are
+/// touched, so garbage collection keeps them until the commit that needs them.
+async fn stored_chunks(
+    store: &Arc<dyn BlobStore>,
+    hashes: &[String],
+) -> std::io::Result<HashSet<String>> {
+    let mut stored = HashSet::new();
+    let mut lookups = JoinSet::new();
+    let mut hashes = hashes.iter().cloned();
+    loop {
+        while lookups.len() < CHUNK_LOOKUPS
+            && let Some(hash) = hashes.next()
+        {
+            let store = store.clone();
+            lookups.spawn(async move {
+                let exists = store.touch_chunk(&hash).await?;
+                Ok::<_, std::io::Error>((hash, exists))
+            });
+        }
+        let Some(result) = lookups.join_next().await else {
+            return Ok(stored);
+        };
+        let (hash, exists) = result.map_err(std::io::Error::other)??;
+        if exists {
+            stored.insert(hash);
         }
     }
+}
+
+fn too_large(max_size: u64) -> Status {
+    Status::resource_exhausted(format!("rejected archive: larger than {} bytes", max_size))
+}
+
+fn read_error(err: std::io::Error) -> Status {
+    error!(error = %err, "Failed to read registry");
+    Status::internal(format!("failed to read store path: {:?}", err))
+}
 
-    Ok(format!("{:x}", hasher.finalize()))
+fn write_error(err: std::io::Error) -> Status {
+    error!(error = %err, "Failed to write upload file");
+    Status::internal(format!("failed to write store path: {:?}", err))
 }
@@ -0,0 +1,123 @@
+use std::{io, time::SystemTime};
+
+use package::{BoxedReader, Compression, Digest};
+use tempfile::NamedTempFile;
+
+mod local;
+mod s3;
+
+pub use local::LocalStore;
+pub use s3::{S3Config, S3Store};
+
+/// A stored package archive
+#[derive(Debug, Clone)]
+pub struct BlobMeta {
+    pub digest: String,
+    pub compression: Compression,
+    pub size: u64,
+    /// When the archive was stored
+    pub created: SystemTime,
+    /// When the archive was last stored or touched, garbage collection goes by this
+    pub modified: SystemTime,
+}
+
+/// A stored chunk of a chunked push
+#[derive(Debug, Clone)]
+pub struct ChunkMeta {
+    pub hash: String,
+    pub modified: SystemTime,
+}
+
+/// Where package archives are kept. Every archive is named after its digest and
+/// never changes once stored, so several registries can share one store.
+#[tonic::async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stored archive of `digest`, `None` when there is none
+    async fn stat(&self, digest: &str) -> io::Result<Option<BlobMeta>>;
+
+    /// Every stored archive, sorted by digest
+    async fn list(&self) -> io::Result<Vec<BlobMeta>>;
+
+    /// Read the archive of `digest` as it is stored
+    async fn open(&self, blob: &BlobMeta) -> io::Result<BoxedReader<'static>>;
+
+    /// Store a finished upload as the archive of `digest`
+    async fn put(
+        &self,
+        digest: &str,
+        compression: Compression,
+        upload: NamedTempFile,
+    ) -> io::Result<()>;
+
+    /// Mark `blob` as stored just now, so garbage collection keeps it for another
+    /// grace period
+    async fn touch(&self, blob: &BlobMeta) -> io::Result<()>;
+
+    /// Remove every stored copy of `digest` and its signatures, returns whether
+    /// there was one
+    async fn delete(&self, digest: &str) -> io::Result<bool>;
+
+    /// Signatures over `digest`, one per line as sent by the signers
+    async fn signatures(&self, digest: &str) -> io::Result<Vec<String>>;
+
+    async fn put_signatures(&self, digest: &str, signatures: &[String]) -> io::Result<()>;
+
+    /// Store the uncompressed bytes of a chunk under their hash
+    async fn put_chunk(&self, hash: &str, data: Vec<u8>) -> io::Result<()>;
+
+    async fn get_chunk(&self, hash: &str) -> io::Result<Vec<u8>>;
+
+    async fn has_chunk(&self, hash: &str) -> io::Result<bool>;
+
+    /// Mark the chunk `hash` as stored just now, like `touch`, and return whether
+    /// it is stored
+    async fn touch_chunk(&self, hash: &str) -> io::Result<bool>;
+
+    async fn list_chunks(&self) -> io::Result<Vec<ChunkMeta>>;
+
+    async fn delete_chunk(&self, hash: &str) -> io::Result<()>;
+
+    /// Hashes of the chunks `digest` was committed from, empty when it was pushed
+    /// whole
+    async fn chunk_list(&self, digest: &str) -> io::Result<Vec<String>>;
+
+    async fn put_chunk_list(&self, digest: &str, hashes: &[String]) -> io::Result<()>;
+}
+
+/// Chunks are kept apart from the archives, below this name
+const CHUNK_DIR: &str = "chunks";
+
+fn signature_name(digest: &str) -> String {
+    format!("{}.sig", digest)
+}
+
+fn chunk_list_name(digest: &str) -> String {
+    format!("{}.chunks", digest)
+}
+
+/// Archives are never rewritten, so their own time is when they were stored and
+/// the time they were last touched lives next to them
+fn touched_name(digest: &str) -> String {
+    format!("{}.touched", digest)
+}
+
+fn blob_name(digest: &str, compression: Compression) -> String {
+    format!("{}.{}", digest, compression.extension())
+}
+
+/// Digest and compression of the archive called `name`. Uploads in progress and
+/// anything else not named after a digest are skipped.
+fn parse_blob_name(name: &str) -> Option<(String, Compression)> {
+    Compression::ALL.into_iter().find_map(|compression| {
+        name.strip_suffix(&format!(".{}", compression.extension()))
+            .filter(|digest| digest.parse::<Digest>().is_ok())
+            .map(|digest| (digest.to_string(), compression))
+    })
+}
+
+/// Sort by digest and keep one archive per digest, a package pushed with several
+/// compressions at once may briefly be stored more than once
+fn sort_blobs(blobs: &mut Vec<BlobMeta>) {
+    blobs.sort_by(|a, b| a.digest.cmp(&b.digest));
+    blobs.dedup_by(|a, b| a.digest == b.digest);
+}
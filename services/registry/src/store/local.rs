@@ -0,0 +1,385 @@
+use std::{fs::Permissions, io, os::unix::fs::PermissionsExt, path::PathBuf, time::SystemTime};
+
+use package::{BoxedReader, Compression, is_chunk_hash};
+use tempfile::NamedTempFile;
+use tokio::fs::{
+    File, create_dir_all, metadata, read, read_dir, read_to_string, remove_file, set_permissions,
+    write,
+};
+
+use super::{
+    BlobMeta, BlobStore, CHUNK_DIR, ChunkMeta, blob_name, chunk_list_name, parse_blob_name,
+    signature_name, sort_blobs, touched_name,
+};
+
+/// Temporary files are private, stored packages are readable like before
+const STORED_MODE: u32 = 0o644;
+
+/// Archives kept as files in a directory
+pub struct LocalStore {
+    registry_dir: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(registry_dir: PathBuf) -> Self {
+        Self { registry_dir }
+    }
+
+    fn path(&self, digest: &str, compression: Compression) -> PathBuf {
+        self.registry_dir.join(blob_name(digest, compression))
+    }
+
+    fn touched_path(&self, digest: &str) -> PathBuf {
+        self.registry_dir.join(touched_name(digest))
+    }
+
+    fn chunk_dir(&self) -> PathBuf {
+        self.registry_dir.join(CHUNK_DIR)
+    }
+
+    async fn blob_meta(&self, digest: &str, compression: Compression) -> io::Result<BlobMeta> {
+        let stored = metadata(self.path(digest, compression)).await?;
+        let created = stored.modified()?;
+        let touched = match metadata(self.touched_path(digest)).await {
+            Ok(touched) => Some(touched.modified()?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        Ok(BlobMeta {
+            digest: digest.to_string(),
+            compression,
+            size: stored.len(),
+            created,
+            modified: touched.map_or(created, |touched| touched.max(created)),
+        })
+    }
+
+    /// Replace the file `name` with `lines`, never leaving it half written
+    async fn put_lines(&self, name: &str, lines: &[String]) -> io::Result<()> {
+        let temp = tempfile::Builder::new()
+            .prefix(".upload-")
+            .tempfile_in(&self.registry_dir)?;
+        write(temp.path(), lines.join("\n")).await?;
+        set_permissions(temp.path(), Permissions::from_mode(STORED_MODE)).await?;
+        temp.persist(self.registry_dir.join(name))
+            .map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl BlobStore for LocalStore {
+    async fn stat(&self, digest: &str) -> io::Result<Option<BlobMeta>> {
+        for compression in Compression::ALL {
+            match self.blob_meta(digest, compression).await {
+                Ok(blob) => return Ok(Some(blob)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    async fn list(&self) -> io::Result<Vec<BlobMeta>> {
+        let mut entries = read_dir(&self.registry_dir).await?;
+        let mut blobs = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some((digest, compression)) = parse_blob_name(&name) else {
+                continue;
+            };
+            match self.blob_meta(&digest, compression).await {
+                Ok(blob) => blobs.push(blob),
+                // Deleted since the directory was read.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        sort_blobs(&mut blobs);
+        Ok(blobs)
+    }
+
+    async fn open(&self, blob: &BlobMeta) -> io::Result<BoxedReader<'static>> {
+        let file = File::open(self.path(&blob.digest, blob.compression)).await?;
+        Ok(Box::pin(file))
+    }
+
+    async fn put(
+        &self,
+        digest: &str,
+        compression: Compression,
+        upload: NamedTempFile,
+    ) -> io::Result<()> {
+        let path = self.path(digest, compression);
+        blocking(move || {
+            upload.as_file().sync_all()?;
+            std::fs::set_permissions(upload.path(), Permissions::from_mode(STORED_MODE))?;
+            upload.persist(path).map_err(|err| err.error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn touch(&self, blob: &BlobMeta) -> io::Result<()> {
+        // The archive keeps its own time, which is when it was stored.
+        let path = self.touched_path(&blob.digest);
+        blocking(move || {
+            std::fs::File::options()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?
+                .set_modified(SystemTime::now())
+        })
+        .await
+    }
+
+    async fn delete(&self, digest: &str) -> io::Result<bool> {
+        let mut removed = false;
+        for compression in Compression::ALL {
+            match remove_file(self.path(digest, compression)).await {
+                Ok(()) => removed = true,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        for name in [
+            signature_name(digest),
+            chunk_list_name(digest),
+            touched_name(digest),
+        ] {
+            match remove_file(self.registry_dir.join(name)).await {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn signatures(&self, digest: &str) -> io::Result<Vec<String>> {
+        match read_to_string(self.registry_dir.join(signature_name(digest))).await {
+            Ok(content) => Ok(content.lines().map(str::to_string).collect()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn put_signatures(&self, digest: &str, signatures: &[String]) -> io::Result<()> {
+        self.put_lines(&signature_name(digest), signatures).await
+    }
+
+    async fn put_chunk(&self, hash: &str, data: Vec<u8>) -> io::Result<()> {
+        let chunk_dir = self.chunk_dir();
+        create_dir_all(&chunk_dir).await?;
+        // Written next to the chunk and renamed, so a chunk is never seen half written.
+        let temp = tempfile::Builder::new()
+            .prefix(".upload-")
+            .tempfile_in(&chunk_dir)?;
+        write(temp.path(), data).await?;
+        temp.persist(chunk_dir.join(hash))
+            .map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    async fn get_chunk(&self, hash: &str) -> io::Result<Vec<u8>> {
+        read(self.chunk_dir().join(hash)).await
+    }
+
+    async fn has_chunk(&self, hash: &str) -> io::Result<bool> {
+        match metadata(self.chunk_dir().join(hash)).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn touch_chunk(&self, hash: &str) -> io::Result<bool> {
+        let path = self.chunk_dir().join(hash);
+        let result = blocking(move || {
+            std::fs::File::options()
+                .write(true)
+                .open(path)?
+                .set_modified(SystemTime::now())
+        })
+        .await;
+        match result {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list_chunks(&self) -> io::Result<Vec<ChunkMeta>> {
+        let mut entries = match read_dir(self.chunk_dir()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut chunks = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let hash = entry.file_name().to_string_lossy().to_string();
+            if !is_chunk_hash(&hash) {
+                continue;
+            }
+            match entry.metadata().await.and_then(|m| m.modified()) {
+                Ok(modified) => chunks.push(ChunkMeta { hash, modified }),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(chunks)
+    }
+
+    async fn delete_chunk(&self, hash: &str) -> io::Result<()> {
+        match remove_file(self.chunk_dir().join(hash)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    async fn chunk_list(&self, digest: &str) -> io::Result<Vec<String>> {
+        match read_to_string(self.registry_dir.join(chunk_list_name(digest))).await {
+            Ok(content) => Ok(content.lines().map(str::to_string).collect()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn put_chunk_list(&self, digest: &str, hashes: &[String]) -> io::Result<()> {
+        self.put_lines(&chunk_list_name(digest), hashes).await
+    }
+}
+
+/// Run file system calls tokio has no async version of off the runtime threads
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::Path, time::Duration};
+
+    use tempfile::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    const DIGEST: &str = "v2:0101010101010101010101010101010101010101010101010101010101010101";
+
+    fn chunk(n: u8) -> String {
+        format!("{:02x}", n).repeat(32)
+    }
+
+    async fn put(store: &LocalStore, dir: &Path, content: &[u8]) {
+        let mut upload = NamedTempFile::new_in(dir).unwrap();
+        upload.write_all(content).unwrap();
+        store.put(DIGEST, Compression::Zstd, upload).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_put_open_delete() {
+        let dir = TempDir::new().unwrap();
+        let store = LocalStore::new(dir.path().to_path_buf());
+        assert!(store.stat(DIGEST).await.unwrap().is_none());
+
+        put(&store, dir.path(), b"archive").await;
+        store
+            .put_signatures(DIGEST, &["sig".to_string()])
+            .await
+            .unwrap();
+        let blob = store.stat(DIGEST).await.unwrap().unwrap();
+        assert_eq!(blob.compression, Compression::Zstd);
+        assert_eq!(blob.size, 7);
+        let listed = store.list().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].digest, DIGEST);
+
+        let mut content = Vec::new();
+        store
+            .open(&blob)
+            .await
+            .unwrap()
+            .read_to_end(&mut content)
+            .await
+            .unwrap();
+        assert_eq!(content, b"archive");
+
+        assert!(store.delete(DIGEST).await.unwrap());
+        assert!(!store.delete(DIGEST).await.unwrap());
+        assert!(store.stat(DIGEST).await.unwrap().is_none());
+        assert!(store.signatures(DIGEST).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_touch() {
+        let dir = TempDir::new().unwrap();
+        let store = LocalStore::new(dir.path().to_path_buf());
+        put(&store, dir.path(), b"archive").await;
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(store.path(DIGEST, Compression::Zstd))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let blob = store.stat(DIGEST).await.unwrap().unwrap();
+        store.touch(&blob).await.unwrap();
+        let touched = store.stat(DIGEST).await.unwrap().unwrap();
+        assert!(touched.modified > old + Duration::from_secs(60));
+        // Listing and stat still show when the package was stored.
+        assert_eq!(touched.created, old);
+        assert_eq!(store.list().await.unwrap()[0].created, old);
+    }
+
+    #[tokio::test]
+    async fn test_chunks() {
+        let dir = TempDir::new().unwrap();
+        let store = LocalStore::new(dir.path().to_path_buf());
+        assert!(store.list_chunks().await.unwrap().is_empty());
+        assert!(!store.has_chunk(&chunk(1)).await.unwrap());
+
+        store.put_chunk(&chunk(1), b"one".to_vec()).await.unwrap();
+        assert!(store.has_chunk(&chunk(1)).await.unwrap());
+        assert_eq!(store.get_chunk(&chunk(1)).await.unwrap(), b"one");
+        assert!(store.get_chunk(&chunk(2)).await.is_err());
+        // Leftovers of an interrupted write are not chunks.
+        std::fs::write(dir.path().join(CHUNK_DIR).join(".upload-x"), b"").unwrap();
+        let listed = store.list_chunks().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].hash, chunk(1));
+
+        put(&store, dir.path(), b"archive").await;
+        store
+            .put_chunk_list(DIGEST, &[chunk(1), chunk(2)])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.chunk_list(DIGEST).await.unwrap(),
+            [chunk(1), chunk(2)]
+        );
+        store.delete(DIGEST).await.unwrap();
+        assert!(store.chunk_list(DIGEST).await.unwrap().is_empty());
+
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join(CHUNK_DIR).join(chunk(1)))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        assert!(store.touch_chunk(&chunk(1)).await.unwrap());
+        assert!(!store.touch_chunk(&chunk(2)).await.unwrap());
+        assert!(store.list_chunks().await.unwrap()[0].modified > old + Duration::from_secs(60));
+
+        store.delete_chunk(&chunk(1)).await.unwrap();
+        store.delete_chunk(&chunk(1)).await.unwrap();
+        assert!(!store.has_chunk(&chunk(1)).await.unwrap());
+    }
+}
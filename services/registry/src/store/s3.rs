@@ -0,0 +1,493 @@
+use std::{collections::HashMap, io, sync::Arc, time::SystemTime};
+
+use object_store::{
+    ObjectMeta, ObjectStore, PutPayload,
+    aws::AmazonS3Builder,
+    buffered::{BufReader, BufWriter},
+    path::Path,
+};
+use package::{BoxedReader, Compression, is_chunk_hash};
+use tempfile::NamedTempFile;
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, copy},
+};
+use tokio_stream::StreamExt;
+use tracing::debug;
+
+use super::{
+    BlobMeta, BlobStore, CHUNK_DIR, ChunkMeta, blob_name, chunk_list_name, parse_blob_name,
+    signature_name, sort_blobs, touched_name,
+};
+
+/// Bucket settings. Credentials are read from the usual `AWS_*` variables.
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    /// Set for S3-compatible services such as MinIO, `http://` URLs are allowed
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    /// Key prefix the archives are stored under
+    pub prefix: Option<String>,
+}
+
+/// Archives kept as objects in an S3-compatible bucket
+pub struct S3Store {
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<Path>,
+}
+
+impl S3Store {
+    pub fn new(config: &S3Config) -> Result<Self, object_store::Error> {
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&config.bucket);
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        if let Some(region) = &config.region {
+            builder = builder.with_region(region);
+        }
+
+        Ok(Self {
+            store: Arc::new(builder.build()?),
+            prefix: config.prefix.as_deref().map(Path::from),
+        })
+    }
+
+    fn path(&self, digest: &str, compression: Compression) -> Path {
+        self.child(blob_name(digest, compression))
+    }
+
+    fn signature_path(&self, digest: &str) -> Path {
+        self.child(signature_name(digest))
+    }
+
+    fn chunk_list_path(&self, digest: &str) -> Path {
+        self.child(chunk_list_name(digest))
+    }
+
+    fn touched_path(&self, digest: &str) -> Path {
+        self.child(touched_name(digest))
+    }
+
+    fn child(&self, name: String) -> Path {
+        match &self.prefix {
+            Some(prefix) => prefix.child(name),
+            None => Path::from(name),
+        }
+    }
+
+    fn chunk_dir(&self) -> Path {
+        self.child(CHUNK_DIR.to_string())
+    }
+
+    /// Lines of the object at `path`, empty when there is none
+    async fn get_lines(&self, path: &Path) -> io::Result<Vec<String>> {
+        let result = match self.store.get(path).await {
+            Ok(result) => result,
+            Err(object_store::Error::NotFound { .. }) => return Ok(Vec::new()),
+            Err(err) => return Err(io_error(err)),
+        };
+        let content = result.bytes().await.map_err(io_error)?;
+        Ok(String::from_utf8_lossy(&content)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// When `digest` was last touched, `None` when it never was
+    async fn touched(&self, digest: &str) -> io::Result<Option<SystemTime>> {
+        match self.store.head(&self.touched_path(digest)).await {
+            Ok(meta) => Ok(Some(meta.last_modified.into())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    async fn put_lines(&self, path: &Path, lines: &[String]) -> io::Result<()> {
+        self.store
+            .put(path, PutPayload::from(lines.join("\n").into_bytes()))
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl BlobStore for S3Store {
+    async fn stat(&self, digest: &str) -> io::Result<Option<BlobMeta>> {
+        for compression in Compression::ALL {
+            match self.store.head(&self.path(digest, compression)).await {
+                Ok(meta) => {
+                    let touched = self.touched(digest).await?;
+                    return Ok(Some(blob_meta(&meta, digest, compression, touched)));
+                }
+                Err(object_store::Error::NotFound { .. }) => {}
+                Err(err) => return Err(io_error(err)),
+            }
+        }
+        Ok(None)
+    }
+
+    async fn list(&self) -> io::Result<Vec<BlobMeta>> {
+        // Only archives directly under the prefix belong to the registry.
+        let depth = self
+            .prefix
+            .as_ref()
+            .map_or(0, |prefix| prefix.parts().count());
+        let mut objects = self.store.list(self.prefix.as_ref());
+        let mut archives = Vec::new();
+        let mut touched = HashMap::new();
+        while let Some(meta) = objects.next().await {
+            let meta = meta.map_err(io_error)?;
+            if meta.location.parts().count() != depth + 1 {
+                continue;
+            }
+            let Some(name) = meta.location.filename() else {
+                continue;
+            };
+            if let Some((digest, compression)) = parse_blob_name(name) {
+                archives.push((meta, digest, compression));
+            } else if let Some(digest) = name.strip_suffix(".touched") {
+                touched.insert(digest.to_string(), SystemTime::from(meta.last_modified));
+            }
+        }
+        let mut blobs: Vec<BlobMeta> = archives
+            .iter()
+            .map(|(meta, digest, compression)| {
+                blob_meta(meta, digest, *compression, touched.get(digest).copied())
+            })
+            .collect();
+        sort_blobs(&mut blobs);
+        Ok(blobs)
+    }
+
+    async fn open(&self, blob: &BlobMeta) -> io::Result<BoxedReader<'static>> {
+        let meta = self
+            .store
+            .head(&self.path(&blob.digest, blob.compression))
+            .await
+            .map_err(io_error)?;
+        Ok(Box::pin(BufReader::new(self.store.clone(), &meta)))
+    }
+
+    async fn put(
+        &self,
+        digest: &str,
+        compression: Compression,
+        upload: NamedTempFile,
+    ) -> io::Result<()> {
+        let path = self.path(digest, compression);
+        debug!(path = %path, "Uploading archive to bucket");
+
+        // Large archives are sent in parts instead of being held in memory.
+        let mut file = File::open(upload.path()).await?;
+        let mut writer = BufWriter::new(self.store.clone(), path);
+        if let Err(err) = copy(&mut file, &mut writer).await {
+            let _ = writer.abort().await;
+            return Err(err);
+        }
+        writer.shutdown().await
+    }
+
+    async fn touch(&self, blob: &BlobMeta) -> io::Result<()> {
+        // Objects cannot be copied onto themselves unchanged, and copying through
+        // another object races with other registries touching the same archive. A
+        // put replaces the marker in one step, whoever writes last wins.
+        self.store
+            .put(&self.touched_path(&blob.digest), PutPayload::new())
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    async fn delete(&self, digest: &str) -> io::Result<bool> {
+        // Deleting a missing object succeeds, so look before deleting.
+        let mut removed = false;
+        for compression in Compression::ALL {
+            let path = self.path(digest, compression);
+            match self.store.head(&path).await {
+                Ok(_) => {}
+                Err(object_store::Error::NotFound { .. }) => continue,
+                Err(err) => return Err(io_error(err)),
+            }
+            self.store.delete(&path).await.map_err(io_error)?;
+            removed = true;
+        }
+        for path in [
+            self.signature_path(digest),
+            self.chunk_list_path(digest),
+            self.touched_path(digest),
+        ] {
+            self.store.delete(&path).await.map_err(io_error)?;
+        }
+        Ok(removed)
+    }
+
+    async fn signatures(&self, digest: &str) -> io::Result<Vec<String>> {
+        self.get_lines(&self.signature_path(digest)).await
+    }
+
+    async fn put_signatures(&self, digest: &str, signatures: &[String]) -> io::Result<()> {
+        self.put_lines(&self.signature_path(digest), signatures)
+            .await
+    }
+
+    async fn put_chunk(&self, hash: &str, data: Vec<u8>) -> io::Result<()> {
+        self.store
+            .put(&self.chunk_dir().child(hash), PutPayload::from(data))
+            .await
+            .map_err(io_error)?;
+        Ok(())
+    }
+
+    async fn get_chunk(&self, hash: &str) -> io::Result<Vec<u8>> {
+        let result = self
+            .store
+            .get(&self.chunk_dir().child(hash))
+            .await
+            .map_err(io_error)?;
+        Ok(result.bytes().await.map_err(io_error)?.to_vec())
+    }
+
+    async fn has_chunk(&self, hash: &str) -> io::Result<bool> {
+        match self.store.head(&self.chunk_dir().child(hash)).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    async fn touch_chunk(&self, hash: &str) -> io::Result<bool> {
+        // Touched through a marker, for the same reason as archives.
+        if !self.has_chunk(hash).await? {
+            return Ok(false);
+        }
+        self.store
+            .put(
+                &self.chunk_dir().child(touched_name(hash)),
+                PutPayload::new(),
+            )
+            .await
+            .map_err(io_error)?;
+        Ok(true)
+    }
+
+    async fn list_chunks(&self) -> io::Result<Vec<ChunkMeta>> {
+        let mut objects = self.store.list(Some(&self.chunk_dir()));
+        let mut chunks = Vec::new();
+        let mut touched = HashMap::new();
+        while let Some(meta) = objects.next().await {
+            let meta = meta.map_err(io_error)?;
+            let Some(name) = meta.location.filename() else {
+                continue;
+            };
+            if is_chunk_hash(name) {
+                chunks.push(ChunkMeta {
+                    hash: name.to_string(),
+                    modified: meta.last_modified.into(),
+                });
+            } else if let Some(hash) = name.strip_suffix(".touched") {
+                touched.insert(hash.to_string(), SystemTime::from(meta.last_modified));
+            }
+        }
+        for chunk in &mut chunks {
+            if let Some(&touched) = touched.get(&chunk.hash) {
+                chunk.modified = chunk.modified.max(touched);
+            }
+        }
+        Ok(chunks)
+    }
+
+    async fn delete_chunk(&self, hash: &str) -> io::Result<()> {
+        for name in [hash.to_string(), touched_name(hash)] {
+            self.store
+                .delete(&self.chunk_dir().child(name))
+                .await
+                .map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    async fn chunk_list(&self, digest: &str) -> io::Result<Vec<String>> {
+        self.get_lines(&self.chunk_list_path(digest)).await
+    }
+
+    async fn put_chunk_list(&self, digest: &str, hashes: &[String]) -> io::Result<()> {
+        self.put_lines(&self.chunk_list_path(digest), hashes).await
+    }
+}
+
+/// `touched` is when the archive was last touched, if it was since being stored
+fn blob_meta(
+    meta: &ObjectMeta,
+    digest: &str,
+    compression: Compression,
+    touched: Option<SystemTime>,
+) -> BlobMeta {
+    let stored = SystemTime::from(meta.last_modified);
+    BlobMeta {
+        digest: digest.to_string(),
+        compression,
+        size: meta.size,
+        created: stored,
+        modified: touched.map_or(stored, |touched| touched.max(stored)),
+    }
+}
+
+fn io_error(err: object_store::Error) -> io::Error {
+    match err {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, err),
+        err => io::Error::other(err),
+    }
+}
+
+/// Run against a real bucket, see "Testing the S3 backend" in the README. Every test
+/// works below its own prefix, so runs do not see each other.
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    const DIGEST: &str = "v2:0101010101010101010101010101010101010101010101010101010101010101";
+    const DEFAULT_BUCKET: &str = "noctiforge-test";
+
+    fn chunk(n: u8) -> String {
+        format!("{:02x}", n).repeat(32)
+    }
+
+    fn store(test: &str) -> S3Store {
+        let endpoint = std::env::var("S3_ENDPOINT").expect("S3_ENDPOINT is not set");
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        S3Store::new(&S3Config {
+            bucket: std::env::var("S3_BUCKET").unwrap_or_else(|_| DEFAULT_BUCKET.to_string()),
+            endpoint: Some(endpoint),
+            region: Some(std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string())),
+            prefix: Some(format!("test-{}-{}-{}", test, std::process::id(), nanos)),
+        })
+        .unwrap()
+    }
+
+    async fn put(store: &S3Store, content: &[u8]) {
+        let mut upload = NamedTempFile::new().unwrap();
+        upload.write_all(content).unwrap();
+        store.put(DIGEST, Compression::Zstd, upload).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible service at S3_ENDPOINT"]
+    async fn test_put_open_delete() {
+        let store = store("put");
+        assert!(store.stat(DIGEST).await.unwrap().is_none());
+
+        put(&store, b"archive").await;
+        store
+            .put_signatures(DIGEST, &["sig".to_string()])
+            .await
+            .unwrap();
+        let blob = store.stat(DIGEST).await.unwrap().unwrap();
+        assert_eq!(blob.compression, Compression::Zstd);
+        assert_eq!(blob.size, 7);
+        let listed = store.list().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].digest, DIGEST);
+
+        let mut content = Vec::new();
+        store
+            .open(&blob)
+            .await
+            .unwrap()
+            .read_to_end(&mut content)
+            .await
+            .unwrap();
+        assert_eq!(content, b"archive");
+
+        assert!(store.delete(DIGEST).await.unwrap());
+        assert!(!store.delete(DIGEST).await.unwrap());
+        assert!(store.stat(DIGEST).await.unwrap().is_none());
+        assert!(store.signatures(DIGEST).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible service at S3_ENDPOINT"]
+    async fn test_touch() {
+        let store = store("touch");
+        put(&store, b"archive").await;
+        let blob = store.stat(DIGEST).await.unwrap().unwrap();
+        assert_eq!(blob.modified, blob.created);
+
+        // Object times cannot be set, so wait for the clock to move on.
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        // Touching at once from several registries must not fail or lose the archive.
+        let (a, b) = tokio::join!(store.touch(&blob), store.touch(&blob));
+        a.unwrap();
+        b.unwrap();
+
+        let touched = store.stat(DIGEST).await.unwrap().unwrap();
+        assert!(touched.modified > blob.created);
+        assert_eq!(touched.created, blob.created);
+        // A listing reports times more precisely than a head request, so they are
+        // only compared with each other.
+        let listed = store.list().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].modified > listed[0].created);
+
+        // The marker goes with the archive.
+        assert!(store.delete(DIGEST).await.unwrap());
+        put(&store, b"archive").await;
+        let stored = store.stat(DIGEST).await.unwrap().unwrap();
+        assert_eq!(stored.modified, stored.created);
+        store.delete(DIGEST).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3-compatible service at S3_ENDPOINT"]
+    async fn test_chunks() {
+        let store = store("chunks");
+        assert!(store.list_chunks().await.unwrap().is_empty());
+        assert!(!store.has_chunk(&chunk(1)).await.unwrap());
+
+        store.put_chunk(&chunk(1), b"one".to_vec()).await.unwrap();
+        assert!(store.has_chunk(&chunk(1)).await.unwrap());
+        assert_eq!(store.get_chunk(&chunk(1)).await.unwrap(), b"one");
+        let err = store.get_chunk(&chunk(2)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let listed = store.list_chunks().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].hash, chunk(1));
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(store.touch_chunk(&chunk(1)).await.unwrap());
+        assert!(!store.touch_chunk(&chunk(2)).await.unwrap());
+        let touched = store.list_chunks().await.unwrap();
+        assert_eq!(touched.len(), 1);
+        assert!(touched[0].modified > listed[0].modified);
+
+        put(&store, b"archive").await;
+        store
+            .put_chunk_list(DIGEST, &[chunk(1), chunk(2)])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.chunk_list(DIGEST).await.unwrap(),
+            [chunk(1), chunk(2)]
+        );
+        store.delete(DIGEST).await.unwrap();
+        assert!(store.chunk_list(DIGEST).await.unwrap().is_empty());
+
+        store.delete_chunk(&chunk(1)).await.unwrap();
+        store.delete_chunk(&chunk(1)).await.unwrap();
+        assert!(!store.has_chunk(&chunk(1)).await.unwrap());
+        assert!(store.list_chunks().await.unwrap().is_empty());
+    }
+}
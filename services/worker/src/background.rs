@@ -5,25 +5,35 @@ use tokio::time::{Instant, sleep};
 use tokio_util::sync::CancellationToken;
 use tracing::info;
 
-use crate::worker::function_invocations::FunctionInvocations;
+use crate::{
+    client::registry_clint::RegistryClient, worker::function_invocations::FunctionInvocations,
+};
 
 pub struct BackgroundConfig {
     pub time: Duration,
     pub resource_ttl: Duration,
+    /// Bytes the extracted packages may take, `None` for no limit
+    pub package_cache_size: Option<u64>,
 }
 
 pub struct BackgroundJob {
     config: BackgroundConfig,
     cancel: CancellationToken,
     function_invocations: Arc<FunctionInvocations>,
+    registry: RegistryClient,
 }
 
 impl BackgroundJob {
-    pub fn new(config: BackgroundConfig, function_invocations: &Arc<FunctionInvocations>) -> Self {
+    pub fn new(
+        config: BackgroundConfig,
+        function_invocations: &Arc<FunctionInvocations>,
+        registry: RegistryClient,
+    ) -> Self {
         Self {
             config,
             cancel: CancellationToken::new(),
             function_invocations: function_invocations.clone(),
+            registry,
         }
     }
 
@@ -33,6 +43,8 @@ impl BackgroundJob {
         let time = self.config.time;
         let resource_ttl = self.config.resource_ttl;
         let function = self.function_invocations.clone();
+        let package_cache_size = self.config.package_cache_size;
+        let registry = self.registry.clone();
 
         tokio::spawn(async move {
             while !cancel.is_cancelled() {
@@ -42,6 +54,11 @@ impl BackgroundJob {
                         tracing::error!("Something when worng with {}: {:?}", instance_id, err);
                     }
                 }
+                if let Some(budget) = package_cache_size
+                    && let Err(err) = registry.evict_packages(budget).await
+                {
+                    tracing::error!("Failed to evict packages: {:?}", err);
+                }
             }
         });
     }
@@ -1,34 +1,63 @@
+use std::{sync::Arc, time::Duration};
+
 use anyhow::{Ok, Result};
-use proto::api::controlplane::{
-    GetDigestByNameRequest, control_plane_service_client::ControlPlaneServiceClient,
+use auth::ClientAuth;
+use proto::{
+    api::controlplane::{
+        GetDigestByNameRequest, WatchDeploymentsRequest,
+        control_plane_service_client::ControlPlaneServiceClient,
+    },
+    namespace,
 };
-use tonic::Request;
-use tracing::{debug, instrument, warn};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tonic::{Request, service::interceptor::InterceptedService, transport::Channel};
+use tracing::{debug, info, instrument, warn};
+
+use crate::client::digest_cache::DigestCache;
+
+const WATCH_RETRY_INITIAL: Duration = Duration::from_secs(1);
+const WATCH_RETRY_MAX: Duration = Duration::from_secs(30);
 
+#[derive(Clone)]
 pub struct ControlPlaneClient {
     pub addr: String,
+    client: ControlPlaneServiceClient<InterceptedService<Channel, ClientAuth>>,
+    cache: Arc<DigestCache>,
 }
 
 impl ControlPlaneClient {
-    pub fn new(addr: String) -> Self {
+    /// `channel` should connect lazily so it reconnects on its own, a single
+    /// client is shared by every request.
+    pub fn new(addr: String, channel: Channel, cache_ttl: Duration, auth: ClientAuth) -> Self {
         debug!(addr = %addr, "Creating ControlPlaneClient");
-        Self { addr }
+        Self {
+            addr,
+            client: ControlPlaneServiceClient::with_interceptor(channel, auth),
+            cache: Arc::new(DigestCache::new(cache_ttl)),
+        }
     }
 }
 
 impl ControlPlaneClient {
     #[instrument(skip(self), fields(addr = %self.addr))]
-    pub async fn get_digest(&self, key: String) -> Result<String> {
-        debug!(key = %key, "Fetching digest from control plane");
-        let mut client = ControlPlaneServiceClient::connect(self.addr.clone())
-            .await
-            .map_err(|e| {
-                warn!(error = %e, "Failed to connect to control plane");
-                e
-            })?;
+    pub async fn get_digest(&self, namespace: &str, name: &str) -> Result<String> {
+        let key = namespace::qualified(namespace, name);
+        if let Some(digest) = self.cache.get(&key).await {
+            debug!(key = %key, digest = %digest, "Using cached digest");
+            return Ok(digest);
+        }
 
-        let response = client
-            .get_digest_by_name(Request::new(GetDigestByNameRequest { key: key.clone() }))
+        debug!(key = %key, "Fetching digest from control plane");
+        // A deployment event that lands while the request is in flight wins.
+        let generation = self.cache.generation().await;
+        let response = self
+            .client
+            .clone()
+            .get_digest_by_name(Request::new(GetDigestByNameRequest {
+                key: name.to_string(),
+                namespace: namespace.to_string(),
+            }))
             .await
             .map_err(|e| {
                 warn!(key = %key, error = %e, "Failed to get digest by name");
@@ -37,7 +66,61 @@ impl ControlPlaneClient {
             .into_inner();
 
         debug!(key = %key, digest = %response.digest, "Successfully retrieved digest");
+        self.cache
+            .fill(key, response.digest.clone(), generation)
+            .await;
 
         Ok(response.digest)
     }
+
+    /// Follow the control plane deployment stream until `cancel` fires, keeping the
+    /// digest cache in sync with every re-pointed name.
+    pub fn watch(&self, cancel: CancellationToken) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut retry = WATCH_RETRY_INITIAL;
+            while !cancel.is_cancelled() {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    result = this.follow_deployments(&mut retry) => {
+                        if let Err(e) = result {
+                            warn!(error = %e, retry_in = ?retry, "Deployment watch interrupted");
+                        }
+                    }
+                }
+
+                // Events may have been missed while disconnected.
+                this.cache.clear().await;
+
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = sleep(retry) => {}
+                }
+                retry = (retry * 2).min(WATCH_RETRY_MAX);
+            }
+            info!("Stopped watching deployments");
+        });
+    }
+
+    async fn follow_deployments(&self, retry: &mut Duration) -> Result<()> {
+        let mut stream = self
+            .client
+            .clone()
+            .watch_deployments(Request::new(WatchDeploymentsRequest {}))
+            .await?
+            .into_inner();
+
+        info!(addr = %self.addr, "Watching deployments");
+        *retry = WATCH_RETRY_INITIAL;
+        // Anything cached before the subscription started may already be stale.
+        self.cache.clear().await;
+
+        while let Some(event) = stream.message().await? {
+            let key = namespace::qualified(&event.namespace, &event.key);
+            debug!(key = %key, digest = %event.digest, "Deployment changed");
+            self.cache.insert(key, event.digest).await;
+        }
+
+        anyhow::bail!("deployment stream closed by control plane")
+    }
 }
@@ -0,0 +1,172 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::{sync::RwLock, time::Instant};
+
+struct CachedDigest {
+    digest: String,
+    inserted_at: Instant,
+    generation: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Bumped by every write, so a lookup can tell whether it raced one
+    generation: u64,
+    cleared_at: u64,
+    digests: HashMap<String, CachedDigest>,
+}
+
+/// Short-lived name → digest cache, kept fresh by the control plane deployment stream
+pub struct DigestCache {
+    ttl: Duration,
+    entries: RwLock<Entries>,
+}
+
+impl DigestCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RwLock::new(Entries::default()),
+        }
+    }
+
+    /// Get the digest for `key` if it was cached less than `ttl` ago
+    pub async fn get(&self, key: &str) -> Option<String> {
+        let entries = self.entries.read().await;
+        entries
+            .digests
+            .get(key)
+            .filter(|entry| entry.inserted_at.elapsed() < self.ttl)
+            .map(|entry| entry.digest.clone())
+    }
+
+    /// Current generation, taken before looking a digest up to pass to `fill`
+    pub async fn generation(&self) -> u64 {
+        self.entries.read().await.generation
+    }
+
+    /// Cache an authoritative digest, e.g. from the deployment stream
+    pub async fn insert(&self, key: String, digest: String) {
+        let mut entries = self.entries.write().await;
+        entries.generation += 1;
+        let generation = entries.generation;
+        entries.digests.insert(
+            key,
+            CachedDigest {
+                digest,
+                inserted_at: Instant::now(),
+                generation,
+            },
+        );
+    }
+
+    /// Cache a digest looked up at `generation`, unless `key` was written or the
+    /// cache cleared since then, in which case the lookup may already be stale
+    pub async fn fill(&self, key: String, digest: String, generation: u64) {
+        let mut entries = self.entries.write().await;
+        let changed = entries.cleared_at > generation
+            || entries
+                .digests
+                .get(&key)
+                .is_some_and(|entry| entry.generation > generation);
+        if !changed {
+            entries.generation += 1;
+            let generation = entries.generation;
+            entries.digests.insert(
+                key,
+                CachedDigest {
+                    digest,
+                    inserted_at: Instant::now(),
+                    generation,
+                },
+            );
+        }
+    }
+
+    pub async fn clear(&self) {
+        let mut entries = self.entries.write().await;
+        entries.generation += 1;
+        entries.cleared_at = entries.generation;
+        entries.digests.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_missing_key() {
+        let cache = DigestCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get("echo").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_get() {
+        let cache = DigestCache::new(Duration::from_secs(60));
+        cache.insert("echo".to_string(), "abc".to_string()).await;
+        assert_eq!(cache.get("echo").await, Some("abc".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_insert_replaces_digest() {
+        let cache = DigestCache::new(Duration::from_secs(60));
+        cache.insert("echo".to_string(), "abc".to_string()).await;
+        cache.insert("echo".to_string(), "def".to_string()).await;
+        assert_eq!(cache.get("echo").await, Some("def".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_expired_entry_is_not_returned() {
+        let cache = DigestCache::new(Duration::ZERO);
+        cache.insert("echo".to_string(), "abc".to_string()).await;
+        assert_eq!(cache.get("echo").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_fill() {
+        let cache = DigestCache::new(Duration::from_secs(60));
+        let generation = cache.generation().await;
+        cache
+            .fill("echo".to_string(), "abc".to_string(), generation)
+            .await;
+        assert_eq!(cache.get("echo").await, Some("abc".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_fill_does_not_overwrite_newer_event() {
+        let cache = DigestCache::new(Duration::from_secs(60));
+        // The lookup starts, then a deployment event lands before it returns.
+        let generation = cache.generation().await;
+        cache.insert("echo".to_string(), "new".to_string()).await;
+        cache
+            .fill("echo".to_string(), "old".to_string(), generation)
+            .await;
+        assert_eq!(cache.get("echo").await, Some("new".to_string()));
+
+        // Events for other names do not hold the lookup back.
+        cache
+            .fill("other".to_string(), "abc".to_string(), generation)
+            .await;
+        assert_eq!(cache.get("other").await, Some("abc".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_fill_after_clear_is_dropped() {
+        let cache = DigestCache::new(Duration::from_secs(60));
+        let generation = cache.generation().await;
+        cache.clear().await;
+        cache
+            .fill("echo".to_string(), "old".to_string(), generation)
+            .await;
+        assert_eq!(cache.get("echo").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let cache = DigestCache::new(Duration::from_secs(60));
+        cache.insert("echo".to_string(), "abc".to_string()).await;
+        cache.clear().await;
+        assert_eq!(cache.get("echo").await, None);
+    }
+}
@@ -1,2 +1,3 @@
 pub mod controlplane_client;
+mod digest_cache;
 pub mod registry_clint;
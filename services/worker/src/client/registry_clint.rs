@@ -1,59 +1,375 @@
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
 
-use anyhow::{Ok, Result};
-use proto::api::registry::{RegistryPullRequest, registry_service_client::RegistryServiceClient};
-use std::io::Cursor;
-use tokio::fs::create_dir;
+use anyhow::{Context, Ok, Result, bail};
+use auth::ClientAuth;
+use package::{
+    Compression, Digest, Limits, Signature, SignatureError, TrustedKeys, compute_digest,
+    validate_archive,
+};
+use proto::api::registry::{
+    RegistryPullRequest, RegistryPullResponse, RegistryStatRequest,
+    registry_service_client::RegistryServiceClient,
+};
+use tempfile::NamedTempFile;
+use tokio::{
+    fs::{
+        File, create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, rename, write,
+    },
+    io::{AsyncWriteExt, BufReader},
+};
+use tokio_stream::{Stream, StreamExt};
 use tokio_tar::Archive;
-use tonic::Request;
-use tracing::{debug, info, instrument, warn};
+use tonic::{
+    Request, Status,
+    service::interceptor::InterceptedService,
+    transport::{Channel, Endpoint},
+};
+use tracing::{debug, error, info, instrument, warn};
+use uuid::Uuid;
 
-use crate::path::get_dir_path;
+use crate::path::{
+    MARKER_SUFFIX, get_dir_path, get_marker_path, get_quarantine_dir, get_signature_path,
+};
+
+/// Packages used more recently than this are never evicted, a function may be
+/// starting from them
+const MIN_IDLE: Duration = Duration::from_secs(60);
+/// Downloads and extractions untouched for this long were left behind by a worker
+/// that stopped halfway
+const STALE_TEMP: Duration = Duration::from_secs(60 * 60);
+/// A refused package has its signatures fetched again at most this often, it may
+/// have been signed since it was pulled
+const SIGNATURE_REFRESH: Duration = Duration::from_secs(10);
+/// Prefixes of the temporary files and directories kept next to the packages
+const TEMP_PREFIXES: [&str; 3] = [".download-", ".extract-", ".evict-"];
 
 #[derive(Clone)]
 pub struct RegistryClient {
     pub addr: String,
+    endpoint: Endpoint,
+    pkgs_dir: PathBuf,
+    auth: ClientAuth,
+    limits: Limits,
+    /// Keys a package must be signed with before it runs, `None` runs any package
+    trusted_keys: Option<TrustedKeys>,
 }
 
 impl RegistryClient {
-    pub fn new(addr: String) -> Self {
-        debug!(addr = %addr, "Creating RegistryClient");
-        Self { addr }
+    pub fn new(
+        endpoint: Endpoint,
+        pkgs_dir: PathBuf,
+        auth: ClientAuth,
+        limits: Limits,
+        trusted_keys: Option<TrustedKeys>,
+    ) -> Self {
+        let addr = endpoint.uri().to_string();
+        debug!(addr = %addr, pkgs_dir = ?pkgs_dir, "Creating RegistryClient");
+        Self {
+            addr,
+            endpoint,
+            pkgs_dir,
+            auth,
+            limits,
+            trusted_keys,
+        }
     }
 }
 
 impl RegistryClient {
     #[instrument(skip(self), fields(addr = %self.addr))]
     pub async fn get_tar_by_digest(&self, digest: &str) -> Result<PathBuf> {
-        let dir_path = get_dir_path(digest);
+        let expected: Digest = digest.parse()?;
+        let dir_path = get_dir_path(&self.pkgs_dir, digest);
 
         if dir_path.exists() {
-            debug!(digest = %digest, path = ?dir_path, "Using cached archive");
-            return Ok(dir_path);
+            if self.is_verified(digest).await {
+                debug!(digest = %digest, path = ?dir_path, "Using cached archive");
+                // The marker's modification time is when the package was last used.
+                write(get_marker_path(&self.pkgs_dir, digest), digest).await?;
+                return Ok(dir_path);
+            }
+            // Left behind by an interrupted extraction or an older worker.
+            warn!(digest = %digest, path = ?dir_path, "Cached archive was never verified");
+            self.quarantine(&dir_path, digest).await?;
         }
 
         info!(digest = %digest, "Fetching archive from registry");
-        let data = self.fetch_digest(digest).await?;
+        let (archive, compression, signatures) = self.fetch_digest(digest).await?;
+
+        debug!(digest = %digest, compression = %compression, "Archive downloaded, verifying");
+        // Bounded, so an archive that unpacks to far more than it may is not read to
+        // its end before the limits below turn it down.
+        let file = File::open(archive.path()).await?;
+        let actual = compute_digest(
+            Archive::new(
+                self.limits
+                    .limit_reader(compression.decoder(BufReader::new(file))),
+            ),
+            expected.version(),
+        )
+        .await
+        .context("failed to read downloaded archive")?;
+        if actual != expected {
+            error!(digest = %digest, actual = %actual, "Downloaded archive does not match its digest");
+            let archive = archive.into_temp_path().keep()?;
+            self.quarantine(&archive, digest).await?;
+            bail!(
+                "archive for digest {} has digest {}, refusing to run it",
+                digest,
+                actual
+            );
+        }
 
-        debug!(digest = %digest, size_bytes = data.len(), "Archive downloaded, extracting");
-        self.extract_archive(&data, &dir_path).await?;
+        // The registry checks uploads too, this guards against one that does not.
+        // The ratio limit is checked against the compressed size.
+        let size = archive.as_file().metadata()?.len();
+        let file = File::open(archive.path()).await?;
+        if let Err(e) = validate_archive(
+            Archive::new(compression.decoder(BufReader::new(file))),
+            size,
+            &self.limits,
+        )
+        .await
+        {
+            error!(digest = %digest, error = %e, "Refusing to extract unsafe archive");
+            let archive = archive.into_temp_path().keep()?;
+            self.quarantine(&archive, digest).await?;
+            bail!("archive for digest {} is unsafe: {}", digest, e);
+        }
+
+        debug!(digest = %digest, "Archive verified, extracting");
+        self.extract_archive(archive.path(), compression, &dir_path)
+            .await?;
+        write(
+            get_signature_path(&self.pkgs_dir, digest),
+            signatures.join("\n"),
+        )
+        .await
+        .context("failed to store package signatures")?;
+        write(get_marker_path(&self.pkgs_dir, digest), digest)
+            .await
+            .context("failed to mark archive as verified")?;
 
         info!(digest = %digest, path = ?dir_path, "Archive extracted successfully");
         Ok(dir_path)
     }
 
-    #[instrument(skip(self), fields(addr = %self.addr))]
-    async fn fetch_digest(&self, digest: &str) -> Result<Vec<u8>> {
-        let mut client = RegistryServiceClient::connect(self.addr.clone())
+    async fn is_verified(&self, digest: &str) -> bool {
+        // Packages cached before signatures were kept are fetched again.
+        get_signature_path(&self.pkgs_dir, digest).exists()
+            && read_to_string(get_marker_path(&self.pkgs_dir, digest))
+                .await
+                .is_ok_and(|marker| marker == digest)
+    }
+
+    /// Check that `digest` was signed by one of the trusted keys. The error is a
+    /// [`package::SignatureError`] when the package itself is refused.
+    #[instrument(skip(self))]
+    pub async fn verify_signature(&self, digest: &str) -> Result<()> {
+        let Some(trusted_keys) = &self.trusted_keys else {
+            return Ok(());
+        };
+
+        let result = match self.check_signatures(trusted_keys, digest).await {
+            Err(e) if is_unsigned(&e) && self.signatures_stale(digest).await => {
+                debug!(digest = %digest, "Package refused, fetching its signatures again");
+                match self.refresh_signatures(digest).await {
+                    std::result::Result::Ok(()) => {
+                        self.check_signatures(trusted_keys, digest).await
+                    }
+                    Err(refresh_error) => {
+                        warn!(digest = %digest, error = %refresh_error, "Failed to refresh signatures");
+                        Err(e)
+                    }
+                }
+            }
+            result => result,
+        };
+        if let Err(e) = &result
+            && e.is::<SignatureError>()
+        {
+            warn!(digest = %digest, error = %e, "Refusing package");
+        }
+        result
+    }
+
+    async fn check_signatures(&self, trusted_keys: &TrustedKeys, digest: &str) -> Result<()> {
+        let content = match read_to_string(get_signature_path(&self.pkgs_dir, digest)).await {
+            std::result::Result::Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).context("failed to read package signatures"),
+        };
+        let signatures = content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::parse)
+            .collect::<std::result::Result<Vec<Signature>, _>>()?;
+
+        let key = trusted_keys.verify(&digest.parse()?, &signatures)?;
+        debug!(digest = %digest, key = %key, "Package signature verified");
+        Ok(())
+    }
+
+    /// Whether the stored signatures of `digest` were fetched long enough ago to
+    /// ask the registry again
+    async fn signatures_stale(&self, digest: &str) -> bool {
+        tokio::fs::metadata(get_signature_path(&self.pkgs_dir, digest))
             .await
-            .map_err(|e| {
-                warn!(error = %e, "Failed to connect to registry");
-                e
-            })?;
+            .and_then(|metadata| metadata.modified())
+            .map_or(true, |fetched| {
+                fetched.elapsed().unwrap_or_default() >= SIGNATURE_REFRESH
+            })
+    }
+
+    async fn refresh_signatures(&self, digest: &str) -> Result<()> {
+        let signatures = self
+            .client()
+            .await?
+            .stat(Request::new(RegistryStatRequest {
+                digest: digest.to_string(),
+            }))
+            .await?
+            .into_inner()
+            .signatures;
+        write(
+            get_signature_path(&self.pkgs_dir, digest),
+            signatures.join("\n"),
+        )
+        .await
+        .context("failed to store package signatures")
+    }
+
+    /// Evict the least recently used packages until the cache takes at most
+    /// `budget` bytes and return how many were evicted. Quarantined packages count
+    /// towards the budget and go first, leftovers of interrupted downloads and
+    /// extractions are always removed.
+    #[instrument(skip(self))]
+    pub async fn evict_packages(&self, budget: u64) -> Result<usize> {
+        let mut packages = Vec::new();
+        let mut total = 0;
+        let now = SystemTime::now();
+
+        let mut entries = read_dir(&self.pkgs_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if TEMP_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+                let modified = entry.metadata().await?.modified()?;
+                if now.duration_since(modified).unwrap_or_default() >= STALE_TEMP {
+                    debug!(path = ?entry.path(), "Removing leftover temporary file");
+                    remove_path(&entry.path()).await?;
+                }
+                continue;
+            }
+            let Some(digest) = name.strip_suffix(MARKER_SUFFIX) else {
+                continue;
+            };
+            let dir_path = get_dir_path(&self.pkgs_dir, digest);
+            if !dir_path.exists() {
+                continue;
+            }
+
+            let last_used = entry.metadata().await?.modified()?;
+            let size = dir_size(&dir_path).await?;
+            total += size;
+            packages.push((last_used, digest.to_string(), size));
+        }
 
-        let mut response = client
+        let mut quarantined = Vec::new();
+        let quarantine_dir = get_quarantine_dir(&self.pkgs_dir);
+        if quarantine_dir.exists() {
+            let mut entries = read_dir(&quarantine_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let modified = entry.metadata().await?.modified()?;
+                let size = path_size(&entry.path()).await?;
+                total += size;
+                quarantined.push((modified, entry.path(), size));
+            }
+        }
+
+        if total <= budget {
+            debug!(total, budget, "Package cache within budget");
+            return Ok(0);
+        }
+
+        let mut evicted = 0;
+        quarantined.sort();
+        for (_, path, size) in quarantined {
+            if total <= budget {
+                break;
+            }
+            remove_path(&path).await?;
+            debug!(path = ?path, "Removed quarantined package");
+            total -= size;
+            evicted += 1;
+        }
+
+        packages.sort();
+        for (last_used, digest, size) in packages {
+            if total <= budget {
+                break;
+            }
+            if now.duration_since(last_used).unwrap_or_default() < MIN_IDLE {
+                continue;
+            }
+
+            self.evict(&digest).await?;
+            total -= size;
+            evicted += 1;
+        }
+
+        info!(total, budget, evicted, "Evicted packages from cache");
+        Ok(evicted)
+    }
+
+    async fn evict(&self, digest: &str) -> Result<()> {
+        // Moved aside first, so the package disappears at once and a half deleted
+        // directory is never used.
+        let dir_path = get_dir_path(&self.pkgs_dir, digest);
+        let doomed = self.pkgs_dir.join(format!(".evict-{}", Uuid::new_v4()));
+        rename(&dir_path, &doomed).await?;
+        let _ = remove_file(get_marker_path(&self.pkgs_dir, digest)).await;
+        let _ = remove_file(get_signature_path(&self.pkgs_dir, digest)).await;
+        remove_dir_all(&doomed).await?;
+
+        debug!(digest = %digest, "Evicted package");
+        Ok(())
+    }
+
+    /// Move `path` out of the package cache so it is never run, but kept for
+    /// inspection
+    #[instrument(skip(self))]
+    async fn quarantine(&self, path: &Path, digest: &str) -> Result<()> {
+        let quarantine_dir = get_quarantine_dir(&self.pkgs_dir);
+        create_dir_all(&quarantine_dir)
+            .await
+            .context("failed to create quarantine directory")?;
+
+        let target = quarantine_dir.join(format!("{}-{}", digest, Uuid::new_v4()));
+        rename(path, &target)
+            .await
+            .with_context(|| format!("failed to quarantine {}", path.display()))?;
+
+        warn!(path = ?target, "Quarantined package");
+        Ok(())
+    }
+
+    /// Download `digest` into a temporary file next to the package cache, which is
+    /// removed once dropped, along with how the registry compressed it and the
+    /// signatures it holds for it
+    #[instrument(skip(self), fields(addr = %self.addr))]
+    async fn fetch_digest(
+        &self,
+        digest: &str,
+    ) -> Result<(NamedTempFile, Compression, Vec<String>)> {
+        let response = self
+            .client()
+            .await?
             .pull(Request::new(RegistryPullRequest {
                 digest: digest.to_string(),
+                accept: Compression::ALL.into_iter().map(i32::from).collect(),
             }))
             .await
             .map_err(|e| {
@@ -62,30 +378,337 @@ impl RegistryClient {
             })?
             .into_inner();
 
-        let mut data = Vec::new();
-        while let Some(message) = response.message().await? {
-            data.extend_from_slice(&message.data);
+        self.download(digest, response).await
+    }
+
+    /// Write the pulled `messages` into a temporary file, giving up as soon as they
+    /// exceed the largest archive the limits allow so a bad registry cannot fill
+    /// the disk
+    async fn download(
+        &self,
+        digest: &str,
+        mut messages: impl Stream<Item = std::result::Result<RegistryPullResponse, Status>> + Unpin,
+    ) -> Result<(NamedTempFile, Compression, Vec<String>)> {
+        let temp = tempfile::Builder::new()
+            .prefix(".download-")
+            .suffix(".tar")
+            .tempfile_in(&self.pkgs_dir)
+            .context("failed to create download file")?;
+        let mut file = File::from_std(temp.reopen()?);
+
+        let max_size = self.limits.max_compressed_size();
+        let mut total_bytes = 0;
+        let mut compression = Compression::None;
+        let mut signatures = Vec::new();
+        let mut first = true;
+        while let Some(message) = messages.next().await {
+            let message = message?;
+            if first {
+                compression =
+                    Compression::try_from(message.compression).map_err(anyhow::Error::msg)?;
+                signatures = message.signatures;
+                first = false;
+            }
+            total_bytes += message.data.len() as u64;
+            if total_bytes > max_size {
+                error!(digest = %digest, total_bytes, "Registry sent an oversized archive");
+                bail!(
+                    "archive for digest {} is larger than {} bytes",
+                    digest,
+                    max_size
+                );
+            }
+            file.write_all(&message.data).await?;
         }
+        file.flush().await?;
 
-        debug!(digest = %digest, total_bytes = data.len(), "Download complete");
-        Ok(data)
+        debug!(digest = %digest, total_bytes, compression = %compression, "Download complete");
+        Ok((temp, compression, signatures))
     }
 
-    #[instrument(skip(self, data))]
-    async fn extract_archive(&self, data: &[u8], dir_path: &Path) -> Result<()> {
-        create_dir(dir_path).await.map_err(|e| {
-            warn!(path = ?dir_path, error = %e, "Failed to create directory");
+    async fn client(
+        &self,
+    ) -> Result<RegistryServiceClient<InterceptedService<Channel, ClientAuth>>> {
+        let channel = self.endpoint.connect().await.map_err(|e| {
+            warn!(error = %e, "Failed to connect to registry");
             e
         })?;
+        Ok(RegistryServiceClient::with_interceptor(
+            channel,
+            self.auth.clone(),
+        ))
+    }
 
-        let cursor = Cursor::new(&data);
-        let mut archive = Archive::new(cursor);
+    /// Unpack `archive` into a temporary directory and rename it to `dir_path`, so a
+    /// half extracted package is never visible under its digest. It is decompressed
+    /// while being unpacked.
+    #[instrument(skip(self))]
+    async fn extract_archive(
+        &self,
+        archive: &Path,
+        compression: Compression,
+        dir_path: &Path,
+    ) -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix(".extract-")
+            .tempdir_in(&self.pkgs_dir)
+            .context("failed to create extraction directory")?;
 
-        archive.unpack(&dir_path).await.map_err(|e| {
+        let file = File::open(archive).await?;
+        let mut archive = Archive::new(compression.decoder(BufReader::new(file)));
+        archive.unpack(temp_dir.path()).await.map_err(|e| {
             warn!(path = ?dir_path, error = %e, "Failed to extract archive");
             e
         })?;
 
+        let extracted = temp_dir.keep();
+        if let Err(e) = rename(&extracted, dir_path).await {
+            // Another request may have extracted the same digest in the meantime.
+            let _ = remove_dir_all(&extracted).await;
+            if !dir_path.exists() {
+                warn!(path = ?dir_path, error = %e, "Failed to move extracted archive");
+                return Err(e.into());
+            }
+        }
+
         Ok(())
     }
 }
+
+/// Whether `e` refuses a package for lacking a trusted signature, which signing it
+/// later fixes
+fn is_unsigned(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<SignatureError>(),
+        Some(SignatureError::Unsigned | SignatureError::Untrusted)
+    )
+}
+
+/// Size of the file or directory tree at `path`
+async fn path_size(path: &Path) -> Result<u64> {
+    let metadata = tokio::fs::symlink_metadata(path).await?;
+    match metadata.is_dir() {
+        true => dir_size(path).await,
+        false => Ok(metadata.len()),
+    }
+}
+
+async fn remove_path(path: &Path) -> Result<()> {
+    match tokio::fs::symlink_metadata(path).await?.is_dir() {
+        true => remove_dir_all(path).await?,
+        false => remove_file(path).await?,
+    }
+    Ok(())
+}
+
+async fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    let mut stack = vec![path.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let mut entries = read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                stack.push(entry.path());
+            } else {
+                size += metadata.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use package::SigningKey;
+    use tempfile::TempDir;
+
+    async fn cache_package(pkgs_dir: &Path, digest: &str, size: usize, age: Duration) {
+        let dir = get_dir_path(pkgs_dir, digest);
+        create_dir_all(&dir).await.unwrap();
+        write(dir.join("bootstrap"), vec![0u8; size]).await.unwrap();
+
+        let marker = get_marker_path(pkgs_dir, digest);
+        write(&marker, digest).await.unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&marker)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    fn client(pkgs_dir: &Path) -> RegistryClient {
+        RegistryClient::new(
+            Endpoint::from_static("http://127.0.0.1:1"),
+            pkgs_dir.to_path_buf(),
+            ClientAuth::default(),
+            Limits::default(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used_first() {
+        let temp = TempDir::new().unwrap();
+        let pkgs = temp.path();
+        cache_package(pkgs, "old", 100, Duration::from_secs(3600)).await;
+        cache_package(pkgs, "older", 100, Duration::from_secs(7200)).await;
+        cache_package(pkgs, "recent", 100, Duration::from_secs(600)).await;
+
+        let evicted = client(pkgs).evict_packages(250).await.unwrap();
+
+        assert_eq!(evicted, 1);
+        assert!(!get_dir_path(pkgs, "older").exists());
+        assert!(!get_marker_path(pkgs, "older").exists());
+        assert!(get_dir_path(pkgs, "old").exists());
+        assert!(get_dir_path(pkgs, "recent").exists());
+    }
+
+    fn set_age(path: &Path, age: Duration) {
+        std::fs::File::open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_evicts_quarantine_first() {
+        let temp = TempDir::new().unwrap();
+        let pkgs = temp.path();
+        cache_package(pkgs, "old", 100, Duration::from_secs(3600)).await;
+        let quarantine_dir = get_quarantine_dir(pkgs);
+        create_dir_all(&quarantine_dir).await.unwrap();
+        write(quarantine_dir.join("bad-1"), vec![0u8; 100])
+            .await
+            .unwrap();
+
+        let evicted = client(pkgs).evict_packages(150).await.unwrap();
+
+        assert_eq!(evicted, 1);
+        assert!(!quarantine_dir.join("bad-1").exists());
+        assert!(get_dir_path(pkgs, "old").exists());
+    }
+
+    #[tokio::test]
+    async fn test_removes_stale_temporary_files() {
+        let temp = TempDir::new().unwrap();
+        let pkgs = temp.path();
+        let stale_download = pkgs.join(".download-stale.tar");
+        let stale_extract = pkgs.join(".extract-stale");
+        let fresh_download = pkgs.join(".download-fresh.tar");
+        write(&stale_download, "x").await.unwrap();
+        create_dir_all(stale_extract.join("lib")).await.unwrap();
+        write(&fresh_download, "x").await.unwrap();
+        set_age(&stale_download, STALE_TEMP);
+        set_age(&stale_extract, STALE_TEMP);
+
+        client(pkgs).evict_packages(u64::MAX).await.unwrap();
+
+        assert!(!stale_download.exists());
+        assert!(!stale_extract.exists());
+        assert!(fresh_download.exists());
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature() {
+        let temp = TempDir::new().unwrap();
+        let pkgs = temp.path();
+        let digest = format!("v2:{}", "a".repeat(64));
+        let key = SigningKey::generate();
+        let mut client = client(pkgs);
+
+        // Nothing is checked without trusted keys.
+        client.verify_signature(&digest).await.unwrap();
+
+        client.trusted_keys = Some(TrustedKeys::new(vec![key.public_key()]));
+        let refused =
+            |result: Result<()>| result.unwrap_err().downcast::<SignatureError>().unwrap();
+        assert_eq!(
+            refused(client.verify_signature(&digest).await),
+            SignatureError::Unsigned
+        );
+
+        let signature_path = get_signature_path(pkgs, &digest);
+        let other = SigningKey::generate().sign(&digest.parse().unwrap());
+        write(&signature_path, other.to_string()).await.unwrap();
+        assert_eq!(
+            refused(client.verify_signature(&digest).await),
+            SignatureError::Untrusted
+        );
+
+        let signature = key.sign(&digest.parse().unwrap());
+        write(&signature_path, format!("{}\n{}", other, signature))
+            .await
+            .unwrap();
+        client.verify_signature(&digest).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_signature_refresh() {
+        let temp = TempDir::new().unwrap();
+        let pkgs = temp.path();
+        let digest = format!("v2:{}", "a".repeat(64));
+        let mut client = client(pkgs);
+        client.trusted_keys = Some(TrustedKeys::new(vec![SigningKey::generate().public_key()]));
+
+        let signature_path = get_signature_path(pkgs, &digest);
+        write(&signature_path, "").await.unwrap();
+        assert!(!client.signatures_stale(&digest).await);
+
+        set_age(&signature_path, SIGNATURE_REFRESH);
+        assert!(client.signatures_stale(&digest).await);
+
+        // The registry is unreachable, so the package stays refused for the same reason.
+        let err = client.verify_signature(&digest).await.unwrap_err();
+        assert_eq!(
+            err.downcast::<SignatureError>().unwrap(),
+            SignatureError::Unsigned
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keeps_packages_in_use() {
+        let temp = TempDir::new().unwrap();
+        let pkgs = temp.path();
+        cache_package(pkgs, "busy", 100, Duration::ZERO).await;
+
+        let evicted = client(pkgs).evict_packages(0).await.unwrap();
+
+        assert_eq!(evicted, 0);
+        assert!(get_dir_path(pkgs, "busy").exists());
+    }
+
+    fn pull_response(data: Vec<u8>) -> std::result::Result<RegistryPullResponse, Status> {
+        std::result::Result::Ok(RegistryPullResponse {
+            data,
+            compression: Compression::None.into(),
+            signatures: Vec::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_download_stops_at_size_limit() {
+        let temp = TempDir::new().unwrap();
+        let pkgs = temp.path();
+        let mut client = client(pkgs);
+        client.limits = Limits {
+            max_total_size: 1024,
+            max_entries: 1,
+            max_ratio: 100,
+        };
+        let max_size = client.limits.max_compressed_size() as usize;
+
+        let within = tokio_stream::iter(vec![pull_response(vec![0u8; max_size])]);
+        let (archive, _, _) = client.download("small", within).await.unwrap();
+        assert_eq!(archive.as_file().metadata().unwrap().len(), max_size as u64);
+        drop(archive);
+
+        // The registry keeps sending, the download ends on the first message over.
+        let endless = tokio_stream::iter(std::iter::repeat_with(|| pull_response(vec![0u8; 4096])));
+        let err = client.download("bomb", endless).await.unwrap_err();
+        assert!(err.to_string().contains("larger than"), "{}", err);
+        assert_eq!(std::fs::read_dir(pkgs).unwrap().count(), 0);
+    }
+}
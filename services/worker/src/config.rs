@@ -1,64 +1,362 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use package::{Limits, TrustedKeys};
+use serde::Deserialize;
+use tls::TlsConfig;
 
 use crate::background::BackgroundConfig;
 
+const DEFAULT_ADDR: &str = "[::1]:50003";
+const DEFAULT_CONTROLPLANE_URL: &str = "http://localhost:50002";
+const DEFAULT_REGISTRY_URL: &str = "http://localhost:50001";
+const DEFAULT_DATA_DIR: &str = "/var/lib/noctiforge/native_worker";
+const DEFAULT_BACKGROUND_TIME_SECS: u64 = 10;
+const DEFAULT_RESOURCE_TTL_SECS: u64 = 30;
+const DEFAULT_DIGEST_CACHE_TTL_SECS: u64 = 30;
+const DEFAULT_PACKAGE_CACHE_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
 #[derive(Debug, PartialEq)]
 pub enum Environment {
     Development,
     Production,
 }
 
+/// Command line arguments. Every flag can also be set through the environment
+/// and takes precedence over the config file.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Path to a TOML config file
+    #[arg(short, long, env = "WORKER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Address the gRPC server listens on
+    #[arg(long, env = "WORKER_ADDR")]
+    addr: Option<SocketAddr>,
+
+    /// URL of the control plane
+    #[arg(long, env = "CONTROLPLANE_CLINET")]
+    controlplane_url: Option<String>,
+
+    /// URL of the registry
+    #[arg(long, env = "REGISTRY_CLINET")]
+    registry_url: Option<String>,
+
+    /// Directory the downloaded packages are cached in
+    #[arg(long, env = "WORKER_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Directory holding container state, defaults to `$XDG_RUNTIME_DIR/noctiforge`
+    #[arg(long, env = "WORKER_RUNTIME_DIR")]
+    runtime_dir: Option<PathBuf>,
+
+    /// Seconds between background cleanup runs
+    #[arg(long, env = "BACGROUND_TIME")]
+    background_time: Option<u64>,
+
+    /// Seconds an idle function is kept alive
+    #[arg(long, env = "BACKGROUND_RESOURCE_TTL")]
+    resource_ttl: Option<u64>,
+
+    /// Seconds a resolved name → digest mapping is cached
+    #[arg(long, env = "DIGEST_CACHE_TTL")]
+    digest_cache_ttl: Option<u64>,
+
+    /// Bytes the extracted packages may take before the least recently used are
+    /// evicted, 0 for no limit
+    #[arg(long, env = "PACKAGE_CACHE_SIZE")]
+    package_cache_size: Option<u64>,
+
+    /// Largest unpacked size in bytes a package may have
+    #[arg(long, env = "MAX_PACKAGE_SIZE")]
+    max_package_size: Option<u64>,
+
+    /// File with the public keys packages must be signed with, one per line.
+    /// Signatures are not checked without it.
+    #[arg(long, env = "TRUSTED_KEYS")]
+    trusted_keys: Option<PathBuf>,
+
+    /// Require an API key on every request
+    #[arg(long, env = "AUTH_ENABLED")]
+    auth: bool,
+
+    /// API key the worker uses towards the control plane and registry
+    #[arg(long, env = "WORKER_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// PEM certificate presented to peers, enables TLS on the server
+    #[arg(long, env = "TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key belonging to `--tls-cert`
+    #[arg(long, env = "TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM CA certificate used to verify servers and, with `--tls-client-auth`, clients
+    #[arg(long, env = "TLS_CA")]
+    tls_ca: Option<PathBuf>,
+
+    /// Require clients to present a certificate signed by `--tls-ca`
+    #[arg(long, env = "TLS_CLIENT_AUTH")]
+    tls_client_auth: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    addr: Option<SocketAddr>,
+    controlplane_url: Option<String>,
+    registry_url: Option<String>,
+    data_dir: Option<PathBuf>,
+    runtime_dir: Option<PathBuf>,
+    background_time: Option<u64>,
+    resource_ttl: Option<u64>,
+    digest_cache_ttl: Option<u64>,
+    package_cache_size: Option<u64>,
+    max_package_size: Option<u64>,
+    trusted_keys: Option<PathBuf>,
+    auth: Option<bool>,
+    api_key: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tls_ca: Option<PathBuf>,
+    tls_client_auth: Option<bool>,
+}
+
 pub struct ServerConfig {
     pub addr: SocketAddr,
     pub controlplane_clinet: String,
     pub registry_clinet: String,
+    pub data_dir: PathBuf,
+    pub runtime_dir: Option<PathBuf>,
     pub env: Environment,
     pub background_config: BackgroundConfig,
+    pub digest_cache_ttl: Duration,
+    pub limits: Limits,
+    pub trusted_keys: Option<TrustedKeys>,
+    pub auth: bool,
+    pub api_key: Option<String>,
+    pub tls: TlsConfig,
 }
 
 impl ServerConfig {
-    pub fn from_env() -> Self {
-        let addr = std::env::var("SERVER_ADDR")
-            .unwrap_or_else(|_| "[::1]:50003".to_string())
-            .parse()
-            .expect("Invalid server address");
+    pub fn load() -> Result<Self> {
+        Self::from_args(Args::parse())
+    }
+
+    fn from_args(args: Args) -> Result<Self> {
+        let file = match &args.config {
+            Some(path) => read_config_file(path)?,
+            None => FileConfig::default(),
+        };
+
+        let addr = match args.addr.or(file.addr) {
+            Some(addr) => addr,
+            None => DEFAULT_ADDR.parse()?,
+        };
 
         let env = match cfg!(debug_assertions) {
             true => Environment::Development,
             false => Environment::Production,
         };
 
-        let controlplane_clinet = std::env::var("CONTROLPLANE_CLINET")
-            .unwrap_or_else(|_| "http://localhost:50002".to_string())
-            .parse()
-            .expect("Invalid controlplane address");
-
-        let registry_clinet = std::env::var("REGISTRY_CLINET")
-            .unwrap_or_else(|_| "http://localhost:50001".to_string())
-            .parse()
-            .expect("Invalid registry address");
-
-        let time = std::env::var("BACGROUND_TIME")
-            .map_err(|_| "Missing BACGROUND_TIME")
-            .and_then(|s| s.parse::<u64>().map_err(|_| "Invalid BACGROUND_TIME"))
-            .map(Duration::from_secs)
-            .unwrap_or(Duration::from_secs(10));
-
-        let resource_ttl = std::env::var("BACKGROUND_RESOURCE_TTL")
-            .map_err(|_| "Missing BACKGROUND_RESOURCE_TTL")
-            .and_then(|s| {
-                s.parse::<u64>()
-                    .map_err(|_| "Invalid BACKGROUND_RESOURCE_TTL")
-            })
-            .map(Duration::from_secs)
-            .unwrap_or(Duration::from_secs(30));
-
-        Self {
+        let controlplane_clinet = args
+            .controlplane_url
+            .or(file.controlplane_url)
+            .unwrap_or_else(|| DEFAULT_CONTROLPLANE_URL.to_string());
+
+        let registry_clinet = args
+            .registry_url
+            .or(file.registry_url)
+            .unwrap_or_else(|| DEFAULT_REGISTRY_URL.to_string());
+
+        let data_dir = args
+            .data_dir
+            .or(file.data_dir)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
+
+        let time = Duration::from_secs(
+            args.background_time
+                .or(file.background_time)
+                .unwrap_or(DEFAULT_BACKGROUND_TIME_SECS),
+        );
+
+        let resource_ttl = Duration::from_secs(
+            args.resource_ttl
+                .or(file.resource_ttl)
+                .unwrap_or(DEFAULT_RESOURCE_TTL_SECS),
+        );
+
+        let digest_cache_ttl = Duration::from_secs(
+            args.digest_cache_ttl
+                .or(file.digest_cache_ttl)
+                .unwrap_or(DEFAULT_DIGEST_CACHE_TTL_SECS),
+        );
+
+        let package_cache_size = match args
+            .package_cache_size
+            .or(file.package_cache_size)
+            .unwrap_or(DEFAULT_PACKAGE_CACHE_SIZE)
+        {
+            0 => None,
+            size => Some(size),
+        };
+
+        let mut limits = Limits::default();
+        if let Some(max) = args.max_package_size.or(file.max_package_size) {
+            limits.max_total_size = max;
+        }
+
+        let trusted_keys = match args.trusted_keys.or(file.trusted_keys) {
+            Some(path) => Some(read_trusted_keys(&path)?),
+            None => None,
+        };
+
+        let tls = TlsConfig {
+            cert: args.tls_cert.or(file.tls_cert),
+            key: args.tls_key.or(file.tls_key),
+            ca: args.tls_ca.or(file.tls_ca),
+            require_client_cert: args.tls_client_auth || file.tls_client_auth.unwrap_or(false),
+        };
+
+        Ok(Self {
             addr,
             controlplane_clinet,
             registry_clinet,
+            data_dir,
+            runtime_dir: args.runtime_dir.or(file.runtime_dir),
             env,
-            background_config: BackgroundConfig { time, resource_ttl },
-        }
+            background_config: BackgroundConfig {
+                time,
+                resource_ttl,
+                package_cache_size,
+            },
+            digest_cache_ttl,
+            limits,
+            trusted_keys,
+            auth: args.auth || file.auth.unwrap_or(false),
+            api_key: args.api_key.or(file.api_key),
+            tls,
+        })
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<FileConfig> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))
+}
+
+fn read_trusted_keys(path: &Path) -> Result<TrustedKeys> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read trusted keys {}", path.display()))?;
+    let keys = TrustedKeys::parse(&content)
+        .with_context(|| format!("Failed to parse trusted keys {}", path.display()))?;
+    if keys.is_empty() {
+        bail!("{} holds no trusted keys", path.display());
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+    use tempfile::TempDir;
+
+    /// Parses `args` without looking at the process environment, so a
+    /// `WORKER_ADDR` or similar set in the shell cannot change the outcome.
+    fn parse(args: &[&str]) -> Result<ServerConfig> {
+        let matches = Args::command()
+            .mut_args(|arg| arg.env(None))
+            .try_get_matches_from(std::iter::once("worker").chain(args.iter().copied()))?;
+        ServerConfig::from_args(Args::from_arg_matches(&matches)?)
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = parse(&[]).unwrap();
+        assert_eq!(config.addr, DEFAULT_ADDR.parse().unwrap());
+        assert_eq!(config.data_dir, PathBuf::from(DEFAULT_DATA_DIR));
+        assert_eq!(config.runtime_dir, None);
+        assert_eq!(config.background_config.time, Duration::from_secs(10));
+        assert!(!config.auth);
+        assert_eq!(config.api_key, None);
+        assert!(config.trusted_keys.is_none());
+        assert_eq!(
+            config.background_config.package_cache_size,
+            Some(DEFAULT_PACKAGE_CACHE_SIZE)
+        );
+    }
+
+    #[test]
+    fn test_zero_package_cache_size_disables_eviction() {
+        let config = parse(&["--package-cache-size", "0"]).unwrap();
+        assert_eq!(config.background_config.package_cache_size, None);
+    }
+
+    #[test]
+    fn test_file_overrides_defaults() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("worker.toml");
+        std::fs::write(
+            &path,
+            "addr = \"127.0.0.1:6000\"\ndata_dir = \"/tmp/nocti\"\nresource_ttl = 5\n",
+        )
+        .unwrap();
+
+        let config = parse(&["--config", path.to_str().unwrap()]).unwrap();
+        assert_eq!(config.addr, "127.0.0.1:6000".parse().unwrap());
+        assert_eq!(config.data_dir, PathBuf::from("/tmp/nocti"));
+        assert_eq!(
+            config.background_config.resource_ttl,
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn test_args_override_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("worker.toml");
+        std::fs::write(&path, "data_dir = \"/tmp/from-file\"\n").unwrap();
+
+        let config = parse(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--data-dir",
+            "/tmp/from-args",
+        ])
+        .unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/tmp/from-args"));
+    }
+
+    #[test]
+    fn test_trusted_keys() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("trusted_keys");
+        let key = package::SigningKey::generate().public_key();
+        std::fs::write(&path, format!("# release key\n{}\n", key)).unwrap();
+
+        let config = parse(&["--trusted-keys", path.to_str().unwrap()]).unwrap();
+        assert_eq!(config.trusted_keys.unwrap().len(), 1);
+
+        std::fs::write(&path, "# nobody\n").unwrap();
+        assert!(parse(&["--trusted-keys", path.to_str().unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_file_field_is_rejected() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("worker.toml");
+        std::fs::write(&path, "unknown = 1\n").unwrap();
+
+        assert!(parse(&["--config", path.to_str().unwrap()]).is_err());
     }
 }
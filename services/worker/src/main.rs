@@ -7,8 +7,10 @@ mod worker;
 use nix::sys::stat::Mode;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result, bail};
+use auth::{ClientAuth, RemoteAuthorizer, ServerAuth};
 use libcontainer::syscall::syscall::create_syscall;
 use libcontainer::utils::create_dir_all_with_mode;
 use proto::api::worker::worker_service_server::WorkerServiceServer;
@@ -18,10 +20,12 @@ use tracing::info;
 use crate::client::controlplane_client::ControlPlaneClient;
 use crate::client::registry_clint::RegistryClient;
 use crate::config::Environment;
+use crate::path::get_pkgs_dir;
 use crate::server::WorkerServer;
 use crate::worker::function_invocations::FunctionInvocations;
 use crate::worker::organizer::{Config, NativeWorker};
 use tokio::signal;
+use tokio_util::sync::CancellationToken;
 use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
@@ -29,6 +33,9 @@ mod background;
 
 use background::BackgroundJob;
 
+/// How long a successful API key check is trusted before asking the control plane again
+const AUTH_CACHE_TTL: Duration = Duration::from_secs(30);
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::registry()
@@ -45,22 +52,53 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Starting application");
 
-    let syscall = create_syscall();
-    let root_path = determine_rootpath(&*syscall)?;
-
-    let config = config::ServerConfig::from_env();
+    let config = config::ServerConfig::load()?;
     if config.env == config::Environment::Development {
         info!("Starting in Development mode");
     }
 
+    let syscall = create_syscall();
+    let root_path = determine_rootpath(&*syscall, config.runtime_dir.as_deref())?;
+
+    let pkgs_dir = get_pkgs_dir(&config.data_dir);
+    tokio::fs::create_dir_all(&pkgs_dir)
+        .await
+        .with_context(|| format!("failed to create package directory {}", pkgs_dir.display()))?;
+    info!("Caching packages at: {}", pkgs_dir.display());
+
     let function_invocations = Arc::new(FunctionInvocations::new(root_path.to_path_buf()));
 
-    let registry_clinet = RegistryClient::new(config.registry_clinet);
-    let controlplane_client = ControlPlaneClient::new(config.controlplane_clinet);
+    let client_auth = ClientAuth::new(config.api_key.as_deref())
+        .context("API key is not a valid header value")?;
+
+    // Shared by the digest lookups and the authorization checks. It connects on
+    // first use and reconnects on its own.
+    let controlplane_channel = config
+        .tls
+        .endpoint(config.controlplane_clinet.clone())?
+        .connect_lazy();
+
+    let registry_clinet = RegistryClient::new(
+        config.tls.endpoint(config.registry_clinet)?,
+        pkgs_dir,
+        client_auth.clone(),
+        config.limits,
+        config.trusted_keys,
+    );
+    let controlplane_client = ControlPlaneClient::new(
+        config.controlplane_clinet,
+        controlplane_channel.clone(),
+        config.digest_cache_ttl,
+        client_auth,
+    );
+
+    let authorizer = config
+        .auth
+        .then(|| RemoteAuthorizer::new(controlplane_channel, AUTH_CACHE_TTL));
 
     let function_worker = NativeWorker::new(
         &function_invocations,
-        registry_clinet,
+        registry_clinet.clone(),
         root_path,
         &*syscall,
         Config {
@@ -68,15 +106,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
     )?;
 
-    let mut background_server = BackgroundJob::new(config.background_config, &function_invocations);
-    let worker_server = WorkerServer::new(function_worker, controlplane_client);
+    let mut background_server = BackgroundJob::new(
+        config.background_config,
+        &function_invocations,
+        registry_clinet,
+    );
+    let worker_server = WorkerServer::new(function_worker, controlplane_client.clone(), authorizer);
 
     info!("Worker listening on {}", config.addr);
+    info!("API key authentication enabled: {}", config.auth);
     background_server.start().await;
 
+    let watch_cancel = CancellationToken::new();
+    controlplane_client.watch(watch_cancel.clone());
+
+    let mut server = Server::builder();
+    if let Some(tls) = config.tls.server()? {
+        info!(
+            "TLS enabled, client certificates required: {}",
+            config.tls.require_client_cert
+        );
+        server = server.tls_config(tls)?;
+    }
+
     // Graceful shutdown with signal handling
-    let server = Server::builder()
-        .add_service(WorkerServiceServer::new(worker_server))
+    let server = server
+        .add_service(WorkerServiceServer::with_interceptor(
+            worker_server,
+            ServerAuth::new(config.auth),
+        ))
         .serve(config.addr);
 
     tokio::select! {
@@ -90,13 +148,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Server shut down gracefully");
     background_server.stop();
+    watch_cancel.cancel();
     function_invocations.delete_all().await?;
     Ok(())
 }
 
-fn determine_rootpath(syscall: &dyn libcontainer::syscall::Syscall) -> Result<PathBuf> {
+fn determine_rootpath(
+    syscall: &dyn libcontainer::syscall::Syscall,
+    runtime_dir: Option<&Path>,
+) -> Result<PathBuf> {
     let uid = syscall.get_uid().as_raw();
 
+    if let Some(path) = runtime_dir {
+        create_dir_all_with_mode(path, uid, Mode::S_IRWXU)
+            .with_context(|| format!("failed to create runtime directory {}", path.display()))?;
+        return Ok(path.to_path_buf());
+    }
+
     if let Ok(path) = std::env::var("XDG_RUNTIME_DIR") {
         let path = Path::new(&path).join("noctiforge");
         if create_dir_all_with_mode(&path, uid, Mode::S_IRWXU).is_ok() {
@@ -2,8 +2,30 @@ use anyhow::{Ok, Result};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 
-pub fn get_dir_path(digest: &str) -> PathBuf {
-    get_pkgs_dir().join(digest)
+pub const MARKER_SUFFIX: &str = ".verified";
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+pub fn get_dir_path(pkgs_dir: &Path, digest: &str) -> PathBuf {
+    pkgs_dir.join(digest)
+}
+
+/// Written once `digest` has been verified and fully extracted
+pub fn get_marker_path(pkgs_dir: &Path, digest: &str) -> PathBuf {
+    pkgs_dir.join(format!("{}{}", digest, MARKER_SUFFIX))
+}
+
+/// Where packages that failed verification are moved for inspection
+pub fn get_quarantine_dir(pkgs_dir: &Path) -> PathBuf {
+    pkgs_dir.join(".quarantine")
+}
+
+/// Signatures the registry returned for `digest`, one per line
+pub fn get_signature_path(pkgs_dir: &Path, digest: &str) -> PathBuf {
+    pkgs_dir.join(format!("{}{}", digest, SIGNATURE_SUFFIX))
+}
+
+pub fn get_pkgs_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("pkgs")
 }
 
 pub async fn copy_dir_all(src: PathBuf, dst: PathBuf) -> Result<()> {
@@ -25,14 +47,6 @@ pub async fn copy_dir_all(src: PathBuf, dst: PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn get_root_dir_path() -> PathBuf {
-    Path::new("/var/lib/noctiforge/native_worker").to_path_buf()
-}
-
-fn get_pkgs_dir() -> PathBuf {
-    get_root_dir_path().join("pkgs")
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,15 +55,9 @@ mod tests {
 
     // ==================== Path Functions Tests ====================
 
-    #[test]
-    fn test_get_root_dir_path() {
-        let root = get_root_dir_path();
-        assert_eq!(root, PathBuf::from("/var/lib/noctiforge/native_worker"));
-    }
-
     #[test]
     fn test_get_pkgs_dir() {
-        let pkgs_dir = get_pkgs_dir();
+        let pkgs_dir = get_pkgs_dir(Path::new("/var/lib/noctiforge/native_worker"));
         assert_eq!(
             pkgs_dir,
             PathBuf::from("/var/lib/noctiforge/native_worker/pkgs")
@@ -58,20 +66,35 @@ mod tests {
 
     #[test]
     fn test_get_dir_path() {
-        let dir_path = get_dir_path("digest123");
-        assert_eq!(
-            dir_path,
-            PathBuf::from("/var/lib/noctiforge/native_worker/pkgs/digest123")
-        );
+        let dir_path = get_dir_path(Path::new("/tmp/nocti/pkgs"), "digest123");
+        assert_eq!(dir_path, PathBuf::from("/tmp/nocti/pkgs/digest123"));
     }
 
     #[test]
     fn test_get_dir_path_with_hash() {
         let digest = "sha256:abc123def456";
-        let dir_path = get_dir_path(digest);
+        let dir_path = get_dir_path(Path::new("/tmp/nocti/pkgs"), digest);
         assert!(dir_path.to_string_lossy().contains("sha256:abc123def456"));
     }
 
+    #[test]
+    fn test_get_marker_path() {
+        let marker = get_marker_path(Path::new("/tmp/nocti/pkgs"), "v2:abc");
+        assert_eq!(marker, PathBuf::from("/tmp/nocti/pkgs/v2:abc.verified"));
+    }
+
+    #[test]
+    fn test_get_signature_path() {
+        let path = get_signature_path(Path::new("/tmp/nocti/pkgs"), "v2:abc");
+        assert_eq!(path, PathBuf::from("/tmp/nocti/pkgs/v2:abc.sig"));
+    }
+
+    #[test]
+    fn test_get_quarantine_dir() {
+        let dir = get_quarantine_dir(Path::new("/tmp/nocti/pkgs"));
+        assert_eq!(dir, PathBuf::from("/tmp/nocti/pkgs/.quarantine"));
+    }
+
     // ==================== copy_dir_all Tests ====================
 
     #[tokio::test]
@@ -1,6 +1,10 @@
 use std::sync::Arc;
 
-use proto::api::worker::{ExecuteRequest, ExecuteResponse, worker_service_server::WorkerService};
+use auth::{RemoteAuthorizer, Scope};
+use proto::{
+    api::worker::{ExecuteRequest, ExecuteResponse, worker_service_server::WorkerService},
+    namespace,
+};
 use tokio::sync::Mutex;
 use tonic::{Request, Response, Status};
 use tracing::{debug, info, instrument, warn};
@@ -10,14 +14,20 @@ use crate::{client::controlplane_client::ControlPlaneClient, worker::organizer::
 pub struct WorkerServer {
     function_worker: Arc<Mutex<NativeWorker>>,
     controlplane_client: ControlPlaneClient,
+    authorizer: Option<RemoteAuthorizer>,
 }
 
 impl WorkerServer {
-    pub fn new(function_worker: NativeWorker, controlplane_client: ControlPlaneClient) -> Self {
+    pub fn new(
+        function_worker: NativeWorker,
+        controlplane_client: ControlPlaneClient,
+        authorizer: Option<RemoteAuthorizer>,
+    ) -> Self {
         debug!("Creating WorkerServer");
         Self {
             function_worker: Arc::new(Mutex::new(function_worker)),
             controlplane_client,
+            authorizer,
         }
     }
 }
@@ -29,14 +39,27 @@ impl WorkerService for WorkerServer {
         &self,
         request: Request<ExecuteRequest>,
     ) -> Result<Response<ExecuteResponse>, Status> {
-        let req = request.into_inner();
+        let (namespace, name) = {
+            let req = request.get_ref();
+            let (namespace, name) = namespace::resolve(&req.namespace, &req.action)
+                .map_err(Status::invalid_argument)?;
+            (namespace.to_string(), name.to_string())
+        };
+
+        if let Some(authorizer) = &self.authorizer {
+            authorizer
+                .authorize(&request, &namespace, Scope::Invoke, &name)
+                .await?;
+        }
+        let mut req = request.into_inner();
+        req.action = namespace::qualified(&namespace, &name);
 
         info!(action = %req.action, "Executing request");
 
         debug!(action = %req.action, "Fetching digest from control plane");
         let digest = self
             .controlplane_client
-            .get_digest(req.action.clone())
+            .get_digest(&namespace, &name)
             .await
             .map_err(|e| {
                 warn!(action = %req.action, error = %e, "Failed to communicate with control plane");
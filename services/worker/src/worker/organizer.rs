@@ -2,6 +2,7 @@ use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::{Ok, Result};
 use libcontainer::syscall::Syscall;
+use package::{Digest, SignatureError};
 use proto::api::worker::ExecuteResponse;
 use proto::api::worker::ExecuteSuccess;
 use proto::api::worker::execute_response::Outcome;
@@ -67,7 +68,13 @@ impl NativeWorker {
     ) -> Result<ExecuteResponse> {
         debug!("Executing function");
 
-        let uri = self.get_available_handler_uri(digest.clone()).await?;
+        let uri = match self.get_available_handler_uri(digest.clone()).await {
+            std::result::Result::Ok(uri) => uri,
+            Err(e) => match e.downcast_ref::<SignatureError>() {
+                Some(err) => return Ok(refused_package(&digest, err)),
+                None => return Err(e),
+            },
+        };
 
         debug!(uri = %uri, "Connecting to function handler");
         let mut client = FunctionRunnerServiceClient::connect(uri.to_string())
@@ -120,9 +127,8 @@ impl NativeWorker {
     }
 
     async fn get_available_handler_uri(&mut self, digest: String) -> Result<Url> {
-        // TODO: This is a workaround i don't like as there could be a very low way that this
-        // fails.
-        let short_digest = &digest[..16];
+        let short_digest = digest.parse::<Digest>()?.short();
+        let short_digest = short_digest.as_str();
 
         let url = if let Some(invocation) = self.function_invocations.get(short_digest).await {
             info!("Loading existing function");
@@ -133,6 +139,7 @@ impl NativeWorker {
         } else {
             info!("Creating new function");
             let dir_path = self.registry_service.get_tar_by_digest(&digest).await?;
+            self.registry_service.verify_signature(&digest).await?;
 
             let proc = Arc::new(
                 container::ProccesContainer::new(
@@ -189,3 +196,20 @@ impl NativeWorker {
         unreachable!("Loop should have returned")
     }
 }
+
+/// Answer for a package the trusted keys do not vouch for, it is never started
+fn refused_package(digest: &str, err: &SignatureError) -> ExecuteResponse {
+    let kind = match err {
+        SignatureError::Unsigned => "unsigned-package",
+        SignatureError::Untrusted => "untrusted-signature",
+        SignatureError::Invalid | SignatureError::Malformed(_) => "invalid-signature",
+    };
+    ExecuteResponse {
+        outcome: Some(Outcome::Problem(proto::api::worker::ProblemDetails {
+            r#type: format!("urn:noctiforge:problem:{}", kind),
+            detail: format!("refusing to run {}: {}", digest, err),
+            instance: String::new(),
+            extensions: HashMap::new(),
+        })),
+    }
+}